@@ -0,0 +1,270 @@
+//! Assertion files: tuples to write, and checks to make against them with the
+//! result each is expected to have.
+//!
+//! ```json
+//! {
+//!   "tuples": [
+//!     "document:1#viewer@user:anya",
+//!     {"tuple": "document:2#viewer@user:anya", "condition": "in_office", "context": {"office": "10.0.0.1"}}
+//!   ],
+//!   "checks": [
+//!     {"check": "document:1#viewer@user:anya", "allowed": true},
+//!     {"check": "document:2#viewer@user:anya", "context": {"request": {"ip": "192.168.0.1"}}, "allowed": false}
+//!   ]
+//! }
+//! ```
+
+use crate::error::CliError;
+use pgfga_core::text::TupleKey;
+use pgfga_core::{CheckError, Checker, MemoryStore, Schema, Tuple, TupleStore};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Assertions {
+    #[serde(default)]
+    pub tuples: Vec<AssertedTuple>,
+
+    #[serde(default)]
+    pub checks: Vec<Assertion>,
+}
+
+/// A tuple in the text format, e.g. `document:1#viewer@user:anya`, either on
+/// its own or along with a condition.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum AssertedTuple {
+    Key(String),
+    Conditional {
+        tuple: String,
+
+        #[serde(default)]
+        condition: String,
+
+        #[serde(default)]
+        context: Option<Value>,
+    },
+}
+
+/// A check in the same format as a tuple, with the action in place of the
+/// relation.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Assertion {
+    pub check: String,
+
+    #[serde(default)]
+    pub context: Map<String, Value>,
+
+    pub allowed: bool,
+}
+
+/// A check that didn't have the result it was expected to.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub check: String,
+    pub expected: bool,
+
+    /// The result of the check, or the error it failed with.
+    pub actual: Result<bool, String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+impl Assertions {
+    pub fn parse(text: &str) -> Result<Self, CliError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    /// Write the tuples to a new store and make the checks against them.
+    /// Invalid tuples and checks are errors, rather than failures.
+    pub fn run(&self, schema: Schema) -> Result<Report, CliError> {
+        let mut store = MemoryStore::new();
+        for tuple in &self.tuples {
+            let text = tuple.text();
+            let tuple = tuple
+                .to_tuple(&schema)
+                .map_err(|err| CliError::InvalidTuple(text.to_string(), Box::new(err)))?;
+            store.create_tuple(&tuple, None)?;
+        }
+
+        let mut checker = Checker::new(store, schema, Map::new())?;
+        let mut report = Report::default();
+
+        for assertion in &self.checks {
+            let check = TupleKey::parse(&assertion.check)?;
+
+            checker.set_context(assertion.context.clone());
+            let actual = checker
+                .check(
+                    &check.object.namespace,
+                    &check.object.id,
+                    &check.relation,
+                    &check.subject.namespace,
+                    &check.subject.id,
+                    &check.subject.action,
+                )
+                .map_err(|err| err.to_string());
+
+            if actual == Ok(assertion.allowed) {
+                report.passed += 1;
+            } else {
+                report.failures.push(Failure {
+                    check: assertion.check.clone(),
+                    expected: assertion.allowed,
+                    actual,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+impl AssertedTuple {
+    fn text(&self) -> &str {
+        match self {
+            AssertedTuple::Key(tuple) => tuple,
+            AssertedTuple::Conditional { tuple, .. } => tuple,
+        }
+    }
+
+    /// The tuple, if the schema allows writing it. Its subject must be
+    /// allowed by the relation, which covers wildcards too.
+    fn to_tuple(&self, schema: &Schema) -> Result<Tuple, CliError> {
+        let mut tuple = Tuple::from(TupleKey::parse(self.text())?);
+
+        if let AssertedTuple::Conditional {
+            condition, context, ..
+        } = self
+        {
+            tuple.condition = condition.clone();
+            tuple.condition_context = context.clone();
+        }
+
+        schema.validate_tuple(
+            &tuple.resource_namespace,
+            &tuple.relation,
+            &tuple.subject_namespace,
+            &tuple.subject_id,
+            &tuple.subject_action,
+        )?;
+
+        if !tuple.condition.is_empty() && !schema.conditions.contains_key(&tuple.condition) {
+            return Err(CheckError::UnknownCondition(tuple.condition).into());
+        }
+
+        Ok(tuple)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        serde_json::from_value(json!({
+            "namespaces": {
+                "user": {},
+                "group": {
+                    "relations": {"member": [{"namespace": "user"}]}
+                },
+                "document": {
+                    "relations": {
+                        "viewer": [
+                            {"namespace": "user"},
+                            {"namespaceAction": ["group", "member"]},
+                            {"namespaceWildcard": "user"}
+                        ]
+                    },
+                    "permissions": {"view": {"computedUserset": "viewer"}}
+                }
+            },
+            "conditions": {
+                "in_office": {"expression": "request.ip == office"}
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn run_works() {
+        let assertions = Assertions::parse(
+            r#"{
+                "tuples": [
+                    "group:eng#member@user:anya",
+                    "document:1#viewer@group:eng#member",
+                    "document:2#viewer@user:*",
+                    {
+                        "tuple": "document:3#viewer@user:anya",
+                        "condition": "in_office",
+                        "context": {"office": "10.0.0.1"}
+                    }
+                ],
+                "checks": [
+                    {"check": "document:1#view@user:anya", "allowed": true},
+                    {"check": "document:1#view@user:bob", "allowed": false},
+                    {"check": "document:2#view@user:bob", "allowed": true},
+                    {
+                        "check": "document:3#view@user:anya",
+                        "context": {"request": {"ip": "10.0.0.1"}},
+                        "allowed": true
+                    },
+                    {"check": "document:3#view@user:anya", "allowed": true}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let report = assertions.run(schema()).unwrap();
+
+        assert_eq!(report.passed, 4);
+        assert_eq!(
+            report.failures,
+            vec![Failure {
+                check: "document:3#view@user:anya".to_string(),
+                expected: true,
+                actual: Err("error evaluating condition 'in_office': missing context parameter 'request.ip'".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn run_rejects_invalid_tuples() {
+        let run = |tuple: Value| {
+            let assertions: Assertions =
+                serde_json::from_value(json!({ "tuples": [tuple] })).unwrap();
+            assertions.run(schema()).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            run(json!("document:1#editor@user:anya")),
+            "tuple 'document:1#editor@user:anya': 'document' has no relation 'editor'"
+        );
+        assert_eq!(
+            run(json!("document:1#viewer@group:eng")),
+            "tuple 'document:1#viewer@group:eng': 'group:eng' is not an allowed subject of 'document#viewer'"
+        );
+        assert_eq!(
+            run(json!({"tuple": "document:1#viewer@user:anya", "condition": "on_call"})),
+            "tuple 'document:1#viewer@user:anya': 'on_call' does not correspond to any condition in the schema"
+        );
+        assert!(
+            run(json!("document:1#viewer")).starts_with("tuple 'document:1#viewer': invalid tuple")
+        );
+    }
+
+    #[test]
+    fn parse_rejects_unknown_fields() {
+        assert!(
+            Assertions::parse(r#"{"checks": [{"check": "document:1#view@user:anya"}]}"#).is_err()
+        );
+        assert!(Assertions::parse(r#"{"check": []}"#).is_err());
+    }
+}
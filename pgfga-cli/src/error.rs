@@ -0,0 +1,28 @@
+use pgfga_core::schema::{ImportError, ValidationError};
+use pgfga_core::text::ParseError;
+use pgfga_core::CheckError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Import(#[from] ImportError),
+
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    #[error(transparent)]
+    Check(#[from] CheckError),
+
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+
+    #[error("tuple '{0}': {1}")]
+    InvalidTuple(String, Box<CliError>),
+}
@@ -0,0 +1,127 @@
+//! `pgfga`, for checking schemas and their behaviour without Postgres, e.g. to
+//! gate schema changes in CI. Checks are made by `pgfga_core`, exactly as the
+//! extension makes them.
+
+mod assertions;
+mod error;
+mod schema_file;
+
+use assertions::Assertions;
+use clap::{Parser, Subcommand};
+use error::CliError;
+use schema_file::Format;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Validate a schema and print it in pgfga's JSON format.
+    Validate {
+        schema: PathBuf,
+
+        /// The format of the schema, by default the one of its extension.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+
+    /// Validate a schema and run assertion files against it.
+    Test {
+        schema: PathBuf,
+
+        #[arg(required = true)]
+        assertions: Vec<PathBuf>,
+
+        /// The format of the schema, by default the one of its extension.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Command::Validate { schema, format } => validate(&schema, format),
+        Command::Test {
+            schema,
+            assertions,
+            format,
+        } => test(&schema, &assertions, format),
+    }
+}
+
+fn validate(path: &Path, format: Option<Format>) -> ExitCode {
+    let canonical =
+        schema_file::read(path, format).and_then(|schema| schema_file::canonical(&schema));
+
+    match canonical {
+        Ok(canonical) => {
+            println!("{canonical}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => fail(path, err),
+    }
+}
+
+fn test(path: &Path, assertion_paths: &[PathBuf], format: Option<Format>) -> ExitCode {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for assertions_path in assertion_paths {
+        // The schema is read again for each file, since checkers own theirs.
+        let schema = match schema_file::read(path, format) {
+            Ok(schema) => schema,
+            Err(err) => return fail(path, err),
+        };
+
+        let report = std::fs::read_to_string(assertions_path)
+            .map_err(CliError::from)
+            .and_then(|text| Assertions::parse(&text))
+            .and_then(|assertions| assertions.run(schema));
+
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => return fail(assertions_path, err),
+        };
+
+        for failure in &report.failures {
+            let expected = if failure.expected {
+                "allowed"
+            } else {
+                "denied"
+            };
+            let actual = match &failure.actual {
+                Ok(true) => "allowed".to_string(),
+                Ok(false) => "denied".to_string(),
+                Err(err) => format!("error: {err}"),
+            };
+
+            println!(
+                "FAIL {}: {}: expected {expected}, got {actual}",
+                assertions_path.display(),
+                failure.check
+            );
+        }
+
+        passed += report.passed;
+        failed += report.failures.len();
+    }
+
+    println!("{passed} passed, {failed} failed");
+
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn fail(path: &Path, err: CliError) -> ExitCode {
+    eprintln!("error: {}: {err}", path.display());
+    ExitCode::FAILURE
+}
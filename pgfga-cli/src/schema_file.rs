@@ -0,0 +1,140 @@
+//! Reading schemas from files, in pgfga's JSON format or in the formats the
+//! extension imports, and validating them the way `pgfga.create_schema` does.
+
+use crate::error::CliError;
+use clap::ValueEnum;
+use pgfga_core::check::parse_conditions;
+use pgfga_core::{openfga, spicedb, Schema};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// pgfga's JSON format, or an OpenFGA model in its JSON format.
+    Json,
+
+    /// OpenFGA's DSL.
+    Openfga,
+
+    /// SpiceDB's schema language.
+    Spicedb,
+}
+
+impl Format {
+    /// The format of a file with the given extension, `.fga` for OpenFGA and
+    /// `.zed` for SpiceDB, or JSON otherwise.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("fga") => Format::Openfga,
+            Some("zed") => Format::Spicedb,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// Read and validate the schema in the file, in the given format or the one
+/// of its extension.
+pub fn read(path: &Path, format: Option<Format>) -> Result<Schema, CliError> {
+    let text = std::fs::read_to_string(path)?;
+
+    parse(&text, format.unwrap_or_else(|| Format::of(path)))
+}
+
+/// Parse and validate a schema.
+pub fn parse(text: &str, format: Format) -> Result<Schema, CliError> {
+    let schema = match format {
+        Format::Json => {
+            let value: serde_json::Value = serde_json::from_str(text)?;
+
+            // OpenFGA's models are JSON too, but always have type definitions.
+            if value.get("type_definitions").is_some() {
+                openfga::import_json(value)?
+            } else {
+                serde_json::from_value(value)?
+            }
+        }
+        Format::Openfga => openfga::import_dsl(text)?,
+        Format::Spicedb => spicedb::import_schema(text)?,
+    };
+
+    parse_conditions(&schema)?;
+    schema.validate_sources()?;
+
+    Ok(schema)
+}
+
+/// The schema in pgfga's JSON format, with its keys sorted, so that schemas
+/// that are the same are printed the same.
+pub fn canonical(schema: &Schema) -> Result<String, CliError> {
+    let value = serde_json::to_value(schema)?;
+
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DSL: &str = "
+model
+  schema 1.1
+
+type user
+
+type document
+  relations
+    define viewer: [user]
+";
+
+    #[test]
+    fn format_of_works() {
+        assert_eq!(Format::of(Path::new("schema.json")), Format::Json);
+        assert_eq!(Format::of(Path::new("model.fga")), Format::Openfga);
+        assert_eq!(Format::of(Path::new("schema.zed")), Format::Spicedb);
+        assert_eq!(Format::of(Path::new("schema")), Format::Json);
+    }
+
+    #[test]
+    fn formats_parse_to_the_same_schema() {
+        let from_dsl = parse(DSL, Format::Openfga).unwrap();
+        let from_json = parse(&canonical(&from_dsl).unwrap(), Format::Json).unwrap();
+
+        assert_eq!(
+            canonical(&from_json).unwrap(),
+            canonical(&from_dsl).unwrap()
+        );
+        assert!(from_json.is_relation("document", "viewer"));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_schemas() {
+        assert!(matches!(parse("{", Format::Json), Err(CliError::Json(_))));
+        assert!(matches!(
+            parse(
+                "type document\n  relations\n    define viewer: [",
+                Format::Openfga
+            ),
+            Err(CliError::Import(_))
+        ));
+
+        let unknown_source = r#"{
+            "namespaces": {
+                "document": {
+                    "relations": {},
+                    "permissions": {},
+                    "sources": {
+                        "owner": {
+                            "table": "documents",
+                            "resourceId": "id",
+                            "subjectId": "owner_id",
+                            "subjectNamespace": "user"
+                        }
+                    }
+                }
+            }
+        }"#;
+        assert!(matches!(
+            parse(unknown_source, Format::Json),
+            Err(CliError::Validation(_))
+        ));
+    }
+}
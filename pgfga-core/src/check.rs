@@ -0,0 +1,843 @@
+use crate::condition::Expr;
+use crate::error::CheckError;
+use crate::schema::{Rewrite, Schema, WILDCARD};
+use crate::tuple_store::{Tuple, TupleStore};
+use serde_json::{Map, Value};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+
+pub const MAX_DEPTH: i64 = 25;
+
+/// Checks the tuples of a schema in a store.
+pub struct Checker<S> {
+    store: S,
+    schema: Schema,
+    conditions: HashMap<String, Expr>,
+    context: Map<String, Value>,
+    closure_relations: HashSet<(String, String)>,
+    depth_reached: Cell<i64>,
+}
+
+/// Parse the expressions of all the conditions defined by the schema.
+pub fn parse_conditions(schema: &Schema) -> Result<HashMap<String, Expr>, CheckError> {
+    schema
+        .conditions
+        .iter()
+        .map(|(name, condition)| {
+            Expr::parse(&condition.expression)
+                .map(|expr| (name.clone(), expr))
+                .map_err(|err| CheckError::ConditionError(name.clone(), err))
+        })
+        .collect()
+}
+
+impl<S: TupleStore> Checker<S> {
+    /// A checker of the tuples in `store`, which were written with `schema`.
+    /// `context` is the request context conditions are evaluated with.
+    pub fn new(store: S, schema: Schema, context: Map<String, Value>) -> Result<Self, S::Error> {
+        let conditions = parse_conditions(&schema)?;
+
+        let closure_relations = store.read_closure_relations()?;
+
+        Ok(Checker {
+            store,
+            schema,
+            conditions,
+            context,
+            closure_relations,
+            depth_reached: Cell::new(0),
+        })
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Replace the request context of the checks that follow.
+    pub fn set_context(&mut self, context: Map<String, Value>) {
+        self.context = context;
+    }
+
+    pub fn check(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        action: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<bool, S::Error> {
+        self.depth_reached.set(0);
+
+        self.check_with_depth(
+            resource_namespace,
+            resource_id,
+            action,
+            subject_namespace,
+            subject_id,
+            subject_action,
+            0,
+        )
+    }
+
+    /// The deepest the last check recursed, where 0 is the check itself.
+    pub fn depth_reached(&self) -> i64 {
+        self.depth_reached.get()
+    }
+
+    fn check_with_depth(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        action: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        if depth == MAX_DEPTH {
+            return Err(CheckError::MaxDepth.into());
+        }
+
+        self.depth_reached.set(self.depth_reached.get().max(depth));
+
+        if self.schema.is_relation(resource_namespace, action) {
+            // The group closure holds the members found through
+            // unconditional tuples, so it can only say yes. Otherwise fall
+            // back to walking the tuples.
+            if self
+                .closure_relations
+                .contains(&(resource_namespace.to_string(), action.to_string()))
+                && self.store.in_group_closure(
+                    resource_namespace,
+                    resource_id,
+                    action,
+                    subject_namespace,
+                    subject_id,
+                    subject_action,
+                )?
+            {
+                return Ok(true);
+            }
+
+            // If the action is a relation we can attempt a direct check.
+            if let Some(tuple) = self.store.read_tuple(
+                resource_namespace,
+                resource_id,
+                action,
+                subject_namespace,
+                subject_id,
+                subject_action,
+            )? {
+                // We found it.
+                if self.tuple_applies(&tuple)? {
+                    return Ok(true);
+                }
+            }
+
+            // A wildcard tuple grants the relation to every subject in its
+            // namespace.
+            if subject_action.is_empty() {
+                if let Some(tuple) = self.store.read_tuple(
+                    resource_namespace,
+                    resource_id,
+                    action,
+                    subject_namespace,
+                    WILDCARD,
+                    "",
+                )? {
+                    if self.tuple_applies(&tuple)? {
+                        return Ok(true);
+                    }
+                }
+            }
+
+            // The relation may also be read from application data.
+            let source = self.schema.source(resource_namespace, action);
+            if let Some(source) = source {
+                if source.subject_namespace == subject_namespace
+                    && source.subject_action == subject_action
+                    && self.store.in_source(source, resource_id, subject_id)?
+                {
+                    return Ok(true);
+                }
+            }
+
+            // Now let's take a look at subject sets.
+            let mut tuples =
+                self.store
+                    .read_subjectset_tuples(resource_namespace, resource_id, action)?;
+
+            if let Some(source) = source.filter(|source| !source.subject_action.is_empty()) {
+                tuples.extend(self.store.read_source_tuples(
+                    resource_namespace,
+                    resource_id,
+                    action,
+                    source,
+                )?);
+            }
+
+            for tuple in tuples {
+                if !self.tuple_applies(&tuple)? {
+                    continue;
+                }
+
+                let result = self.check_with_depth(
+                    &tuple.subject_namespace,
+                    &tuple.subject_id,
+                    &tuple.subject_action,
+                    subject_namespace,
+                    subject_id,
+                    subject_action,
+                    depth + 1,
+                );
+
+                // We want to ignore errors here.
+                if let Ok(true) = result {
+                    return Ok(true);
+                }
+            }
+        }
+
+        // Now let's take a look at permissions
+        let rw = self
+            .schema
+            .namespaces
+            .get(resource_namespace)
+            .and_then(|ns| ns.permissions.get(action));
+
+        if let Some(rewrite) = rw {
+            return self.check_rewrite(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                rewrite,
+                depth,
+            );
+        }
+
+        Ok(false)
+    }
+
+    /// A tuple applies if it is unconditional, or if its condition holds
+    /// given the request context overlaid with the tuple's own context.
+    fn tuple_applies(&self, tuple: &Tuple) -> Result<bool, CheckError> {
+        if tuple.condition.is_empty() {
+            return Ok(true);
+        }
+
+        let expr = self
+            .conditions
+            .get(&tuple.condition)
+            .ok_or_else(|| CheckError::UnknownCondition(tuple.condition.clone()))?;
+
+        let mut context = self.context.clone();
+        if let Some(Value::Object(tuple_context)) = &tuple.condition_context {
+            context.extend(tuple_context.clone());
+        }
+
+        expr.evaluate(&context)
+            .map_err(|err| CheckError::ConditionError(tuple.condition.clone(), err))
+    }
+
+    fn check_rewrite(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        rewrite: &Rewrite,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        if depth == MAX_DEPTH {
+            return Err(CheckError::MaxDepth.into());
+        }
+
+        match rewrite {
+            Rewrite::ComputedUserset(computed_userset) => self.check_cu(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                computed_userset,
+                depth,
+            ),
+            Rewrite::TupleToUserset(tupleset, computed_userset) => self.check_ttu(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                tupleset,
+                computed_userset,
+                depth,
+            ),
+            Rewrite::Union(rewrites) => self.check_union(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                rewrites,
+                depth,
+            ),
+            Rewrite::Intersection(rewrites) => self.check_intersection(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                rewrites,
+                depth,
+            ),
+            Rewrite::Exclusion(minuend, subtrahend) => self.check_exclusion(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                minuend,
+                subtrahend,
+                depth,
+            ),
+        }
+    }
+
+    fn check_cu(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        computed_userset: &str,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        self.check_with_depth(
+            resource_namespace,
+            resource_id,
+            computed_userset,
+            subject_namespace,
+            subject_id,
+            subject_action,
+            depth,
+        )
+    }
+
+    fn check_ttu(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        tupleset: &str,
+        computed_userset: &str,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        let mut tuples =
+            self.store
+                .read_tuples(resource_namespace, resource_id, tupleset, "", "", "")?;
+
+        if let Some(source) = self.schema.source(resource_namespace, tupleset) {
+            tuples.extend(self.store.read_source_tuples(
+                resource_namespace,
+                resource_id,
+                tupleset,
+                source,
+            )?);
+        }
+
+        for tuple in tuples {
+            if !self.tuple_applies(&tuple)? {
+                continue;
+            }
+
+            let new_resource_namespace = tuple.subject_namespace.as_str();
+            let new_resource_id = tuple.subject_id.as_str();
+            let new_resource_action = tuple.subject_action.as_str();
+
+            // If computed_userset is not actually a relation or permission
+            // in new_resource_namespace then there is nothing to do so
+            // skip it.
+            if !self
+                .schema
+                .is_relation(new_resource_namespace, computed_userset)
+                && !self
+                    .schema
+                    .is_permission(new_resource_namespace, computed_userset)
+            {
+                continue;
+            }
+
+            // If the subject has a nonempty relation (new_resource_action)
+            // which does not match the computed_userset then skip it.
+            if !new_resource_action.is_empty() && new_resource_action != computed_userset {
+                continue;
+            }
+
+            let result = self.check_with_depth(
+                new_resource_namespace,
+                new_resource_id,
+                computed_userset,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                depth + 1,
+            );
+
+            if let Ok(true) = result {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn check_union(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        rewrites: &Vec<Rewrite>,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        for rewrite in rewrites {
+            let result = self.check_rewrite(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                rewrite,
+                depth + 1,
+            );
+
+            if let Ok(true) = result {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn check_intersection(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        rewrites: &Vec<Rewrite>,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        // Errors are passed on rather than ignored, as they are in unions,
+        // since a branch that can't be checked mustn't be taken as allowed.
+        for rewrite in rewrites {
+            let result = self.check_rewrite(
+                resource_namespace,
+                resource_id,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                rewrite,
+                depth + 1,
+            )?;
+
+            if !result {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn check_exclusion(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        minuend: &Rewrite,
+        subtrahend: &Rewrite,
+        depth: i64,
+    ) -> Result<bool, S::Error> {
+        let minuend_result = self.check_rewrite(
+            resource_namespace,
+            resource_id,
+            subject_namespace,
+            subject_id,
+            subject_action,
+            minuend,
+            depth + 1,
+        )?;
+
+        if !minuend_result {
+            return Ok(false);
+        }
+
+        let subtrahend_result = self.check_rewrite(
+            resource_namespace,
+            resource_id,
+            subject_namespace,
+            subject_id,
+            subject_action,
+            subtrahend,
+            depth + 1,
+        )?;
+
+        // A subtrahend that can't be checked, e.g. for a condition missing
+        // from the context, mustn't be taken as the subject not being
+        // excluded.
+        if subtrahend_result {
+            return Ok(false);
+        }
+
+        // minuend_result = true && subtrahend_result = false
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tuple_store::MemoryStore;
+    use serde_json::json;
+    use std::time::{Duration, SystemTime};
+
+    fn schema() -> Schema {
+        serde_json::from_value(json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                "folder": {
+                    "relations": {"viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]},
+                    "permissions": {"can_view": {"computedUserset": "viewer"}}
+                },
+                "document": {
+                    "relations": {
+                        "parent": [{"namespace": "folder"}],
+                        "viewer": [{"namespace": "user"}, {"namespaceWildcard": "user"}],
+                        "editor": [{"namespace": "user"}],
+                        "blocked": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "can_view": {"union": [
+                            {"computedUserset": "viewer"},
+                            {"tupleToUserset": ["parent", "can_view"]}
+                        ]},
+                        "can_read": {"exclusion": [
+                            {"computedUserset": "can_view"},
+                            {"computedUserset": "blocked"}
+                        ]},
+                        "can_edit": {"intersection": [
+                            {"computedUserset": "can_view"},
+                            {"computedUserset": "editor"}
+                        ]}
+                    }
+                }
+            },
+            "conditions": {"in_office": {"expression": "request.ip == office"}}
+        }))
+        .unwrap()
+    }
+
+    /// A tuple in the text format, e.g. `document:1#viewer@user:anya`,
+    /// without escapes.
+    fn tuple(text: &str) -> Tuple {
+        let (resource, subject) = text.split_once('@').unwrap();
+        let (resource, relation) = resource.split_once('#').unwrap();
+        let (resource_namespace, resource_id) = resource.split_once(':').unwrap();
+        let (subject, subject_action) = subject.split_once('#').unwrap_or((subject, ""));
+        let (subject_namespace, subject_id) = subject.split_once(':').unwrap();
+
+        Tuple {
+            resource_namespace: resource_namespace.to_string(),
+            resource_id: resource_id.to_string(),
+            relation: relation.to_string(),
+            subject_namespace: subject_namespace.to_string(),
+            subject_id: subject_id.to_string(),
+            subject_action: subject_action.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn write(
+        store: &mut MemoryStore,
+        text: &str,
+        expires_at: Option<SystemTime>,
+        condition: &str,
+        condition_context: Option<Value>,
+    ) -> i64 {
+        let tuple = Tuple {
+            condition: condition.to_string(),
+            condition_context,
+            ..tuple(text)
+        };
+
+        store.create_tuple(&tuple, expires_at).unwrap()
+    }
+
+    fn checker(tuples: &[&str], context: Value) -> Checker<MemoryStore> {
+        let mut store = MemoryStore::new();
+        for text in tuples {
+            write(&mut store, text, None, "", None);
+        }
+
+        let Value::Object(context) = context else {
+            panic!("context is not an object");
+        };
+
+        Checker::new(store, schema(), context).unwrap()
+    }
+
+    fn check(checker: &Checker<MemoryStore>, text: &str) -> Result<bool, CheckError> {
+        let tuple = tuple(text);
+
+        checker.check(
+            &tuple.resource_namespace,
+            &tuple.resource_id,
+            &tuple.relation,
+            &tuple.subject_namespace,
+            &tuple.subject_id,
+            &tuple.subject_action,
+        )
+    }
+
+    #[test]
+    fn relations_are_followed_through_subject_sets() {
+        let checker = checker(
+            &[
+                "group:eng#member@user:anya",
+                "group:all#member@group:eng#member",
+                "folder:1#viewer@group:all#member",
+                "document:1#viewer@user:bob",
+            ],
+            json!({}),
+        );
+
+        assert!(check(&checker, "document:1#viewer@user:bob").unwrap());
+        assert!(check(&checker, "group:all#member@user:anya").unwrap());
+        assert!(check(&checker, "folder:1#viewer@user:anya").unwrap());
+        assert!(check(&checker, "folder:1#viewer@group:eng#member").unwrap());
+        assert_eq!(checker.depth_reached(), 1);
+        assert!(!check(&checker, "document:1#viewer@user:anya").unwrap());
+        assert!(!check(&checker, "folder:1#viewer@user:bob").unwrap());
+    }
+
+    #[test]
+    fn permissions_are_rewritten() {
+        let checker = checker(
+            &[
+                "folder:1#viewer@user:anya",
+                "folder:1#viewer@user:bob",
+                "document:1#parent@folder:1",
+                "document:1#blocked@user:bob",
+                "document:1#editor@user:anya",
+                "document:1#editor@user:carl",
+            ],
+            json!({}),
+        );
+
+        // tupleToUserset
+        assert!(check(&checker, "document:1#can_view@user:anya").unwrap());
+        assert!(check(&checker, "document:1#can_view@user:bob").unwrap());
+
+        // exclusion
+        assert!(check(&checker, "document:1#can_read@user:anya").unwrap());
+        assert!(!check(&checker, "document:1#can_read@user:bob").unwrap());
+
+        // intersection
+        assert!(check(&checker, "document:1#can_edit@user:anya").unwrap());
+        assert!(!check(&checker, "document:1#can_edit@user:bob").unwrap());
+        assert!(!check(&checker, "document:1#can_edit@user:carl").unwrap());
+    }
+
+    #[test]
+    fn wildcards_grant_every_subject() {
+        let checker = checker(&["document:1#viewer@user:*"], json!({}));
+
+        assert!(check(&checker, "document:1#viewer@user:anya").unwrap());
+        assert!(check(&checker, "document:1#can_read@user:anya").unwrap());
+        assert!(!check(&checker, "document:1#viewer@group:eng#member").unwrap());
+        assert!(!check(&checker, "document:2#viewer@user:anya").unwrap());
+    }
+
+    #[test]
+    fn conditions_are_evaluated() {
+        let mut store = MemoryStore::new();
+        write(
+            &mut store,
+            "document:1#viewer@user:anya",
+            None,
+            "in_office",
+            Some(json!({"office": "10.0.0.1"})),
+        );
+        write(
+            &mut store,
+            "document:2#viewer@user:anya",
+            None,
+            "nope",
+            None,
+        );
+
+        let context = json!({"request": {"ip": "10.0.0.1"}});
+        let Value::Object(context) = context else {
+            unreachable!()
+        };
+        let checker = Checker::new(store, schema(), context).unwrap();
+
+        assert!(check(&checker, "document:1#viewer@user:anya").unwrap());
+        assert!(matches!(
+            check(&checker, "document:2#viewer@user:anya"),
+            Err(CheckError::UnknownCondition(_))
+        ));
+
+        let checker = Checker::new(
+            checker.into_store(),
+            schema(),
+            Map::from_iter([("request".to_string(), json!({"ip": "192.168.0.1"}))]),
+        )
+        .unwrap();
+
+        assert!(!check(&checker, "document:1#viewer@user:anya").unwrap());
+    }
+
+    #[test]
+    fn conditions_that_fail_deny_exclusions_and_intersections() {
+        let mut store = MemoryStore::new();
+        let office = Some(json!({"office": "10.0.0.1"}));
+        write(&mut store, "document:1#viewer@user:anya", None, "", None);
+        write(
+            &mut store,
+            "document:1#blocked@user:anya",
+            None,
+            "in_office",
+            office.clone(),
+        );
+        write(
+            &mut store,
+            "document:1#editor@user:anya",
+            None,
+            "in_office",
+            office,
+        );
+
+        // Without a context the conditions can't be evaluated, which is an
+        // error rather than anya not being blocked.
+        let checker = Checker::new(store, schema(), Map::new()).unwrap();
+        assert!(matches!(
+            check(&checker, "document:1#can_read@user:anya"),
+            Err(CheckError::ConditionError(..))
+        ));
+        assert!(matches!(
+            check(&checker, "document:1#can_edit@user:anya"),
+            Err(CheckError::ConditionError(..))
+        ));
+
+        let checker = Checker::new(
+            checker.into_store(),
+            schema(),
+            Map::from_iter([("request".to_string(), json!({"ip": "10.0.0.1"}))]),
+        )
+        .unwrap();
+        assert!(!check(&checker, "document:1#can_read@user:anya").unwrap());
+        assert!(check(&checker, "document:1#can_edit@user:anya").unwrap());
+    }
+
+    #[test]
+    fn expired_tuples_are_ignored() {
+        let mut store = MemoryStore::new();
+        let past = SystemTime::now() - Duration::from_secs(60);
+        let future = SystemTime::now() + Duration::from_secs(60);
+
+        write(
+            &mut store,
+            "document:1#viewer@user:anya",
+            Some(past),
+            "",
+            None,
+        );
+        write(
+            &mut store,
+            "document:2#viewer@user:anya",
+            Some(future),
+            "",
+            None,
+        );
+
+        // An expired tuple is replaced, and a live one isn't.
+        assert_eq!(
+            write(
+                &mut store,
+                "document:3#viewer@user:anya",
+                Some(past),
+                "",
+                None
+            ),
+            1
+        );
+        assert_eq!(
+            write(&mut store, "document:3#viewer@user:anya", None, "", None),
+            1
+        );
+        assert_eq!(
+            write(&mut store, "document:3#viewer@user:anya", None, "", None),
+            0
+        );
+
+        let checker = Checker::new(store, schema(), Map::new()).unwrap();
+
+        assert!(!check(&checker, "document:1#viewer@user:anya").unwrap());
+        assert!(check(&checker, "document:2#viewer@user:anya").unwrap());
+        assert!(check(&checker, "document:3#viewer@user:anya").unwrap());
+    }
+
+    #[test]
+    fn deleted_tuples_are_gone() {
+        let mut checker = checker(&["document:1#viewer@user:anya"], json!({}));
+
+        assert_eq!(
+            checker
+                .store_mut()
+                .delete_tuple(&tuple("document:1#viewer@user:anya"))
+                .unwrap(),
+            1
+        );
+        assert!(checker.store().is_empty());
+        assert!(!check(&checker, "document:1#viewer@user:anya").unwrap());
+    }
+
+    #[test]
+    fn cycles_stop_at_the_maximum_depth() {
+        let checker = checker(
+            &[
+                "group:a#member@group:b#member",
+                "group:b#member@group:a#member",
+            ],
+            json!({}),
+        );
+
+        assert!(!check(&checker, "group:a#member@user:anya").unwrap());
+        assert_eq!(checker.depth_reached(), MAX_DEPTH - 1);
+    }
+}
@@ -0,0 +1,658 @@
+//! A small expression language for conditional tuples (caveats).
+//!
+//! Expressions are evaluated against a JSON object made from the `context`
+//! given to `check` overlaid with the tuple's `condition_context`. For
+//! example:
+//!
+//! ```text
+//! resource.classification <= user.clearance && ip_in_cidr(request.ip, "10.0.0.0/8")
+//! ```
+//!
+//! Supported are number, string, boolean and `null` literals, lists
+//! (`[1, 2]`), dotted paths into the context, the operators `||`, `&&`, `!`,
+//! `==`, `!=`, `<`, `<=`, `>`, `>=`, `in` and unary `-`, parentheses, and the
+//! functions `ip_in_cidr(ip, cidr)` and `size(value)`. Evaluation never
+//! loops, and expressions can't nest more than `MAX_NESTING` deep, so any
+//! expression is safe to parse and run during a check.
+//!
+//! There is deliberately no clock: a check only depends on its tuples and
+//! context. Conditions on the time, e.g. the time of day, compare it with a
+//! value passed in the context, such as `request.hour >= 9 && request.hour < 17`.
+
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::net::IpAddr;
+use thiserror::Error;
+
+/// The deepest operators, parentheses, lists and calls can nest, so that
+/// parsing and evaluating an expression can't overflow the stack.
+pub const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ConditionError {
+    #[error("syntax error at position {0}: {1}")]
+    Syntax(usize, String),
+
+    #[error("missing context parameter '{0}'")]
+    MissingParameter(String),
+
+    #[error("type error: {0}")]
+    Type(String),
+
+    #[error("unknown function '{0}'")]
+    UnknownFunction(String),
+
+    #[error("expression nested more than {MAX_NESTING} deep at position {0}")]
+    TooDeep(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    List(Vec<Expr>),
+    Path(Vec<String>),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 16] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "!", "-", "(", ")", "[", "]", ",", ".",
+];
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ConditionError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| ConditionError::Syntax(start, format!("invalid number '{text}'")))?;
+            tokens.push((start, Token::Number(number)));
+        } else if c == '"' || c == '\'' {
+            let start = i;
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return Err(ConditionError::Syntax(
+                            start,
+                            "unterminated string".to_string(),
+                        ))
+                    }
+                    Some('\\') => {
+                        let escaped = chars.get(i + 1).ok_or_else(|| {
+                            ConditionError::Syntax(i, "unterminated string".to_string())
+                        })?;
+                        value.push(*escaped);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        value.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push((start, Token::String(value)));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Ident(chars[start..i].iter().collect())));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| ConditionError::Syntax(i, format!("unexpected character '{c}'")))?;
+            tokens.push((i, Token::Op(op)));
+            i += op.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+
+    /// How deep the expression being parsed is nested.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(offset, _)| *offset)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ConditionError> {
+        Err(ConditionError::Syntax(self.offset(), message.to_string()))
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), ConditionError> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{op}'"))
+        }
+    }
+
+    /// Go a level deeper, failing if that is too deep. Each level of the
+    /// expression is a level deeper, as well as each parenthesis.
+    fn nest(&mut self) -> Result<(), ConditionError> {
+        if self.depth == MAX_NESTING {
+            return Err(ConditionError::TooDeep(self.offset()));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, ConditionError> {
+        let depth = self.depth;
+
+        // Chains are nested to the left, so each operator is a level deeper.
+        let mut lhs = self.and()?;
+        while self.eat_op("||") {
+            self.nest()?;
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(self.and()?));
+        }
+
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ConditionError> {
+        let depth = self.depth;
+
+        let mut lhs = self.comparison()?;
+        while self.eat_op("&&") {
+            self.nest()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(self.comparison()?));
+        }
+
+        self.depth = depth;
+        Ok(lhs)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ConditionError> {
+        let lhs = self.unary()?;
+
+        let op = match self.peek() {
+            Some(Token::Op("==")) => BinaryOp::Eq,
+            Some(Token::Op("!=")) => BinaryOp::Ne,
+            Some(Token::Op("<")) => BinaryOp::Lt,
+            Some(Token::Op("<=")) => BinaryOp::Le,
+            Some(Token::Op(">")) => BinaryOp::Gt,
+            Some(Token::Op(">=")) => BinaryOp::Ge,
+            Some(Token::Ident(ident)) if ident == "in" => BinaryOp::In,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+
+        self.nest()?;
+        let rhs = self.unary()?;
+        self.depth -= 1;
+
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn unary(&mut self) -> Result<Expr, ConditionError> {
+        let depth = self.depth;
+
+        let expr = if self.eat_op("!") {
+            self.nest()?;
+            Expr::Not(Box::new(self.unary()?))
+        } else if self.eat_op("-") {
+            self.nest()?;
+            Expr::Neg(Box::new(self.unary()?))
+        } else {
+            self.primary()?
+        };
+
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ConditionError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return self.error("unexpected end of expression"),
+        };
+
+        match token {
+            Token::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::from(n)))
+            }
+            Token::String(s) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::String(s)))
+            }
+            Token::Op("(") => {
+                self.nest()?;
+                self.pos += 1;
+                let expr = self.or()?;
+                self.depth -= 1;
+                self.expect_op(")")?;
+                Ok(expr)
+            }
+            Token::Op("[") => {
+                self.pos += 1;
+                Ok(Expr::List(self.arguments("]")?))
+            }
+            Token::Ident(ident) => {
+                self.pos += 1;
+                match ident.as_str() {
+                    "true" => Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => Ok(Expr::Literal(Value::Bool(false))),
+                    "null" => Ok(Expr::Literal(Value::Null)),
+                    _ if self.eat_op("(") => Ok(Expr::Call(ident, self.arguments(")")?)),
+                    _ => {
+                        let mut path = vec![ident];
+                        while self.eat_op(".") {
+                            match self.peek() {
+                                Some(Token::Ident(field)) => {
+                                    path.push(field.clone());
+                                    self.pos += 1;
+                                }
+                                _ => return self.error("expected a field name"),
+                            }
+                        }
+                        Ok(Expr::Path(path))
+                    }
+                }
+            }
+            Token::Op(_) => self.error("unexpected operator"),
+        }
+    }
+
+    fn arguments(&mut self, close: &str) -> Result<Vec<Expr>, ConditionError> {
+        let mut args = vec![];
+        if self.eat_op(close) {
+            return Ok(args);
+        }
+
+        self.nest()?;
+        loop {
+            args.push(self.or()?);
+            if self.eat_op(close) {
+                self.depth -= 1;
+                return Ok(args);
+            }
+            self.expect_op(",")?;
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, ConditionError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            end: input.chars().count(),
+            depth: 0,
+        };
+
+        let expr = parser.or()?;
+        if parser.peek().is_some() {
+            return parser.error("unexpected trailing input");
+        }
+
+        Ok(expr)
+    }
+
+    /// Evaluate the expression, which must produce a boolean.
+    pub fn evaluate(&self, context: &Map<String, Value>) -> Result<bool, ConditionError> {
+        match self.value(context)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(ConditionError::Type(format!(
+                "condition evaluated to {other} rather than a boolean"
+            ))),
+        }
+    }
+
+    fn value(&self, context: &Map<String, Value>) -> Result<Value, ConditionError> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::List(items) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| item.value(context))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Path(path) => {
+                let mut value = context.get(&path[0]);
+                for field in &path[1..] {
+                    value = value.and_then(|v| v.get(field));
+                }
+                value
+                    .cloned()
+                    .ok_or_else(|| ConditionError::MissingParameter(path.join(".")))
+            }
+            Expr::Not(expr) => Ok(Value::Bool(!as_bool(&expr.value(context)?)?)),
+            Expr::Neg(expr) => Ok(Value::from(-as_number(&expr.value(context)?)?)),
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => Ok(Value::Bool(
+                as_bool(&lhs.value(context)?)? || as_bool(&rhs.value(context)?)?,
+            )),
+            Expr::Binary(BinaryOp::And, lhs, rhs) => Ok(Value::Bool(
+                as_bool(&lhs.value(context)?)? && as_bool(&rhs.value(context)?)?,
+            )),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.value(context)?;
+                let rhs = rhs.value(context)?;
+
+                let result = match op {
+                    BinaryOp::Eq => equal(&lhs, &rhs),
+                    BinaryOp::Ne => !equal(&lhs, &rhs),
+                    BinaryOp::Lt => compare(&lhs, &rhs)? == Ordering::Less,
+                    BinaryOp::Le => compare(&lhs, &rhs)? != Ordering::Greater,
+                    BinaryOp::Gt => compare(&lhs, &rhs)? == Ordering::Greater,
+                    BinaryOp::Ge => compare(&lhs, &rhs)? != Ordering::Less,
+                    BinaryOp::In => match &rhs {
+                        Value::Array(items) => items.iter().any(|item| equal(&lhs, item)),
+                        Value::Object(map) => map.contains_key(as_str(&lhs)?),
+                        _ => {
+                            return Err(ConditionError::Type(format!(
+                                "cannot test membership in {rhs}"
+                            )))
+                        }
+                    },
+                    BinaryOp::Or | BinaryOp::And => unreachable!(),
+                };
+
+                Ok(Value::Bool(result))
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.value(context))
+                    .collect::<Result<Vec<_>, _>>()?;
+                call(name, &args)
+            }
+        }
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value, ConditionError> {
+    match (name, args) {
+        ("ip_in_cidr", [ip, cidr]) => Ok(Value::Bool(ip_in_cidr(as_str(ip)?, as_str(cidr)?)?)),
+        ("size", [Value::String(s)]) => Ok(Value::from(s.chars().count())),
+        ("size", [Value::Array(items)]) => Ok(Value::from(items.len())),
+        ("size", [Value::Object(map)]) => Ok(Value::from(map.len())),
+        ("ip_in_cidr" | "size", _) => Err(ConditionError::Type(format!(
+            "invalid arguments to {name}()"
+        ))),
+        _ => Err(ConditionError::UnknownFunction(name.to_string())),
+    }
+}
+
+fn ip_in_cidr(ip: &str, cidr: &str) -> Result<bool, ConditionError> {
+    let invalid = |what: &str| ConditionError::Type(format!("invalid {what}"));
+
+    let ip: IpAddr = ip.parse().map_err(|_| invalid("IP address"))?;
+    let (network, prefix) = cidr.split_once('/').ok_or_else(|| invalid("CIDR"))?;
+    let network: IpAddr = network.parse().map_err(|_| invalid("CIDR"))?;
+    let prefix: u32 = prefix.parse().map_err(|_| invalid("CIDR"))?;
+
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            Ok(u32::from(ip) & mask == u32::from(network) & mask)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            Ok(u128::from(ip) & mask == u128::from(network) & mask)
+        }
+        (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => Err(invalid("CIDR")),
+        _ => Ok(false),
+    }
+}
+
+fn as_bool(value: &Value) -> Result<bool, ConditionError> {
+    value
+        .as_bool()
+        .ok_or_else(|| ConditionError::Type(format!("expected a boolean, got {value}")))
+}
+
+fn as_number(value: &Value) -> Result<f64, ConditionError> {
+    value
+        .as_f64()
+        .ok_or_else(|| ConditionError::Type(format!("expected a number, got {value}")))
+}
+
+fn as_str(value: &Value) -> Result<&str, ConditionError> {
+    value
+        .as_str()
+        .ok_or_else(|| ConditionError::Type(format!("expected a string, got {value}")))
+}
+
+fn equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(l), Some(r)) => l == r,
+        _ => lhs == rhs,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Result<Ordering, ConditionError> {
+    match (lhs, rhs) {
+        (Value::Number(_), Value::Number(_)) => as_number(lhs)?
+            .partial_cmp(&as_number(rhs)?)
+            .ok_or_else(|| ConditionError::Type("cannot compare NaN".to_string())),
+        (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+        _ => Err(ConditionError::Type(format!(
+            "cannot compare {lhs} with {rhs}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn eval(expression: &str, context: Value) -> Result<bool, ConditionError> {
+        let context = context.as_object().cloned().unwrap_or_default();
+        Expr::parse(expression)?.evaluate(&context)
+    }
+
+    #[test]
+    fn comparisons_work() {
+        let context = json!({"resource": {"classification": 2}, "user": {"clearance": 3}});
+
+        assert_eq!(
+            eval("resource.classification <= user.clearance", context.clone()),
+            Ok(true)
+        );
+        assert_eq!(
+            eval("resource.classification > user.clearance", context.clone()),
+            Ok(false)
+        );
+        assert_eq!(eval("user.clearance == 3.0", context.clone()), Ok(true));
+        assert_eq!(eval("-user.clearance < 0", context.clone()), Ok(true));
+        assert_eq!(eval("'b' > \"a\"", context), Ok(true));
+    }
+
+    #[test]
+    fn boolean_operators_work() {
+        let context = json!({"a": true, "b": false});
+
+        assert_eq!(eval("a && !b", context.clone()), Ok(true));
+        assert_eq!(eval("b || (a && b)", context.clone()), Ok(false));
+        assert_eq!(eval("b || a && true", context), Ok(true));
+    }
+
+    #[test]
+    fn membership_works() {
+        let context = json!({"role": "admin", "tags": {"public": true}});
+
+        assert_eq!(
+            eval("role in ['admin', 'owner']", context.clone()),
+            Ok(true)
+        );
+        assert_eq!(eval("'public' in tags", context.clone()), Ok(true));
+        assert_eq!(eval("size(tags) == 1", context), Ok(true));
+    }
+
+    #[test]
+    fn ip_in_cidr_works() {
+        assert_eq!(
+            eval("ip_in_cidr(ip, '10.0.0.0/8')", json!({"ip": "10.1.2.3"})),
+            Ok(true)
+        );
+        assert_eq!(
+            eval("ip_in_cidr(ip, '10.0.0.0/8')", json!({"ip": "192.168.0.1"})),
+            Ok(false)
+        );
+        assert_eq!(
+            eval("ip_in_cidr(ip, '0.0.0.0/0')", json!({"ip": "192.168.0.1"})),
+            Ok(true)
+        );
+        assert_eq!(
+            eval(
+                "ip_in_cidr(ip, '2001:db8::/32')",
+                json!({"ip": "2001:db8::1"})
+            ),
+            Ok(true)
+        );
+        assert!(matches!(
+            eval("ip_in_cidr(ip, 'nope')", json!({"ip": "10.1.2.3"})),
+            Err(ConditionError::Type(_))
+        ));
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        assert_eq!(
+            eval("user.clearance > 1", json!({})),
+            Err(ConditionError::MissingParameter(
+                "user.clearance".to_string()
+            ))
+        );
+        assert_eq!(
+            eval("a &&", json!({"a": true})),
+            Err(ConditionError::Syntax(
+                4,
+                "unexpected end of expression".to_string()
+            ))
+        );
+        assert_eq!(
+            eval("a b", json!({})),
+            Err(ConditionError::Syntax(
+                2,
+                "unexpected trailing input".to_string()
+            ))
+        );
+        assert_eq!(
+            eval("1 + 2", json!({})),
+            Err(ConditionError::Syntax(
+                2,
+                "unexpected character '+'".to_string()
+            ))
+        );
+        assert!(matches!(eval("1", json!({})), Err(ConditionError::Type(_))));
+        assert!(matches!(
+            eval("now()", json!({})),
+            Err(ConditionError::UnknownFunction(_))
+        ));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize, open: &str, close: &str| {
+            format!("{}a{}", open.repeat(depth), close.repeat(depth))
+        };
+        let context = json!({"a": true, "b": [true]});
+
+        assert_eq!(
+            eval(&nested(MAX_NESTING, "(", ")"), context.clone()),
+            Ok(true)
+        );
+        assert_eq!(
+            eval(&nested(MAX_NESTING + 1, "(", ")"), context.clone()),
+            Err(ConditionError::TooDeep(MAX_NESTING))
+        );
+        assert_eq!(
+            eval(&nested(MAX_NESTING, "!", ""), context.clone()),
+            Ok(true)
+        );
+        assert!(matches!(
+            eval(&nested(MAX_NESTING + 1, "!", ""), context.clone()),
+            Err(ConditionError::TooDeep(_))
+        ));
+        assert!(matches!(
+            eval(&nested(MAX_NESTING + 1, "[", "]"), context.clone()),
+            Err(ConditionError::TooDeep(_))
+        ));
+        assert!(matches!(
+            eval(&vec!["a"; MAX_NESTING + 2].join(" && "), context.clone()),
+            Err(ConditionError::TooDeep(_))
+        ));
+
+        // Far deeper than the stack allows, which must fail before recursing.
+        let deep = nested(1_000_000, "(", ")");
+        assert!(matches!(
+            eval(&deep, context.clone()),
+            Err(ConditionError::TooDeep(_))
+        ));
+        assert!(matches!(
+            eval(&"!".repeat(1_000_000), context),
+            Err(ConditionError::TooDeep(_))
+        ));
+    }
+}
@@ -0,0 +1,16 @@
+use crate::check::MAX_DEPTH;
+use crate::condition::ConditionError;
+use thiserror::Error;
+
+/// An error checking tuples. Stores wrap these in their own errors.
+#[derive(Debug, Error, PartialEq)]
+pub enum CheckError {
+    #[error("check max depth of {MAX_DEPTH} exceeded")]
+    MaxDepth,
+
+    #[error("'{0}' does not correspond to any condition in the schema")]
+    UnknownCondition(String),
+
+    #[error("error evaluating condition '{0}': {1}")]
+    ConditionError(String, ConditionError),
+}
@@ -0,0 +1,22 @@
+//! Schemas and checks without Postgres. The `pgfga` extension checks the
+//! tuples in `pgfga.tuple` with this crate, and anything else can check
+//! tuples from elsewhere with the exact same semantics by implementing
+//! `TupleStore`, or by using `MemoryStore`.
+
+// Checks take the fields of tuples as separate arguments, like the SQL
+// functions of the extension do.
+#![allow(clippy::too_many_arguments)]
+
+pub mod check;
+pub mod condition;
+pub mod error;
+pub mod openfga;
+pub mod schema;
+pub mod spicedb;
+pub mod text;
+pub mod tuple_store;
+
+pub use check::Checker;
+pub use error::CheckError;
+pub use schema::Schema;
+pub use tuple_store::{MemoryStore, Tuple, TupleStore};
@@ -0,0 +1,831 @@
+//! Import OpenFGA authorization models, in JSON or the DSL, as schemas.
+//!
+//! OpenFGA defines relations and permissions alike as relations, so each one
+//! is converted by its rewrite: a relation that is only `this` (its directly
+//! related user types) becomes a relation, and any other becomes a
+//! permission. A relation that mixes `this` with other rewrites, as in
+//! `define viewer: [user] or editor`, is split in two: the directly related
+//! user types become a relation named `viewer_direct`, and `viewer` becomes a
+//! permission using it. Tuples of such relations have to be written to the
+//! `_direct` relation.
+//!
+//! Wildcards (`user:*`) are supported. Conditions, and anything else without
+//! an equivalent in a schema, are reported rather than dropped.
+
+use crate::schema::{ImportError, Namespace, Rewrite, Schema, TypeRestriction};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Appended to the name of a relation that mixes `this` with other rewrites,
+/// for the relation its directly related user types are split into.
+pub const DIRECT_SUFFIX: &str = "_direct";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Model {
+    #[serde(default)]
+    pub schema_version: Option<String>,
+
+    #[serde(default)]
+    pub type_definitions: Vec<TypeDefinition>,
+
+    #[serde(default)]
+    pub conditions: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct TypeDefinition {
+    #[serde(rename = "type")]
+    pub name: String,
+
+    #[serde(default)]
+    pub relations: BTreeMap<String, Userset>,
+
+    #[serde(default)]
+    pub metadata: Option<Metadata>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Metadata {
+    #[serde(default)]
+    pub relations: Option<BTreeMap<String, RelationMetadata>>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct RelationMetadata {
+    #[serde(default)]
+    pub directly_related_user_types: Vec<RelationReference>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct RelationReference {
+    #[serde(rename = "type")]
+    pub name: String,
+
+    #[serde(default)]
+    pub relation: Option<String>,
+
+    #[serde(default)]
+    pub wildcard: Option<serde_json::Value>,
+
+    #[serde(default)]
+    pub condition: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ObjectRelation {
+    #[serde(default)]
+    pub object: String,
+
+    #[serde(default)]
+    pub relation: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TupleToUserset {
+    pub tupleset: ObjectRelation,
+    pub computed_userset: ObjectRelation,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Usersets {
+    pub child: Vec<Userset>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Difference {
+    pub base: Box<Userset>,
+    pub subtract: Box<Userset>,
+}
+
+/// The empty object of `this`.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct This {}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Userset {
+    This(This),
+    ComputedUserset(ObjectRelation),
+    TupleToUserset(TupleToUserset),
+    Union(Usersets),
+    Intersection(Usersets),
+    Difference(Difference),
+}
+
+impl Userset {
+    fn this() -> Self {
+        Userset::This(This {})
+    }
+
+    fn computed_userset(relation: &str) -> Self {
+        Userset::ComputedUserset(ObjectRelation {
+            object: String::new(),
+            relation: relation.to_string(),
+        })
+    }
+
+    fn contains_this(&self) -> bool {
+        match self {
+            Userset::This(_) => true,
+            Userset::ComputedUserset(_) | Userset::TupleToUserset(_) => false,
+            Userset::Union(usersets) | Userset::Intersection(usersets) => {
+                usersets.child.iter().any(Userset::contains_this)
+            }
+            Userset::Difference(difference) => {
+                difference.base.contains_this() || difference.subtract.contains_this()
+            }
+        }
+    }
+}
+
+/// Import a model in OpenFGA's JSON format, as returned by its API.
+pub fn import_json(model: serde_json::Value) -> Result<Schema, ImportError> {
+    let model: Model =
+        serde_json::from_value(model).map_err(|err| ImportError::Invalid(err.to_string()))?;
+
+    import(model)
+}
+
+/// Import a model in OpenFGA's DSL.
+pub fn import_dsl(model: &str) -> Result<Schema, ImportError> {
+    import(parse_dsl(model)?)
+}
+
+/// The state of converting a single type.
+struct Converter<'a> {
+    type_name: &'a str,
+
+    /// The relations that are split in two, by name.
+    split: Vec<&'a str>,
+
+    unsupported: &'a mut Vec<String>,
+}
+
+impl Converter<'_> {
+    fn direct_name(&self, relation: &str) -> String {
+        if self.split.contains(&relation) {
+            format!("{relation}{DIRECT_SUFFIX}")
+        } else {
+            relation.to_string()
+        }
+    }
+
+    fn restriction(&mut self, relation: &str, reference: &RelationReference) -> TypeRestriction {
+        if !reference.condition.is_empty() {
+            self.unsupported.push(format!(
+                "condition '{}' on {}#{relation}",
+                reference.condition, self.type_name
+            ));
+        }
+
+        match (&reference.relation, &reference.wildcard) {
+            (_, Some(_)) => TypeRestriction::NamespaceWildcard(reference.name.clone()),
+            (Some(action), None) if !action.is_empty() => {
+                TypeRestriction::NamespaceAction(reference.name.clone(), action.clone())
+            }
+            _ => TypeRestriction::Namespace(reference.name.clone()),
+        }
+    }
+
+    /// `object` is a leftover of OpenFGA's schema version 1.0.
+    fn check_object(&mut self, relation: &str, computed: &ObjectRelation) {
+        if !computed.object.is_empty() {
+            self.unsupported.push(format!(
+                "userset object '{}' in {}#{relation}",
+                computed.object, self.type_name
+            ));
+        }
+    }
+
+    fn rewrite(&mut self, relation: &str, userset: &Userset) -> Rewrite {
+        match userset {
+            Userset::This(_) => Rewrite::ComputedUserset(self.direct_name(relation)),
+            Userset::ComputedUserset(computed) => {
+                self.check_object(relation, computed);
+                Rewrite::ComputedUserset(computed.relation.clone())
+            }
+            Userset::TupleToUserset(ttu) => {
+                self.check_object(relation, &ttu.computed_userset);
+                Rewrite::TupleToUserset(
+                    self.direct_name(&ttu.tupleset.relation),
+                    ttu.computed_userset.relation.clone(),
+                )
+            }
+            Userset::Union(usersets) => Rewrite::Union(
+                usersets
+                    .child
+                    .iter()
+                    .map(|child| self.rewrite(relation, child))
+                    .collect(),
+            ),
+            Userset::Intersection(usersets) => Rewrite::Intersection(
+                usersets
+                    .child
+                    .iter()
+                    .map(|child| self.rewrite(relation, child))
+                    .collect(),
+            ),
+            Userset::Difference(difference) => Rewrite::Exclusion(
+                Box::new(self.rewrite(relation, &difference.base)),
+                Box::new(self.rewrite(relation, &difference.subtract)),
+            ),
+        }
+    }
+}
+
+/// Convert a model into a schema, or report everything in it that can't be
+/// converted.
+pub fn import(model: Model) -> Result<Schema, ImportError> {
+    let mut unsupported = vec![];
+
+    if let Some(version) = model.schema_version.as_deref() {
+        if version != "1.1" {
+            unsupported.push(format!("schema version {version}"));
+        }
+    }
+
+    for name in model.conditions.keys() {
+        unsupported.push(format!("condition '{name}'"));
+    }
+
+    let mut namespaces = HashMap::new();
+
+    for type_definition in &model.type_definitions {
+        let type_name = type_definition.name.as_str();
+        if namespaces.contains_key(type_name) {
+            return Err(ImportError::Invalid(format!(
+                "type '{type_name}' is defined more than once"
+            )));
+        }
+
+        let metadata = type_definition
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.relations.as_ref());
+
+        let split: Vec<_> = type_definition
+            .relations
+            .iter()
+            .filter(|(_, userset)| !matches!(userset, Userset::This(_)) && userset.contains_this())
+            .map(|(relation, _)| relation.as_str())
+            .collect();
+
+        let mut converter = Converter {
+            type_name,
+            split,
+            unsupported: &mut unsupported,
+        };
+
+        let mut namespace = Namespace {
+            relations: HashMap::new(),
+            permissions: HashMap::new(),
+            sources: HashMap::new(),
+        };
+
+        for (relation, userset) in &type_definition.relations {
+            let references = metadata
+                .and_then(|metadata| metadata.get(relation))
+                .map(|metadata| metadata.directly_related_user_types.as_slice())
+                .unwrap_or_default();
+
+            if userset.contains_this() {
+                let direct = converter.direct_name(relation);
+                if direct != *relation && type_definition.relations.contains_key(&direct) {
+                    return Err(ImportError::Invalid(format!(
+                        "{type_name}#{relation} can't be split, as {type_name}#{direct} is already defined"
+                    )));
+                }
+
+                let restrictions = references
+                    .iter()
+                    .map(|reference| converter.restriction(relation, reference))
+                    .collect();
+                namespace.relations.insert(direct, restrictions);
+            }
+
+            if !matches!(userset, Userset::This(_)) {
+                let rewrite = converter.rewrite(relation, userset);
+                namespace.permissions.insert(relation.clone(), rewrite);
+            }
+        }
+
+        namespaces.insert(type_name.to_string(), namespace);
+    }
+
+    if !unsupported.is_empty() {
+        return Err(ImportError::Unsupported(unsupported));
+    }
+
+    Ok(Schema {
+        namespaces,
+        conditions: HashMap::new(),
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Punct(char),
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, ImportError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "[](),:*#".contains(c) {
+            tokens.push(Token::Punct(c));
+            chars.next();
+        } else if c.is_alphanumeric() || "_-./".contains(c) {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_alphanumeric() || "_-./".contains(c)) {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            return Err(ImportError::Syntax(line, format!("unexpected '{c}'")));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses the rewrite of a single `define`.
+struct ExprParser<'a> {
+    line: usize,
+    tokens: &'a [Token],
+    pos: usize,
+    references: Option<Vec<RelationReference>>,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ImportError> {
+        Err(ImportError::Syntax(self.line, message.into()))
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(Token::Name(name)) => format!("'{name}'"),
+            Some(Token::Punct(c)) => format!("'{c}'"),
+            None => "end of line".to_string(),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek() == Some(&Token::Name(keyword.to_string())) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn punct(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ImportError> {
+        if !self.punct(c) {
+            let found = self.found();
+            return self.error(format!("expected '{c}', found {found}"));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self, what: &str) -> Result<String, ImportError> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => {
+                let found = self.found();
+                self.error(format!("expected {what}, found {found}"))
+            }
+        }
+    }
+
+    /// `term (or term)*`, `term (and term)*` or `term but not term`. Mixing
+    /// operators takes parentheses, as it does in OpenFGA.
+    fn expr(&mut self) -> Result<Userset, ImportError> {
+        let first = self.term()?;
+
+        if self.keyword("but") {
+            if !self.keyword("not") {
+                let found = self.found();
+                return self.error(format!("expected 'not', found {found}"));
+            }
+            let subtract = self.term()?;
+            return Ok(Userset::Difference(Difference {
+                base: Box::new(first),
+                subtract: Box::new(subtract),
+            }));
+        }
+
+        for (operator, make) in [
+            ("or", Userset::Union as fn(Usersets) -> Userset),
+            ("and", Userset::Intersection),
+        ] {
+            if self.peek() == Some(&Token::Name(operator.to_string())) {
+                let mut child = vec![first];
+                while self.keyword(operator) {
+                    child.push(self.term()?);
+                }
+                return Ok(make(Usersets { child }));
+            }
+        }
+
+        Ok(first)
+    }
+
+    fn term(&mut self) -> Result<Userset, ImportError> {
+        if self.punct('(') {
+            let userset = self.expr()?;
+            self.expect_punct(')')?;
+            return Ok(userset);
+        }
+
+        if self.punct('[') {
+            if self.references.is_some() {
+                return self.error("directly related user types can only be given once");
+            }
+
+            let mut references = vec![self.reference()?];
+            while self.punct(',') {
+                references.push(self.reference()?);
+            }
+            self.expect_punct(']')?;
+
+            self.references = Some(references);
+            return Ok(Userset::this());
+        }
+
+        let relation = self.name("a relation")?;
+        if self.keyword("from") {
+            let tupleset = self.name("a relation")?;
+            return Ok(Userset::TupleToUserset(TupleToUserset {
+                tupleset: ObjectRelation {
+                    object: String::new(),
+                    relation: tupleset,
+                },
+                computed_userset: ObjectRelation {
+                    object: String::new(),
+                    relation,
+                },
+            }));
+        }
+
+        Ok(Userset::computed_userset(&relation))
+    }
+
+    /// `type`, `type:*` or `type#relation`, optionally `with condition`.
+    fn reference(&mut self) -> Result<RelationReference, ImportError> {
+        let mut reference = RelationReference {
+            name: self.name("a type")?,
+            ..Default::default()
+        };
+
+        if self.punct(':') {
+            self.expect_punct('*')?;
+            reference.wildcard = Some(serde_json::json!({}));
+        } else if self.punct('#') {
+            reference.relation = Some(self.name("a relation")?);
+        }
+
+        if self.keyword("with") {
+            reference.condition = self.name("a condition")?;
+        }
+
+        Ok(reference)
+    }
+}
+
+/// Strip a `#` comment, which starts a line or follows whitespace, so that
+/// it isn't confused with `group#member`.
+fn strip_comment(line: &str) -> &str {
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        if c == '#' && prev.is_whitespace() {
+            return &line[..i];
+        }
+        prev = c;
+    }
+    line
+}
+
+/// Parse a model written in OpenFGA's DSL, e.g.
+///
+/// ```text
+/// model
+///   schema 1.1
+/// type user
+/// type document
+///   relations
+///     define viewer: [user, group#member] or editor or viewer from parent
+/// ```
+pub fn parse_dsl(model: &str) -> Result<Model, ImportError> {
+    let mut parsed = Model::default();
+    let mut lines = model.lines().enumerate().map(|(i, line)| (i + 1, line));
+
+    while let Some((line, text)) = lines.next() {
+        let text = strip_comment(text).trim();
+        let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+
+        match keyword {
+            "" | "model" | "relations" => {}
+            "schema" => parsed.schema_version = Some(rest.to_string()),
+            "type" => {
+                if rest.is_empty() {
+                    return Err(ImportError::Syntax(line, "expected a type".to_string()));
+                }
+                parsed.type_definitions.push(TypeDefinition {
+                    name: rest.to_string(),
+                    ..Default::default()
+                });
+            }
+            "define" => {
+                let type_definition = parsed.type_definitions.last_mut().ok_or_else(|| {
+                    ImportError::Syntax(line, "define outside of a type".to_string())
+                })?;
+
+                let (relation, expr) = rest.split_once(':').ok_or_else(|| {
+                    ImportError::Syntax(line, "expected ':' after the relation".to_string())
+                })?;
+                let relation = relation.trim().to_string();
+
+                let tokens = tokenize(line, expr)?;
+                let mut parser = ExprParser {
+                    line,
+                    tokens: &tokens,
+                    pos: 0,
+                    references: None,
+                };
+                let userset = parser.expr()?;
+                if parser.pos < tokens.len() {
+                    let found = parser.found();
+                    return parser.error(format!(
+                        "unexpected {found}; mixing operators takes parentheses"
+                    ));
+                }
+
+                if let Some(references) = parser.references {
+                    type_definition
+                        .metadata
+                        .get_or_insert_with(Metadata::default)
+                        .relations
+                        .get_or_insert_with(BTreeMap::new)
+                        .insert(
+                            relation.clone(),
+                            RelationMetadata {
+                                directly_related_user_types: references,
+                            },
+                        );
+                }
+
+                if type_definition
+                    .relations
+                    .insert(relation.clone(), userset)
+                    .is_some()
+                {
+                    return Err(ImportError::Syntax(
+                        line,
+                        format!("relation '{relation}' is defined more than once"),
+                    ));
+                }
+            }
+            "condition" => {
+                // Conditions are unsupported, but are skipped to the end of
+                // their body so that everything else can still be reported.
+                let name = rest.split('(').next().unwrap_or_default().trim();
+                parsed
+                    .conditions
+                    .insert(name.to_string(), serde_json::Value::Null);
+
+                let mut depth = text.matches('{').count() as i64 - text.matches('}').count() as i64;
+                let mut opened = depth > 0;
+                while !opened || depth > 0 {
+                    let Some((_, text)) = lines.next() else {
+                        return Err(ImportError::Syntax(
+                            line,
+                            format!("condition '{name}' is not closed"),
+                        ));
+                    };
+                    depth += text.matches('{').count() as i64 - text.matches('}').count() as i64;
+                    opened = opened || text.contains('{');
+                }
+            }
+            "module" | "extend" => {
+                return Err(ImportError::Unsupported(vec![format!(
+                    "{keyword} (modular models)"
+                )]));
+            }
+            _ => return Err(ImportError::Syntax(line, format!("unexpected '{keyword}'"))),
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const DSL: &str = "
+model
+  schema 1.1
+
+# Users and the groups they are in.
+type user
+type group
+  relations
+    define member: [user, group#member]
+type folder
+  relations
+    define viewer: [user, user:*]
+type document
+  relations
+    define parent: [folder]
+    define owner: [user]
+    define editor: [user] or owner
+    define viewer: [group#member] or editor or viewer from parent # inherited
+    define blocked: [user]
+    define can_view: viewer but not blocked
+    define can_share: (viewer or editor) and owner
+";
+
+    fn expected() -> Schema {
+        serde_json::from_value(json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                "folder": {"relations": {"viewer": [{"namespace": "user"}, {"namespaceWildcard": "user"}]}},
+                "document": {
+                    "relations": {
+                        "parent": [{"namespace": "folder"}],
+                        "owner": [{"namespace": "user"}],
+                        "editor_direct": [{"namespace": "user"}],
+                        "viewer_direct": [{"namespaceAction": ["group", "member"]}],
+                        "blocked": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "editor": {"union": [{"computedUserset": "editor_direct"}, {"computedUserset": "owner"}]},
+                        "viewer": {"union": [
+                            {"computedUserset": "viewer_direct"},
+                            {"computedUserset": "editor"},
+                            {"tupleToUserset": ["parent", "viewer"]}
+                        ]},
+                        "can_view": {"exclusion": [{"computedUserset": "viewer"}, {"computedUserset": "blocked"}]},
+                        "can_share": {"intersection": [
+                            {"union": [{"computedUserset": "viewer"}, {"computedUserset": "editor"}]},
+                            {"computedUserset": "owner"}
+                        ]}
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn import_dsl_works() {
+        assert_eq!(import_dsl(DSL), Ok(expected()));
+    }
+
+    #[test]
+    fn import_json_works() {
+        let model = json!({
+            "schema_version": "1.1",
+            "type_definitions": [
+                {"type": "user"},
+                {
+                    "type": "group",
+                    "relations": {"member": {"this": {}}},
+                    "metadata": {"relations": {"member": {"directly_related_user_types": [
+                        {"type": "user"}, {"type": "group", "relation": "member"}
+                    ]}}}
+                },
+                {
+                    "type": "folder",
+                    "relations": {"viewer": {"this": {}}},
+                    "metadata": {"relations": {"viewer": {"directly_related_user_types": [
+                        {"type": "user"}, {"type": "user", "wildcard": {}}
+                    ]}}}
+                },
+                {
+                    "type": "document",
+                    "relations": {
+                        "parent": {"this": {}},
+                        "owner": {"this": {}},
+                        "editor": {"union": {"child": [
+                            {"this": {}},
+                            {"computedUserset": {"object": "", "relation": "owner"}}
+                        ]}},
+                        "viewer": {"union": {"child": [
+                            {"this": {}},
+                            {"computedUserset": {"relation": "editor"}},
+                            {"tupleToUserset": {
+                                "tupleset": {"relation": "parent"},
+                                "computedUserset": {"relation": "viewer"}
+                            }}
+                        ]}},
+                        "blocked": {"this": {}},
+                        "can_view": {"difference": {
+                            "base": {"computedUserset": {"relation": "viewer"}},
+                            "subtract": {"computedUserset": {"relation": "blocked"}}
+                        }},
+                        "can_share": {"intersection": {"child": [
+                            {"union": {"child": [
+                                {"computedUserset": {"relation": "viewer"}},
+                                {"computedUserset": {"relation": "editor"}}
+                            ]}},
+                            {"computedUserset": {"relation": "owner"}}
+                        ]}}
+                    },
+                    "metadata": {"relations": {
+                        "parent": {"directly_related_user_types": [{"type": "folder"}]},
+                        "owner": {"directly_related_user_types": [{"type": "user"}]},
+                        "editor": {"directly_related_user_types": [{"type": "user"}]},
+                        "viewer": {"directly_related_user_types": [{"type": "group", "relation": "member"}]},
+                        "blocked": {"directly_related_user_types": [{"type": "user"}]}
+                    }}
+                }
+            ]
+        });
+
+        assert_eq!(import_json(model), Ok(expected()));
+    }
+
+    #[test]
+    fn unsupported_constructs_are_reported() {
+        let model = "
+model
+  schema 1.1
+type user
+type document
+  relations
+    define viewer: [user with non_expired, user:*]
+condition non_expired(expires_at: timestamp, now: timestamp) {
+  now < expires_at
+}
+";
+
+        assert_eq!(
+            import_dsl(model),
+            Err(ImportError::Unsupported(vec![
+                "condition 'non_expired'".to_string(),
+                "condition 'non_expired' on document#viewer".to_string(),
+            ]))
+        );
+
+        assert_eq!(
+            import_dsl("model\n  schema 1.0\ntype user"),
+            Err(ImportError::Unsupported(vec![
+                "schema version 1.0".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert_eq!(
+            import_dsl("type document\n  relations\n    define viewer: a or b and c"),
+            Err(ImportError::Syntax(
+                3,
+                "unexpected 'and'; mixing operators takes parentheses".to_string()
+            ))
+        );
+        assert_eq!(
+            import_dsl("define viewer: [user]"),
+            Err(ImportError::Syntax(
+                1,
+                "define outside of a type".to_string()
+            ))
+        );
+        assert_eq!(
+            import_dsl("type document\n  relations\n    define viewer: [user"),
+            Err(ImportError::Syntax(
+                3,
+                "expected ']', found end of line".to_string()
+            ))
+        );
+    }
+}
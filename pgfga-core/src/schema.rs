@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The subject id of a tuple granting a relation to every subject of a
+/// namespace, as in `user:*`.
+pub const WILDCARD: &str = "*";
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Schema {
+    pub namespaces: HashMap<String, Namespace>,
+
+    #[serde(default)]
+    pub conditions: HashMap<String, Condition>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Namespace {
+    #[serde(default)]
+    pub relations: HashMap<String, Vec<TypeRestriction>>,
+
+    #[serde(default)]
+    pub permissions: HashMap<String, Rewrite>,
+
+    /// Relations whose tuples also come from application tables, keyed by
+    /// relation.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sources: HashMap<String, RelationSource>,
+}
+
+/// Application data a relation is read from, on top of its tuples, so that it
+/// doesn't have to be copied into `pgfga.tuple`. Each row is a tuple from the
+/// resource with the row's resource id to the subject
+/// `subject_namespace:subject_id#subject_action`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelationSource {
+    #[serde(flatten)]
+    pub from: SourceFrom,
+
+    pub subject_namespace: String,
+
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub subject_action: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum SourceFrom {
+    /// The resource and subject id columns of a table, e.g. `documents.id`
+    /// and `documents.owner_id` for `document#owner`. The table may be
+    /// schema-qualified, and the columns are names rather than expressions.
+    #[serde(rename_all = "camelCase")]
+    Table {
+        table: String,
+        resource_id: String,
+        subject_id: String,
+    },
+
+    /// A query returning resource and subject ids, in that order. It is run
+    /// as written, so it should only be taken from trusted schemas.
+    Query { query: String },
+}
+
+/// A named condition that tuples can be made conditional on. See the
+/// `condition` module for the expression syntax.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Condition {
+    pub expression: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TypeRestriction {
+    Namespace(String),
+    NamespaceAction(String, String),
+    NamespaceWildcard(String),
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Rewrite {
+    ComputedUserset(String),
+    TupleToUserset(String, String),
+    Union(Vec<Rewrite>),
+    Intersection(Vec<Rewrite>),
+    Exclusion(Box<Rewrite>, Box<Rewrite>),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ValidationError {
+    #[error("namespace '{0}' is not defined")]
+    UnknownNamespace(String),
+
+    #[error("'{0}' has no relation '{1}'")]
+    UnknownRelation(String, String),
+
+    #[error("'{2}' is not an allowed subject of '{0}#{1}'")]
+    SubjectNotAllowed(String, String, String),
+}
+
+/// An error importing a schema written for another system, e.g. by the
+/// `openfga` and `spicedb` modules.
+#[derive(Debug, Error, PartialEq)]
+pub enum ImportError {
+    #[error("syntax error at line {0}: {1}")]
+    Syntax(usize, String),
+
+    #[error("invalid model: {0}")]
+    Invalid(String),
+
+    #[error("unsupported in model: {}", .0.join(", "))]
+    Unsupported(Vec<String>),
+}
+
+impl TypeRestriction {
+    pub fn allows(&self, subject_namespace: &str, subject_id: &str, subject_action: &str) -> bool {
+        match self {
+            TypeRestriction::Namespace(ns) => {
+                ns == subject_namespace && subject_id != WILDCARD && subject_action.is_empty()
+            }
+            TypeRestriction::NamespaceAction(ns, action) => {
+                ns == subject_namespace && subject_id != WILDCARD && action == subject_action
+            }
+            TypeRestriction::NamespaceWildcard(ns) => {
+                ns == subject_namespace && subject_id == WILDCARD && subject_action.is_empty()
+            }
+        }
+    }
+}
+
+impl Schema {
+    /// Check that the tuple's relation exists and that its subject is
+    /// allowed by the relation's type restrictions.
+    pub fn validate_tuple(
+        &self,
+        resource_namespace: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<(), ValidationError> {
+        let restrictions = self
+            .namespaces
+            .get(resource_namespace)
+            .ok_or_else(|| ValidationError::UnknownNamespace(resource_namespace.to_string()))?
+            .relations
+            .get(relation)
+            .ok_or_else(|| {
+                ValidationError::UnknownRelation(
+                    resource_namespace.to_string(),
+                    relation.to_string(),
+                )
+            })?;
+
+        if restrictions
+            .iter()
+            .any(|restriction| restriction.allows(subject_namespace, subject_id, subject_action))
+        {
+            return Ok(());
+        }
+
+        let mut subject = format!("{subject_namespace}:{subject_id}");
+        if !subject_action.is_empty() {
+            subject.push('#');
+            subject.push_str(subject_action);
+        }
+
+        Err(ValidationError::SubjectNotAllowed(
+            resource_namespace.to_string(),
+            relation.to_string(),
+            subject,
+        ))
+    }
+
+    pub fn is_relation(&self, namespace: &str, action: &str) -> bool {
+        self.namespaces
+            .get(namespace)
+            .and_then(|ns| ns.relations.get(action))
+            .is_some()
+    }
+
+    pub fn allows_wildcard(
+        &self,
+        namespace: &str,
+        relation: &str,
+        subject_namespace: &str,
+    ) -> bool {
+        self.namespaces
+            .get(namespace)
+            .and_then(|ns| ns.relations.get(relation))
+            .is_some_and(|restrictions| {
+                restrictions.iter().any(|restriction| {
+                    matches!(restriction, TypeRestriction::NamespaceWildcard(ns) if ns == subject_namespace)
+                })
+            })
+    }
+
+    pub fn source(&self, namespace: &str, relation: &str) -> Option<&RelationSource> {
+        self.namespaces
+            .get(namespace)
+            .and_then(|ns| ns.sources.get(relation))
+    }
+
+    /// Check that every source is for a relation of its namespace.
+    pub fn validate_sources(&self) -> Result<(), ValidationError> {
+        for (ns, namespace) in &self.namespaces {
+            for relation in namespace.sources.keys() {
+                if !namespace.relations.contains_key(relation) {
+                    return Err(ValidationError::UnknownRelation(
+                        ns.to_string(),
+                        relation.to_string(),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_permission(&self, namespace: &str, action: &str) -> bool {
+        self.namespaces
+            .get(namespace)
+            .and_then(|ns| ns.permissions.get(action))
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ser_then_der_works() {
+        let schema = Schema {
+            namespaces: HashMap::from([
+                (
+                    "user".to_string(),
+                    Namespace {
+                        relations: HashMap::new(),
+                        permissions: HashMap::new(),
+                        sources: HashMap::new(),
+                    },
+                ),
+                (
+                    "document".to_string(),
+                    Namespace {
+                        relations: HashMap::from([(
+                            "viewer".to_string(),
+                            vec![TypeRestriction::Namespace("user".to_string())],
+                        )]),
+                        permissions: HashMap::from([(
+                            "can_view".to_string(),
+                            Rewrite::Union(vec![
+                                Rewrite::ComputedUserset("viewer".to_string()),
+                                Rewrite::TupleToUserset(
+                                    "parent".to_string(),
+                                    "can_view".to_string(),
+                                ),
+                            ]),
+                        )]),
+                        sources: HashMap::from([(
+                            "owner".to_string(),
+                            RelationSource {
+                                from: SourceFrom::Table {
+                                    table: "documents".to_string(),
+                                    resource_id: "id".to_string(),
+                                    subject_id: "owner_id".to_string(),
+                                },
+                                subject_namespace: "user".to_string(),
+                                subject_action: "".to_string(),
+                            },
+                        )]),
+                    },
+                ),
+            ]),
+            conditions: HashMap::from([(
+                "in_office".to_string(),
+                Condition {
+                    expression: "ip_in_cidr(request.ip, '10.0.0.0/8')".to_string(),
+                },
+            )]),
+        };
+
+        let serialized = serde_json::to_string(&schema).unwrap();
+        let deserialized: Schema = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(schema, deserialized);
+    }
+
+    #[test]
+    fn validate_tuple_works() {
+        let schema: Schema = serde_json::from_str(
+            r#"{"namespaces":{"user":{},"group":{"relations":{"member":[{"namespace":"user"}]}},"document":{"relations":{"viewer":[{"namespace":"user"},{"namespaceAction":["group","member"]}]}}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema.validate_tuple("document", "viewer", "user", "anya", ""),
+            Ok(())
+        );
+        assert_eq!(
+            schema.validate_tuple("document", "viewer", "group", "eng", "member"),
+            Ok(())
+        );
+        assert_eq!(
+            schema.validate_tuple("folder", "viewer", "user", "anya", ""),
+            Err(ValidationError::UnknownNamespace("folder".to_string()))
+        );
+        assert_eq!(
+            schema.validate_tuple("document", "editor", "user", "anya", ""),
+            Err(ValidationError::UnknownRelation(
+                "document".to_string(),
+                "editor".to_string()
+            ))
+        );
+        assert_eq!(
+            schema.validate_tuple("document", "viewer", "group", "eng", ""),
+            Err(ValidationError::SubjectNotAllowed(
+                "document".to_string(),
+                "viewer".to_string(),
+                "group:eng".to_string()
+            ))
+        );
+        assert_eq!(
+            schema.validate_tuple("document", "viewer", "user", "*", ""),
+            Err(ValidationError::SubjectNotAllowed(
+                "document".to_string(),
+                "viewer".to_string(),
+                "user:*".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn sources_work() {
+        let schema: Schema = serde_json::from_str(
+            r#"{"namespaces":{"document":{"relations":{"owner":[{"namespace":"user"}],"team":[{"namespaceAction":["team","member"]}]},"sources":{"owner":{"table":"documents","resourceId":"id","subjectId":"owner_id","subjectNamespace":"user"},"team":{"query":"SELECT document_id, team_id FROM document_teams","subjectNamespace":"team","subjectAction":"member"}}}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema.source("document", "owner").unwrap().from,
+            SourceFrom::Table {
+                table: "documents".to_string(),
+                resource_id: "id".to_string(),
+                subject_id: "owner_id".to_string()
+            }
+        );
+        assert_eq!(
+            schema.source("document", "team").unwrap().from,
+            SourceFrom::Query {
+                query: "SELECT document_id, team_id FROM document_teams".to_string()
+            }
+        );
+        assert_eq!(schema.source("document", "viewer"), None);
+        assert_eq!(schema.validate_sources(), Ok(()));
+
+        let schema: Schema = serde_json::from_str(
+            r#"{"namespaces":{"document":{"sources":{"owner":{"query":"SELECT 1, 2","subjectNamespace":"user"}}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schema.validate_sources(),
+            Err(ValidationError::UnknownRelation(
+                "document".to_string(),
+                "owner".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn allows_wildcard_works() {
+        let schema: Schema = serde_json::from_str(
+            r#"{"namespaces":{"document":{"relations":{"viewer":[{"namespace":"user"},{"namespaceWildcard":"user"}],"editor":[{"namespace":"user"}]}}}}"#,
+        )
+        .unwrap();
+
+        assert!(schema.allows_wildcard("document", "viewer", "user"));
+        assert!(!schema.allows_wildcard("document", "viewer", "group"));
+        assert!(!schema.allows_wildcard("document", "editor", "user"));
+        assert!(!schema.allows_wildcard("document", "owner", "user"));
+        assert!(!schema.allows_wildcard("folder", "viewer", "user"));
+    }
+}
@@ -0,0 +1,576 @@
+//! Import SpiceDB schemas, written in its schema language (`.zed`), and
+//! relationships, written as `document:1#viewer@user:anya`.
+//!
+//! Definitions become namespaces, relations become relations, and
+//! permissions become permissions, with `+`, `&`, `-` and `->` as unions,
+//! intersections, exclusions and tuple to usersets. As in SpiceDB, `-` binds
+//! loosest and `+` tightest, so `a + b - c & d` is `(a + b) - (c & d)`.
+//! Expiring relationships (`with expiration`) are supported. Caveats, `nil`,
+//! and `.all()` arrows are reported rather than dropped.
+
+use crate::schema::{ImportError, Namespace, Rewrite, Schema, TypeRestriction};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Punct(&'static str),
+
+    /// Anything else, which is only valid in the body of a caveat.
+    Other(char),
+}
+
+const PUNCTS: [&str; 15] = [
+    "->", "{", "}", "(", ")", ":", "|", "#", "*", "=", "+", "&", "-", ",", ".",
+];
+
+fn tokenize(schema: &str) -> Result<Vec<(usize, Token)>, ImportError> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut rest = schema;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\n' {
+            line += 1;
+            rest = &rest[1..];
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if rest.starts_with("/*") {
+            let end = rest
+                .find("*/")
+                .ok_or_else(|| ImportError::Syntax(line, "unterminated comment".to_string()))?;
+            line += rest[..end].matches('\n').count();
+            rest = &rest[end + 2..];
+        } else if let Some(punct) = PUNCTS.iter().find(|punct| rest.starts_with(*punct)) {
+            tokens.push((line, Token::Punct(punct)));
+            rest = &rest[punct.len()..];
+        } else if c.is_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '/'))
+                .unwrap_or(rest.len());
+            tokens.push((line, Token::Name(rest[..end].to_string())));
+            rest = &rest[end..];
+        } else {
+            tokens.push((line, Token::Other(c)));
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    unsupported: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |(line, _)| *line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ImportError> {
+        Err(ImportError::Syntax(self.line(), message.into()))
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(Token::Name(name)) => format!("'{name}'"),
+            Some(Token::Punct(punct)) => format!("'{punct}'"),
+            Some(Token::Other(c)) => format!("'{c}'"),
+            None => "end of schema".to_string(),
+        }
+    }
+
+    fn punct(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), ImportError> {
+        if !self.punct(punct) {
+            let found = self.found();
+            return self.error(format!("expected '{punct}', found {found}"));
+        }
+        Ok(())
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(name)) if name == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self, what: &str) -> Result<String, ImportError> {
+        match self.peek() {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => {
+                let found = self.found();
+                self.error(format!("expected {what}, found {found}"))
+            }
+        }
+    }
+
+    fn schema(&mut self) -> Result<Schema, ImportError> {
+        let mut namespaces = HashMap::new();
+
+        while self.peek().is_some() {
+            if self.keyword("definition") {
+                let line = self.line();
+                let name = self.name("a definition name")?;
+                let namespace = self.definition(&name)?;
+                if namespaces.insert(name.clone(), namespace).is_some() {
+                    return Err(ImportError::Syntax(
+                        line,
+                        format!("definition '{name}' is defined more than once"),
+                    ));
+                }
+            } else if self.keyword("caveat") {
+                let name = self.name("a caveat name")?;
+                self.unsupported.push(format!("caveat '{name}'"));
+                self.skip_block()?;
+            } else if self.keyword("use") {
+                // Only `use expiration` exists, and every tuple can expire.
+                self.name("a feature")?;
+            } else {
+                let found = self.found();
+                return self.error(format!("expected a definition, found {found}"));
+            }
+        }
+
+        Ok(Schema {
+            namespaces,
+            conditions: HashMap::new(),
+        })
+    }
+
+    /// Skip the parameters and body of a caveat.
+    fn skip_block(&mut self) -> Result<(), ImportError> {
+        while !self.punct("{") {
+            if self.peek().is_none() {
+                return self.error("expected '{', found end of schema");
+            }
+            self.pos += 1;
+        }
+
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek() {
+                Some(Token::Punct("{")) => depth += 1,
+                Some(Token::Punct("}")) => depth -= 1,
+                None => return self.error("expected '}', found end of schema"),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+
+        Ok(())
+    }
+
+    fn definition(&mut self, ns: &str) -> Result<Namespace, ImportError> {
+        let mut namespace = Namespace {
+            relations: HashMap::new(),
+            permissions: HashMap::new(),
+            sources: HashMap::new(),
+        };
+
+        self.expect_punct("{")?;
+
+        while !self.punct("}") {
+            let is_relation = self.keyword("relation");
+            if !is_relation && !self.keyword("permission") {
+                let found = self.found();
+                return self.error(format!(
+                    "expected 'relation', 'permission' or '}}', found {found}"
+                ));
+            }
+
+            let line = self.line();
+            let name = self.name("a relation or permission name")?;
+            if namespace.relations.contains_key(&name) || namespace.permissions.contains_key(&name)
+            {
+                return Err(ImportError::Syntax(
+                    line,
+                    format!("'{name}' is defined more than once in '{ns}'"),
+                ));
+            }
+
+            if is_relation {
+                self.expect_punct(":")?;
+
+                let mut restrictions = vec![self.restriction(ns, &name)?];
+                while self.punct("|") {
+                    restrictions.push(self.restriction(ns, &name)?);
+                }
+                namespace.relations.insert(name, restrictions);
+            } else {
+                self.expect_punct("=")?;
+
+                let rewrite = self.exclusion(ns, &name)?;
+                namespace.permissions.insert(name, rewrite);
+            }
+        }
+
+        Ok(namespace)
+    }
+
+    /// `type`, `type:*` or `type#relation`, optionally `with` traits.
+    fn restriction(&mut self, ns: &str, relation: &str) -> Result<TypeRestriction, ImportError> {
+        let name = self.name("a type")?;
+
+        let restriction = if self.punct(":") {
+            self.expect_punct("*")?;
+            TypeRestriction::NamespaceWildcard(name)
+        } else if self.punct("#") {
+            TypeRestriction::NamespaceAction(name, self.name("a relation")?)
+        } else {
+            TypeRestriction::Namespace(name)
+        };
+
+        if self.keyword("with") {
+            loop {
+                let name = self.name("a caveat or 'expiration'")?;
+                if name != "expiration" {
+                    self.unsupported
+                        .push(format!("caveat '{name}' on {ns}#{relation}"));
+                }
+                if !self.keyword("and") {
+                    break;
+                }
+            }
+        }
+
+        Ok(restriction)
+    }
+
+    fn exclusion(&mut self, ns: &str, permission: &str) -> Result<Rewrite, ImportError> {
+        let mut rewrite = self.intersection(ns, permission)?;
+        while self.punct("-") {
+            let subtrahend = self.intersection(ns, permission)?;
+            rewrite = Rewrite::Exclusion(Box::new(rewrite), Box::new(subtrahend));
+        }
+        Ok(rewrite)
+    }
+
+    fn intersection(&mut self, ns: &str, permission: &str) -> Result<Rewrite, ImportError> {
+        let mut rewrites = vec![self.union(ns, permission)?];
+        while self.punct("&") {
+            rewrites.push(self.union(ns, permission)?);
+        }
+        Ok(if rewrites.len() == 1 {
+            rewrites.remove(0)
+        } else {
+            Rewrite::Intersection(rewrites)
+        })
+    }
+
+    fn union(&mut self, ns: &str, permission: &str) -> Result<Rewrite, ImportError> {
+        let mut rewrites = vec![self.arrow(ns, permission)?];
+        while self.punct("+") {
+            rewrites.push(self.arrow(ns, permission)?);
+        }
+        Ok(if rewrites.len() == 1 {
+            rewrites.remove(0)
+        } else {
+            Rewrite::Union(rewrites)
+        })
+    }
+
+    /// `relation`, `relation->permission`, `relation.any(permission)`, or a
+    /// parenthesized expression.
+    fn arrow(&mut self, ns: &str, permission: &str) -> Result<Rewrite, ImportError> {
+        if self.punct("(") {
+            let rewrite = self.exclusion(ns, permission)?;
+            self.expect_punct(")")?;
+            return Ok(rewrite);
+        }
+
+        let relation = self.name("a relation or permission")?;
+        if relation == "nil" {
+            self.unsupported.push(format!("nil in {ns}#{permission}"));
+        }
+
+        if self.punct("->") {
+            let computed = self.name("a relation or permission")?;
+            return Ok(Rewrite::TupleToUserset(relation, computed));
+        }
+
+        if self.punct(".") {
+            let function = self.name("'any' or 'all'")?;
+            if function != "any" {
+                self.unsupported
+                    .push(format!("{function}() in {ns}#{permission}"));
+            }
+            self.expect_punct("(")?;
+            let computed = self.name("a relation or permission")?;
+            self.expect_punct(")")?;
+            return Ok(Rewrite::TupleToUserset(relation, computed));
+        }
+
+        Ok(Rewrite::ComputedUserset(relation))
+    }
+}
+
+/// Import a schema written in SpiceDB's schema language.
+pub fn import_schema(schema: &str) -> Result<Schema, ImportError> {
+    let mut parser = Parser {
+        tokens: tokenize(schema)?,
+        pos: 0,
+        unsupported: vec![],
+    };
+
+    let schema = parser.schema()?;
+
+    if !parser.unsupported.is_empty() {
+        return Err(ImportError::Unsupported(parser.unsupported));
+    }
+
+    Ok(schema)
+}
+
+/// A relationship of a relationship dump, as written by SpiceDB.
+#[derive(Debug, PartialEq)]
+pub struct Relationship<'a> {
+    pub line: usize,
+
+    /// The relationship itself, e.g. `document:1#viewer@user:anya`.
+    pub tuple: &'a str,
+
+    /// When the relationship expires, if it does, as an RFC 3339 timestamp.
+    pub expiration: Option<&'a str>,
+}
+
+/// Split a relationship dump into its relationships, one per line, skipping
+/// blank lines and `//` comments. A relationship may be followed by
+/// `[expiration:<timestamp>]`.
+pub fn parse_relationships(relationships: &str) -> Result<Vec<Relationship<'_>>, ImportError> {
+    let mut parsed = vec![];
+    let mut unsupported = vec![];
+
+    for (i, text) in relationships.lines().enumerate() {
+        let line = i + 1;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with("//") {
+            continue;
+        }
+
+        let (tuple, expiration) = match text.split_once('[') {
+            None => (text, None),
+            Some((tuple, trait_)) => {
+                let trait_ = trait_.strip_suffix(']').ok_or_else(|| {
+                    ImportError::Syntax(line, "expected ']' at the end of the line".to_string())
+                })?;
+
+                match trait_.split_once(':') {
+                    Some(("expiration", timestamp)) => (tuple, Some(timestamp.trim())),
+                    _ => {
+                        unsupported.push(format!("caveat on line {line}"));
+                        (tuple, None)
+                    }
+                }
+            }
+        };
+
+        parsed.push(Relationship {
+            line,
+            tuple: tuple.trim(),
+            expiration,
+        });
+    }
+
+    if !unsupported.is_empty() {
+        return Err(ImportError::Unsupported(unsupported));
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn import_schema_works() {
+        let schema = "
+            use expiration
+
+            /** A user. */
+            definition user {}
+
+            definition group {
+                relation member: user | group#member
+            }
+
+            definition document {
+                // Where the document is filed.
+                relation parent: folder
+                relation viewer: user | user:* | group#member with expiration
+                relation blocked: user
+                permission view = viewer + parent->view + parent.any(edit)
+                permission read = view - blocked
+                permission share = viewer + blocked - viewer & blocked
+            }
+
+            definition folder {
+                relation viewer: user
+                permission view = viewer
+                permission edit = view
+            }
+        ";
+
+        let expected: Schema = serde_json::from_value(json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                "document": {
+                    "relations": {
+                        "parent": [{"namespace": "folder"}],
+                        "viewer": [{"namespace": "user"}, {"namespaceWildcard": "user"}, {"namespaceAction": ["group", "member"]}],
+                        "blocked": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "view": {"union": [
+                            {"computedUserset": "viewer"},
+                            {"tupleToUserset": ["parent", "view"]},
+                            {"tupleToUserset": ["parent", "edit"]}
+                        ]},
+                        "read": {"exclusion": [{"computedUserset": "view"}, {"computedUserset": "blocked"}]},
+                        "share": {"exclusion": [
+                            {"union": [{"computedUserset": "viewer"}, {"computedUserset": "blocked"}]},
+                            {"intersection": [{"computedUserset": "viewer"}, {"computedUserset": "blocked"}]}
+                        ]}
+                    }
+                },
+                "folder": {
+                    "relations": {"viewer": [{"namespace": "user"}]},
+                    "permissions": {
+                        "view": {"computedUserset": "viewer"},
+                        "edit": {"computedUserset": "view"}
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(import_schema(schema), Ok(expected));
+    }
+
+    #[test]
+    fn unsupported_constructs_are_reported() {
+        let schema = "
+            caveat ip_allowed(ip ipaddress, cidr string) {
+                ip.in_cidr(cidr) && attempts < 3
+            }
+
+            definition document {
+                relation viewer: user with ip_allowed
+                permission view = viewer + nil
+                permission edit = viewer.all(view)
+            }
+        ";
+
+        assert_eq!(
+            import_schema(schema),
+            Err(ImportError::Unsupported(vec![
+                "caveat 'ip_allowed'".to_string(),
+                "caveat 'ip_allowed' on document#viewer".to_string(),
+                "nil in document#view".to_string(),
+                "all() in document#edit".to_string(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert_eq!(
+            import_schema("definition document {\n  relation viewer user\n}"),
+            Err(ImportError::Syntax(
+                2,
+                "expected ':', found 'user'".to_string()
+            ))
+        );
+        assert_eq!(
+            import_schema("definition document {\n  permission view = \n}"),
+            Err(ImportError::Syntax(
+                3,
+                "expected a relation or permission, found '}'".to_string()
+            ))
+        );
+        assert_eq!(
+            import_schema("definition user {}\ndefinition user {}"),
+            Err(ImportError::Syntax(
+                2,
+                "definition 'user' is defined more than once".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        assert_eq!(
+            import_schema(
+                "definition document {\n  relation viewer: user\n  permission viewer = viewer\n}"
+            ),
+            Err(ImportError::Syntax(
+                3,
+                "'viewer' is defined more than once in 'document'".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_relationships_works() {
+        let relationships = "
+// Documents
+document:1#viewer@user:anya
+document:1#viewer@group:eng#member[expiration:2030-01-01T00:00:00Z]
+";
+
+        assert_eq!(
+            parse_relationships(relationships),
+            Ok(vec![
+                Relationship {
+                    line: 3,
+                    tuple: "document:1#viewer@user:anya",
+                    expiration: None,
+                },
+                Relationship {
+                    line: 4,
+                    tuple: "document:1#viewer@group:eng#member",
+                    expiration: Some("2030-01-01T00:00:00Z"),
+                },
+            ])
+        );
+
+        assert_eq!(
+            parse_relationships(
+                "document:1#viewer@user:anya[ip_allowed:{\"cidr\":\"10.0.0.0/8\"}]"
+            ),
+            Err(ImportError::Unsupported(vec![
+                "caveat on line 1".to_string()
+            ]))
+        );
+    }
+}
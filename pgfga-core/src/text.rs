@@ -0,0 +1,343 @@
+//! The text format of objects, subjects, and tuples, the same as Zanzibar's,
+//! e.g. `document:1#viewer@user:anya`.
+
+use crate::tuple_store::Tuple;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use thiserror::Error;
+
+/// An object, e.g. `document:1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectRef {
+    pub namespace: String,
+    pub id: String,
+}
+
+/// A subject, e.g. `user:anya`, or a subject set, e.g. `group:eng#member`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubjectRef {
+    pub namespace: String,
+    pub id: String,
+    pub action: String,
+}
+
+/// A relation between an object and a subject, e.g.
+/// `document:1#viewer@user:anya`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TupleKey {
+    pub object: ObjectRef,
+    pub relation: String,
+    pub subject: SubjectRef,
+}
+
+/// An error parsing the text form of an object, subject, or tuple. The
+/// position is the 1-based character the parser stopped at.
+#[derive(Debug, Error, PartialEq)]
+#[error("invalid {kind} '{input}': {message} at character {position}")]
+pub struct ParseError {
+    pub kind: &'static str,
+    pub input: String,
+    pub position: usize,
+    pub message: String,
+}
+
+/// Characters with a meaning in the text form. They are escaped with a
+/// backslash when part of a name or id.
+const SPECIAL: [char; 4] = [':', '#', '@', '\\'];
+
+struct Parser<'a> {
+    kind: &'static str,
+    input: &'a str,
+    chars: Peekable<Chars<'a>>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(kind: &'static str, input: &'a str) -> Self {
+        Parser {
+            kind,
+            input,
+            chars: input.chars().peekable(),
+            position: 1,
+        }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            kind: self.kind,
+            input: self.input.to_string(),
+            position: self.position,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c.is_some() {
+            self.position += 1;
+        }
+        c
+    }
+
+    /// Read up to the next unescaped special character.
+    fn field(&mut self, what: &str) -> Result<String, ParseError> {
+        let mut field = String::new();
+
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '\\' => {
+                    self.next();
+                    match self.next() {
+                        Some(c) => field.push(c),
+                        None => {
+                            self.position -= 1;
+                            return Err(self.error("unterminated escape".to_string()));
+                        }
+                    }
+                }
+                ':' | '#' | '@' => break,
+                c => {
+                    self.next();
+                    field.push(c);
+                }
+            }
+        }
+
+        if field.is_empty() {
+            let found = self.found();
+            return Err(self.error(format!("expected {what}, found {found}")));
+        }
+
+        Ok(field)
+    }
+
+    fn found(&mut self) -> String {
+        match self.chars.peek() {
+            Some(c) => format!("'{c}'"),
+            None => "end of input".to_string(),
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            let found = self.found();
+            Err(self.error(format!("expected '{c}', found {found}")))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.chars.peek().copied() {
+            None => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{c}'"))),
+        }
+    }
+
+    fn object(&mut self) -> Result<ObjectRef, ParseError> {
+        let namespace = self.field("a namespace")?;
+        self.expect(':')?;
+        let id = self.field("an id")?;
+
+        Ok(ObjectRef { namespace, id })
+    }
+
+    fn subject(&mut self) -> Result<SubjectRef, ParseError> {
+        let object = self.object()?;
+        let action = if self.eat('#') {
+            self.field("an action")?
+        } else {
+            String::new()
+        };
+
+        Ok(SubjectRef {
+            namespace: object.namespace,
+            id: object.id,
+            action,
+        })
+    }
+
+    fn tuple(&mut self) -> Result<TupleKey, ParseError> {
+        let object = self.object()?;
+        self.expect('#')?;
+        let relation = self.field("a relation")?;
+        self.expect('@')?;
+        let subject = self.subject()?;
+
+        Ok(TupleKey {
+            object,
+            relation,
+            subject,
+        })
+    }
+}
+
+impl ObjectRef {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new("object", input);
+        let object = parser.object()?;
+        parser.end()?;
+        Ok(object)
+    }
+}
+
+impl SubjectRef {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new("subject", input);
+        let subject = parser.subject()?;
+        parser.end()?;
+        Ok(subject)
+    }
+}
+
+impl TupleKey {
+    /// Parse a tuple of the form `namespace:id#relation@namespace:id#action`,
+    /// where the subject's `#action` is optional.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new("tuple", input);
+        let tuple = parser.tuple()?;
+        parser.end()?;
+        Ok(tuple)
+    }
+}
+
+impl From<TupleKey> for Tuple {
+    fn from(key: TupleKey) -> Self {
+        Tuple {
+            resource_namespace: key.object.namespace,
+            resource_id: key.object.id,
+            relation: key.relation,
+            subject_namespace: key.subject.namespace,
+            subject_id: key.subject.id,
+            subject_action: key.subject.action,
+            ..Default::default()
+        }
+    }
+}
+
+/// Escape the special characters of a name or id.
+fn escape(f: &mut fmt::Formatter<'_>, field: &str) -> fmt::Result {
+    for c in field.chars() {
+        if SPECIAL.contains(&c) {
+            write!(f, "\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        escape(f, &self.namespace)?;
+        write!(f, ":")?;
+        escape(f, &self.id)
+    }
+}
+
+impl fmt::Display for SubjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        escape(f, &self.namespace)?;
+        write!(f, ":")?;
+        escape(f, &self.id)?;
+        if !self.action.is_empty() {
+            write!(f, "#")?;
+            escape(f, &self.action)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TupleKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#", self.object)?;
+        escape(f, &self.relation)?;
+        write!(f, "@{}", self.subject)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_then_format_works() {
+        for input in [
+            "document:1#viewer@user:anya",
+            "document:1#viewer@group:eng#member",
+            "document:1#viewer@user:*",
+        ] {
+            assert_eq!(TupleKey::parse(input).unwrap().to_string(), input);
+        }
+
+        assert_eq!(
+            TupleKey::parse("document:1#viewer@group:eng#member").unwrap(),
+            TupleKey {
+                object: ObjectRef {
+                    namespace: "document".to_string(),
+                    id: "1".to_string(),
+                },
+                relation: "viewer".to_string(),
+                subject: SubjectRef {
+                    namespace: "group".to_string(),
+                    id: "eng".to_string(),
+                    action: "member".to_string(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn escaping_works() {
+        let tuple = TupleKey::parse(r"url:https\://example.com/a\#b#viewer@user:a\@b\\c").unwrap();
+        assert_eq!(tuple.object.id, "https://example.com/a#b");
+        assert_eq!(tuple.subject.id, r"a@b\c");
+        assert_eq!(
+            tuple.to_string(),
+            r"url:https\://example.com/a\#b#viewer@user:a\@b\\c"
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_input() {
+        let error = |input| TupleKey::parse(input).unwrap_err();
+
+        assert_eq!(
+            error("document:1@user:anya"),
+            ParseError {
+                kind: "tuple",
+                input: "document:1@user:anya".to_string(),
+                position: 11,
+                message: "expected '#', found '@'".to_string(),
+            }
+        );
+        assert_eq!(
+            error("document:1#@user:anya").message,
+            "expected a relation, found '@'"
+        );
+        assert_eq!(
+            error("document:1#viewer").message,
+            "expected '@', found end of input"
+        );
+        assert_eq!(error("document:1#viewer").position, 18);
+        assert_eq!(error("document:1#viewer@user:anya:x").position, 28);
+        assert_eq!(error(r"document:1#viewer@user:anya\").position, 28);
+
+        assert!(ObjectRef::parse("document").is_err());
+        assert!(ObjectRef::parse(":1").is_err());
+        assert!(SubjectRef::parse("group:eng#").is_err());
+        assert_eq!(
+            SubjectRef::parse("group:eng#").unwrap_err().to_string(),
+            "invalid subject 'group:eng#': expected an action, found end of input at character 11"
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Where checks read tuples from. The extension reads the tuples of a schema
+//! from `pgfga.tuple`, and `MemoryStore` keeps them in memory, so that the
+//! check algorithm can be tested, benchmarked, and run without Postgres.
+
+use crate::error::CheckError;
+use crate::schema::RelationSource;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::time::SystemTime;
+
+/// A tuple as checks see it. A store only returns tuples that haven't
+/// expired, so their expiry doesn't matter here.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Tuple {
+    pub resource_namespace: String,
+    pub resource_id: String,
+    pub relation: String,
+    pub subject_namespace: String,
+    pub subject_id: String,
+
+    #[serde(default)]
+    pub subject_action: String,
+
+    #[serde(default)]
+    pub condition: String,
+
+    #[serde(default)]
+    pub condition_context: Option<Value>,
+}
+
+/// The tuples of a single schema.
+pub trait TupleStore {
+    /// Errors reading from or writing to the store. Errors checking the
+    /// tuples are wrapped in them too.
+    type Error: From<CheckError>;
+
+    /// The live tuple with exactly this key, if there is one.
+    fn read_tuple(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<Option<Tuple>, Self::Error>;
+
+    /// The live tuples filtered by every field that isn't empty.
+    fn read_tuples(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<Vec<Tuple>, Self::Error>;
+
+    /// The live tuples of the relation of the resource, whatever their
+    /// subject.
+    fn read_subjectset_tuples(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+    ) -> Result<Vec<Tuple>, Self::Error>;
+
+    /// Write a tuple, replacing it if it has expired. Returns the number of
+    /// tuples written.
+    fn create_tuple(
+        &mut self,
+        tuple: &Tuple,
+        expires_at: Option<SystemTime>,
+    ) -> Result<i64, Self::Error>;
+
+    /// Delete the tuple with the key of `tuple`. Returns the number of tuples
+    /// deleted.
+    fn delete_tuple(&mut self, tuple: &Tuple) -> Result<i64, Self::Error>;
+
+    /// The relations whose transitive members are kept by the store. Checks
+    /// fall back to walking the tuples, so a store may keep none.
+    fn read_closure_relations(&self) -> Result<HashSet<(String, String)>, Self::Error> {
+        Ok(HashSet::new())
+    }
+
+    /// Whether the subject is a transitive member of the group, if
+    /// `read_closure_relations` returned its relation.
+    fn in_group_closure(
+        &self,
+        _group_namespace: &str,
+        _group_id: &str,
+        _relation: &str,
+        _subject_namespace: &str,
+        _subject_id: &str,
+        _subject_action: &str,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// Whether the source has a row from the resource to the subject. Stores
+    /// that can't read application data have no rows in sources.
+    fn in_source(
+        &self,
+        _source: &RelationSource,
+        _resource_id: &str,
+        _subject_id: &str,
+    ) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    /// The rows of the source from the resource, as tuples of `relation`.
+    fn read_source_tuples(
+        &self,
+        _resource_namespace: &str,
+        _resource_id: &str,
+        _relation: &str,
+        _source: &RelationSource,
+    ) -> Result<Vec<Tuple>, Self::Error> {
+        Ok(vec![])
+    }
+}
+
+/// The primary key of a tuple.
+type TupleKey = (String, String, String, String, String, String);
+
+/// Tuples kept in memory, in the order of their keys.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tuples: BTreeMap<TupleKey, (Tuple, Option<SystemTime>)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tuples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tuples.is_empty()
+    }
+
+    /// The live tuples of the relation of the resource, in the order of their
+    /// subjects.
+    fn read_relation<'a>(
+        &'a self,
+        resource_namespace: &'a str,
+        resource_id: &'a str,
+        relation: &'a str,
+    ) -> impl Iterator<Item = &'a Tuple> + 'a {
+        let start = key(resource_namespace, resource_id, relation, "", "", "");
+
+        self.tuples
+            .range(start..)
+            .take_while(move |(_, (tuple, _))| {
+                tuple.resource_namespace == resource_namespace
+                    && tuple.resource_id == resource_id
+                    && tuple.relation == relation
+            })
+            .filter(|(_, (_, expires_at))| is_live(*expires_at))
+            .map(|(_, (tuple, _))| tuple)
+    }
+}
+
+impl TupleStore for MemoryStore {
+    type Error = CheckError;
+
+    fn read_tuple(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<Option<Tuple>, CheckError> {
+        let key = key(
+            resource_namespace,
+            resource_id,
+            relation,
+            subject_namespace,
+            subject_id,
+            subject_action,
+        );
+
+        Ok(self
+            .tuples
+            .get(&key)
+            .filter(|(_, expires_at)| is_live(*expires_at))
+            .map(|(tuple, _)| tuple.clone()))
+    }
+
+    fn read_tuples(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<Vec<Tuple>, CheckError> {
+        let matches = |filter: &str, value: &str| filter.is_empty() || filter == value;
+        let matches_subject = |tuple: &&Tuple| {
+            matches(subject_namespace, &tuple.subject_namespace)
+                && matches(subject_id, &tuple.subject_id)
+                && matches(subject_action, &tuple.subject_action)
+        };
+
+        // Checks always read the tuples of a relation of a resource, which
+        // are next to each other.
+        if !resource_namespace.is_empty() && !resource_id.is_empty() && !relation.is_empty() {
+            return Ok(self
+                .read_relation(resource_namespace, resource_id, relation)
+                .filter(matches_subject)
+                .cloned()
+                .collect());
+        }
+
+        Ok(self
+            .tuples
+            .values()
+            .filter(|(tuple, expires_at)| {
+                matches(resource_namespace, &tuple.resource_namespace)
+                    && matches(resource_id, &tuple.resource_id)
+                    && matches(relation, &tuple.relation)
+                    && is_live(*expires_at)
+            })
+            .map(|(tuple, _)| tuple)
+            .filter(matches_subject)
+            .cloned()
+            .collect())
+    }
+
+    fn read_subjectset_tuples(
+        &self,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+    ) -> Result<Vec<Tuple>, CheckError> {
+        self.read_tuples(resource_namespace, resource_id, relation, "", "", "")
+    }
+
+    fn create_tuple(
+        &mut self,
+        tuple: &Tuple,
+        expires_at: Option<SystemTime>,
+    ) -> Result<i64, CheckError> {
+        let key = tuple_key(tuple);
+
+        // Like `ON CONFLICT ... DO UPDATE ... WHERE expires_at <= NOW()`, an
+        // existing tuple is only replaced once it has expired.
+        if let Some((_, existing)) = self.tuples.get(&key) {
+            if is_live(*existing) {
+                return Ok(0);
+            }
+        }
+
+        self.tuples.insert(key, (tuple.clone(), expires_at));
+
+        Ok(1)
+    }
+
+    fn delete_tuple(&mut self, tuple: &Tuple) -> Result<i64, CheckError> {
+        Ok(self.tuples.remove(&tuple_key(tuple)).map_or(0, |_| 1))
+    }
+}
+
+fn key(
+    resource_namespace: &str,
+    resource_id: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: &str,
+) -> TupleKey {
+    (
+        resource_namespace.to_string(),
+        resource_id.to_string(),
+        relation.to_string(),
+        subject_namespace.to_string(),
+        subject_id.to_string(),
+        subject_action.to_string(),
+    )
+}
+
+fn tuple_key(tuple: &Tuple) -> TupleKey {
+    key(
+        &tuple.resource_namespace,
+        &tuple.resource_id,
+        &tuple.relation,
+        &tuple.subject_namespace,
+        &tuple.subject_id,
+        &tuple.subject_action,
+    )
+}
+
+fn is_live(expires_at: Option<SystemTime>) -> bool {
+    expires_at.is_none_or(|expires_at| expires_at > SystemTime::now())
+}
@@ -1,378 +1,242 @@
+//! Checks of the tuples in `pgfga.tuple`. The checks themselves are made by
+//! `pgfga_core`, so that they behave exactly the same outside of Postgres.
+
+use crate::compile::compile;
 use crate::error::PgFgaError;
-use crate::schema::{Rewrite, Schema};
+use crate::schema::{RelationSource, Schema};
 use crate::storage::Storage;
+use pgfga_core::{Tuple, TupleStore};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use pgfga_core::check::{parse_conditions, Checker, MAX_DEPTH};
 
-pub const MAX_DEPTH: i64 = 25;
+/// Microseconds between the Unix epoch and the Postgres one, 2000-01-01.
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
 
-pub struct Checker<'a> {
+/// The tuples of a schema in `pgfga.tuple`.
+pub struct SchemaTuples<'a> {
     storage: Storage<'a>,
     schema_id: pgrx::Uuid,
-    schema: Schema,
 }
 
-impl<'a> Checker<'a> {
-    pub fn new(storage: Storage<'a>, schema_id: pgrx::Uuid) -> Result<Self, PgFgaError> {
-        let schema = storage
-            .read_schemas(Some(schema_id))?
-            .pop()
-            .ok_or_else(|| PgFgaError::UnknownSchemaId(schema_id))?
-            .try_into()?;
-
-        Ok(Checker {
-            storage,
-            schema_id,
-            schema,
-        })
-    }
-
-    pub fn check(
-        &self,
-        resource_namespace: &str,
-        resource_id: &str,
-        action: &str,
-        subject_namespace: &str,
-        subject_id: &str,
-        subject_action: &str,
-    ) -> Result<bool, PgFgaError> {
-        self.check_with_depth(
-            resource_namespace,
-            resource_id,
-            action,
-            subject_namespace,
-            subject_id,
-            subject_action,
-            0,
-        )
+/// A checker of the schema with the given id, which must not be archived.
+pub fn checker(
+    storage: Storage<'_>,
+    schema_id: pgrx::Uuid,
+    context: Map<String, Value>,
+) -> Result<Checker<SchemaTuples<'_>>, PgFgaError> {
+    let schema_row = storage
+        .read_schemas(Some(schema_id))?
+        .pop()
+        .ok_or_else(|| PgFgaError::UnknownSchemaId(schema_id))?;
+
+    if schema_row.archived {
+        return Err(PgFgaError::SchemaArchived(schema_id));
     }
 
-    fn check_with_depth(
-        &self,
-        resource_namespace: &str,
-        resource_id: &str,
-        action: &str,
-        subject_namespace: &str,
-        subject_id: &str,
-        subject_action: &str,
-        depth: i64,
-    ) -> Result<bool, PgFgaError> {
-        if depth == MAX_DEPTH {
-            return Err(PgFgaError::MaxDepth);
-        }
+    let schema: Schema = schema_row.try_into()?;
 
-        if self.schema.is_relation(resource_namespace, action) {
-            // If the action is a relation we can attempt a direct check.
-            if self
-                .storage
-                .read_tuple(
-                    self.schema_id,
-                    resource_namespace,
-                    resource_id,
-                    action,
-                    subject_namespace,
-                    subject_id,
-                    subject_action,
-                )?
-                .is_some()
-            {
-                // We found it.
-                return Ok(true);
-            }
+    Checker::new(SchemaTuples { storage, schema_id }, schema, context)
+}
 
-            // Now let's take a look at subject sets.
-            let tuples = self.storage.read_subjectset_tuples(
-                self.schema_id,
+/// The ids of the resources of `resource_namespace` the subject has `action`
+/// on, found with a single query compiled from the schema. If the query can
+/// only narrow them down, each of them is checked too.
+pub fn accessible_resources(
+    checker: &Checker<SchemaTuples<'_>>,
+    resource_namespace: &str,
+    action: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: &str,
+) -> Result<Vec<String>, PgFgaError> {
+    let tuples = checker.store();
+    let compiled = compile(checker.schema(), resource_namespace, action, |source| {
+        tuples.storage.source_sql(source)
+    })?;
+
+    let mut ids = tuples.storage.read_compiled(
+        &compiled,
+        tuples.schema_id,
+        subject_namespace,
+        subject_id,
+        subject_action,
+    )?;
+
+    if !compiled.exact {
+        let mut accessible = vec![];
+        for id in ids {
+            if checker.check(
                 resource_namespace,
-                resource_id,
+                &id,
                 action,
-            )?;
-
-            for tuple in tuples {
-                let result = self.check_with_depth(
-                    &tuple.subject_namespace,
-                    &tuple.subject_id,
-                    &tuple.subject_action,
-                    &tuple.subject_namespace,
-                    subject_id,
-                    subject_action,
-                    depth + 1,
-                );
-
-                // We want to ignore errors here.
-                if let Ok(true) = result {
-                    return Ok(true);
-                }
-            }
-        }
-
-        // Now let's take a look at permissions
-        let rw = self
-            .schema
-            .namespaces
-            .get(resource_namespace)
-            .and_then(|ns| ns.permissions.get(action));
-
-        if let Some(rewrite) = rw {
-            return self.check_rewrite(
-                resource_namespace,
-                resource_id,
                 subject_namespace,
                 subject_id,
                 subject_action,
-                rewrite,
-                depth,
-            );
+            )? {
+                accessible.push(id);
+            }
         }
-
-        Ok(false)
+        ids = accessible;
     }
 
-    fn check_rewrite(
+    Ok(ids)
+}
+
+impl TupleStore for SchemaTuples<'_> {
+    type Error = PgFgaError;
+
+    fn read_tuple(
         &self,
         resource_namespace: &str,
         resource_id: &str,
+        relation: &str,
         subject_namespace: &str,
         subject_id: &str,
         subject_action: &str,
-        rewrite: &Rewrite,
-        depth: i64,
-    ) -> Result<bool, PgFgaError> {
-        if depth == MAX_DEPTH {
-            return Err(PgFgaError::MaxDepth);
-        }
+    ) -> Result<Option<Tuple>, PgFgaError> {
+        let tuple = self.storage.read_tuple(
+            self.schema_id,
+            resource_namespace,
+            resource_id,
+            relation,
+            subject_namespace,
+            subject_id,
+            subject_action,
+        )?;
 
-        match rewrite {
-            Rewrite::ComputedUserset(computed_userset) => self.check_cu(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                computed_userset,
-                depth,
-            ),
-            Rewrite::TupleToUserset(tupleset, computed_userset) => self.check_ttu(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                tupleset,
-                computed_userset,
-                depth,
-            ),
-            Rewrite::Union(rewrites) => self.check_union(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                rewrites,
-                depth,
-            ),
-            Rewrite::Intersection(rewrites) => self.check_intersection(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                rewrites,
-                depth,
-            ),
-            Rewrite::Exclusion(minuend, subtrahend) => self.check_exclusion(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                minuend,
-                subtrahend,
-                depth,
-            ),
-        }
+        Ok(tuple.map(Tuple::from))
     }
 
-    fn check_cu(
+    fn read_tuples(
         &self,
         resource_namespace: &str,
         resource_id: &str,
+        relation: &str,
         subject_namespace: &str,
         subject_id: &str,
         subject_action: &str,
-        computed_userset: &str,
-        depth: i64,
-    ) -> Result<bool, PgFgaError> {
-        self.check_with_depth(
+    ) -> Result<Vec<Tuple>, PgFgaError> {
+        let tuples = self.storage.read_tuples(
+            self.schema_id,
             resource_namespace,
             resource_id,
-            computed_userset,
+            relation,
             subject_namespace,
             subject_id,
             subject_action,
-            depth,
-        )
+        )?;
+
+        Ok(tuples.into_iter().map(Tuple::from).collect())
     }
 
-    fn check_ttu(
+    fn read_subjectset_tuples(
         &self,
         resource_namespace: &str,
         resource_id: &str,
-        subject_namespace: &str,
-        subject_id: &str,
-        subject_action: &str,
-        tupleset: &str,
-        computed_userset: &str,
-        depth: i64,
-    ) -> Result<bool, PgFgaError> {
-        let tuples = self.storage.read_tuples(
+        relation: &str,
+    ) -> Result<Vec<Tuple>, PgFgaError> {
+        let tuples = self.storage.read_subjectset_tuples(
             self.schema_id,
             resource_namespace,
             resource_id,
-            tupleset,
-            "",
-            "",
-            "",
+            relation,
         )?;
 
-        for tuple in tuples {
-            let new_resource_namespace = tuple.subject_namespace.as_str();
-            let new_resource_id = tuple.subject_id.as_str();
-            let new_resource_action = tuple.subject_action.as_str();
-
-            // If computed_userset is not actually a relation or permission
-            // in new_resource_namespace then there is nothing to do so
-            // skip it.
-            if !self
-                .schema
-                .is_relation(new_resource_namespace, computed_userset)
-                && !self
-                    .schema
-                    .is_permission(new_resource_namespace, computed_userset)
-            {
-                continue;
-            }
-
-            // If the subject has a nonempty relation (new_resource_action)
-            // which does not match the computed_userset then skip it.
-            if !new_resource_action.is_empty() && new_resource_action != computed_userset {
-                continue;
-            }
+        Ok(tuples.into_iter().map(Tuple::from).collect())
+    }
 
-            let result = self.check_with_depth(
-                new_resource_namespace,
-                new_resource_id,
-                computed_userset,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                depth + 1,
-            );
+    fn create_tuple(
+        &mut self,
+        tuple: &Tuple,
+        expires_at: Option<SystemTime>,
+    ) -> Result<i64, PgFgaError> {
+        self.storage.create_tuple(
+            self.schema_id,
+            &tuple.resource_namespace,
+            &tuple.resource_id,
+            &tuple.relation,
+            &tuple.subject_namespace,
+            &tuple.subject_id,
+            &tuple.subject_action,
+            expires_at.map(timestamp).transpose()?,
+            &tuple.condition,
+            tuple.condition_context.clone().map(pgrx::JsonB),
+        )
+    }
 
-            if let Ok(true) = result {
-                return Ok(true);
-            }
-        }
+    fn delete_tuple(&mut self, tuple: &Tuple) -> Result<i64, PgFgaError> {
+        self.storage.delete_tuple(
+            self.schema_id,
+            &tuple.resource_namespace,
+            &tuple.resource_id,
+            &tuple.relation,
+            &tuple.subject_namespace,
+            &tuple.subject_id,
+            &tuple.subject_action,
+        )
+    }
 
-        Ok(false)
+    fn read_closure_relations(&self) -> Result<HashSet<(String, String)>, PgFgaError> {
+        self.storage.read_closure_relations(self.schema_id)
     }
 
-    fn check_union(
+    fn in_group_closure(
         &self,
-        resource_namespace: &str,
-        resource_id: &str,
+        group_namespace: &str,
+        group_id: &str,
+        relation: &str,
         subject_namespace: &str,
         subject_id: &str,
         subject_action: &str,
-        rewrites: &Vec<Rewrite>,
-        depth: i64,
     ) -> Result<bool, PgFgaError> {
-        for rewrite in rewrites {
-            let result = self.check_rewrite(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                rewrite,
-                depth + 1,
-            );
-
-            if let Ok(true) = result {
-                return Ok(true);
-            }
-        }
-
-        Ok(false)
+        self.storage.in_group_closure(
+            self.schema_id,
+            group_namespace,
+            group_id,
+            relation,
+            subject_namespace,
+            subject_id,
+            subject_action,
+        )
     }
 
-    fn check_intersection(
+    fn in_source(
         &self,
-        resource_namespace: &str,
+        source: &RelationSource,
         resource_id: &str,
-        subject_namespace: &str,
         subject_id: &str,
-        subject_action: &str,
-        rewrites: &Vec<Rewrite>,
-        depth: i64,
     ) -> Result<bool, PgFgaError> {
-        for rewrite in rewrites {
-            let result = self.check_rewrite(
-                resource_namespace,
-                resource_id,
-                subject_namespace,
-                subject_id,
-                subject_action,
-                rewrite,
-                depth + 1,
-            );
-
-            if let Ok(false) = result {
-                return Ok(false);
-            }
-        }
-
-        Ok(true)
+        self.storage.in_source(source, resource_id, subject_id)
     }
 
-    fn check_exclusion(
+    fn read_source_tuples(
         &self,
         resource_namespace: &str,
         resource_id: &str,
-        subject_namespace: &str,
-        subject_id: &str,
-        subject_action: &str,
-        minuend: &Rewrite,
-        subtrahend: &Rewrite,
-        depth: i64,
-    ) -> Result<bool, PgFgaError> {
-        let minuend_result = self.check_rewrite(
+        relation: &str,
+        source: &RelationSource,
+    ) -> Result<Vec<Tuple>, PgFgaError> {
+        let tuples = self.storage.read_source_tuples(
+            self.schema_id,
             resource_namespace,
             resource_id,
-            subject_namespace,
-            subject_id,
-            subject_action,
-            minuend,
-            depth + 1,
-        );
-
-        if let Ok(false) = minuend_result {
-            return Ok(false);
-        }
+            relation,
+            source,
+        )?;
 
-        let subtrahend_result = self.check_rewrite(
-            resource_namespace,
-            resource_id,
-            subject_namespace,
-            subject_id,
-            subject_action,
-            subtrahend,
-            depth + 1,
-        );
+        Ok(tuples.into_iter().map(Tuple::from).collect())
+    }
+}
 
-        if let Ok(true) = subtrahend_result {
-            return Ok(false);
-        }
+fn timestamp(time: SystemTime) -> Result<pgrx::TimestampWithTimeZone, PgFgaError> {
+    let micros = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_micros() as i64,
+        Err(err) => -(err.duration().as_micros() as i64),
+    };
 
-        // minuend_result = true && subtrahend_result = false
-        Ok(true)
-    }
+    pgrx::TimestampWithTimeZone::try_from(micros - POSTGRES_EPOCH_MICROS)
+        .map_err(|err| PgFgaError::Public(err.to_string()))
 }
@@ -0,0 +1,419 @@
+//! Compile a permission into a single set-based query for the resources a
+//! subject can access, rather than checking each resource in turn.
+//!
+//! The query is a recursive CTE that starts from the tuples of the subject and
+//! walks outwards: from a subject set to the tuples it is the subject of, from
+//! a relation to the permissions it is a computed userset of, and along the
+//! tuplesets of tuple to userset rewrites. Intersections and exclusions can't
+//! be expressed this way, so they are over-approximated (by all of their
+//! children, and by their minuend, respectively) and the result is marked as
+//! inexact, meaning each resource in it still has to be checked.
+//!
+//! Each row of the CTE carries the depth `Checker` would reach it at, counted
+//! the same way, and rows are only followed while it is below `MAX_DEPTH`, so
+//! that the query finds no more than checks do.
+
+use crate::check::MAX_DEPTH;
+use crate::error::PgFgaError;
+use crate::schema::{RelationSource, Rewrite, Schema, TypeRestriction, WILDCARD};
+use pgrx::spi::quote_literal;
+use std::collections::BTreeSet;
+
+/// A compiled permission. The query takes the schema id, and the subject's
+/// namespace, id, and action as parameters `$1` to `$4`.
+#[derive(Debug, PartialEq)]
+pub struct Compiled {
+    pub query: String,
+
+    /// Whether every resource returned by the query is accessible. If not, it
+    /// is only a superset of the accessible resources.
+    pub exact: bool,
+}
+
+/// A relation that is a computed userset of a permission, and how much
+/// deeper than the permission `Checker` checks it.
+type ComputedUserset<'a> = (&'a str, &'a str, &'a str, i64);
+
+/// A tupleset and computed userset of a permission, and how much deeper than
+/// the permission `Checker` checks the computed userset.
+type TupleToUserset<'a> = (&'a str, &'a str, &'a str, &'a str, i64);
+
+#[derive(Default)]
+struct Rules<'a> {
+    computed_usersets: BTreeSet<ComputedUserset<'a>>,
+    tuple_to_usersets: BTreeSet<TupleToUserset<'a>>,
+
+    /// Permissions with an intersection or exclusion.
+    inexact: BTreeSet<(&'a str, &'a str)>,
+}
+
+impl<'a> Rules<'a> {
+    fn new(schema: &'a Schema) -> Self {
+        let mut rules = Rules::default();
+
+        for (ns, namespace) in &schema.namespaces {
+            for (permission, rewrite) in &namespace.permissions {
+                rules.add(ns, permission, rewrite, 0);
+            }
+        }
+
+        rules
+    }
+
+    /// Add the rules of `rewrite`, which `Checker` checks `depth` deeper
+    /// than the permission. The children of unions, intersections, and
+    /// exclusions are a level deeper, as are the tuplesets' subjects.
+    fn add(&mut self, ns: &'a str, permission: &'a str, rewrite: &'a Rewrite, depth: i64) {
+        match rewrite {
+            Rewrite::ComputedUserset(relation) => {
+                self.computed_usersets
+                    .insert((ns, relation, permission, depth));
+            }
+            Rewrite::TupleToUserset(tupleset, computed_userset) => {
+                self.tuple_to_usersets.insert((
+                    ns,
+                    tupleset,
+                    computed_userset,
+                    permission,
+                    depth + 1,
+                ));
+            }
+            Rewrite::Union(rewrites) => {
+                for rewrite in rewrites {
+                    self.add(ns, permission, rewrite, depth + 1);
+                }
+            }
+            Rewrite::Intersection(rewrites) => {
+                self.inexact.insert((ns, permission));
+                for rewrite in rewrites {
+                    self.add(ns, permission, rewrite, depth + 1);
+                }
+            }
+            Rewrite::Exclusion(minuend, _) => {
+                self.inexact.insert((ns, permission));
+                self.add(ns, permission, minuend, depth + 1);
+            }
+        }
+    }
+}
+
+/// Whether the query for `namespace#action` passes through an inexact
+/// permission, by walking back from it to everything it can be derived from.
+fn is_exact(schema: &Schema, rules: &Rules, namespace: &str, action: &str) -> bool {
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![(namespace.to_string(), action.to_string())];
+
+    while let Some((ns, action)) = stack.pop() {
+        if !seen.insert((ns.clone(), action.clone())) {
+            continue;
+        }
+
+        if rules.inexact.contains(&(ns.as_str(), action.as_str())) {
+            return false;
+        }
+
+        let relations = schema.namespaces.get(&ns).map(|ns| &ns.relations);
+
+        if let Some(restrictions) = relations.and_then(|relations| relations.get(&action)) {
+            for restriction in restrictions {
+                if let TypeRestriction::NamespaceAction(ns, action) = restriction {
+                    stack.push((ns.clone(), action.clone()));
+                }
+            }
+        }
+
+        for (rule_ns, relation, permission, _) in &rules.computed_usersets {
+            if *rule_ns == ns && *permission == action {
+                stack.push((ns.clone(), relation.to_string()));
+            }
+        }
+
+        for (rule_ns, tupleset, computed_userset, permission, _) in &rules.tuple_to_usersets {
+            if *rule_ns != ns || *permission != action {
+                continue;
+            }
+
+            let restrictions = relations.and_then(|relations| relations.get(*tupleset));
+            for restriction in restrictions.into_iter().flatten() {
+                let subject_ns = match restriction {
+                    TypeRestriction::Namespace(ns)
+                    | TypeRestriction::NamespaceAction(ns, _)
+                    | TypeRestriction::NamespaceWildcard(ns) => ns,
+                };
+                stack.push((subject_ns.clone(), computed_userset.to_string()));
+            }
+        }
+    }
+
+    true
+}
+
+const NOT_EXPIRED: &str = "(t.expires_at IS NULL OR t.expires_at > NOW())";
+
+/// Compile `namespace#action` into a query for the ids of the resources of
+/// `namespace` the subject has `action` on. Sources are read with the queries
+/// `source_sql` gives for them, i.e. `Storage::source_sql`.
+pub fn compile(
+    schema: &Schema,
+    namespace: &str,
+    action: &str,
+    source_sql: impl Fn(&RelationSource) -> Result<String, PgFgaError>,
+) -> Result<Compiled, PgFgaError> {
+    let rules = Rules::new(schema);
+
+    // Conditions can't be evaluated in SQL, so conditional tuples are
+    // followed as if their condition held.
+    let exact = schema.conditions.is_empty() && is_exact(schema, &rules, namespace, action);
+
+    // A subject set is checked a level deeper than the relation of the tuple
+    // it is the subject of.
+    let mut edges = vec![format!(
+        "SELECT t.resource_namespace::text, t.resource_id::text, t.relation::text, granted.depth + 1
+            FROM pgfga.tuple t
+            WHERE t.schema_id = $1
+                AND t.subject_namespace = granted.namespace
+                AND t.subject_id = granted.id
+                AND t.subject_action = granted.action
+                AND {NOT_EXPIRED}"
+    )];
+
+    if !rules.computed_usersets.is_empty() {
+        let rows: Vec<_> = rules
+            .computed_usersets
+            .iter()
+            .map(|(ns, relation, permission, depth)| {
+                format!(
+                    "({}, {}, {}, {depth})",
+                    quote_literal(ns),
+                    quote_literal(relation),
+                    quote_literal(permission)
+                )
+            })
+            .collect();
+
+        edges.push(format!(
+            "SELECT granted.namespace, granted.id, rule.permission, granted.depth + rule.depth
+            FROM (VALUES {}) AS rule (namespace, relation, permission, depth)
+            WHERE rule.namespace = granted.namespace
+                AND rule.relation = granted.action",
+            rows.join(", ")
+        ));
+    }
+
+    if !rules.tuple_to_usersets.is_empty() {
+        let rows: Vec<_> = rules
+            .tuple_to_usersets
+            .iter()
+            .map(|(ns, tupleset, computed_userset, permission, depth)| {
+                format!(
+                    "({}, {}, {}, {}, {depth})",
+                    quote_literal(ns),
+                    quote_literal(tupleset),
+                    quote_literal(computed_userset),
+                    quote_literal(permission)
+                )
+            })
+            .collect();
+
+        edges.push(format!(
+            "SELECT t.resource_namespace::text, t.resource_id::text, rule.permission, granted.depth + rule.depth
+            FROM (VALUES {}) AS rule (namespace, tupleset, computed_userset, permission, depth)
+            JOIN pgfga.tuple t
+                ON t.resource_namespace = rule.namespace
+                AND t.relation = rule.tupleset
+            WHERE t.schema_id = $1
+                AND rule.computed_userset = granted.action
+                AND t.subject_namespace = granted.namespace
+                AND t.subject_id = granted.id
+                AND t.subject_action IN ('', granted.action)
+                AND {NOT_EXPIRED}",
+            rows.join(", ")
+        ));
+    }
+
+    let mut seeds = vec![format!(
+        "SELECT t.resource_namespace::text, t.resource_id::text, t.relation::text, 0
+            FROM pgfga.tuple t
+            WHERE t.schema_id = $1
+                AND t.subject_namespace = $2
+                AND (t.subject_id = $3 OR (t.subject_id = {wildcard} AND $4 = ''))
+                AND t.subject_action = $4
+                AND {NOT_EXPIRED}",
+        wildcard = quote_literal(WILDCARD),
+    )];
+
+    // Rows of sources are tuples too, so they seed the query and are followed
+    // like the tuples above.
+    for (ns, namespace) in &schema.namespaces {
+        for (relation, source) in &namespace.sources {
+            let sql = source_sql(source)?;
+            seeds.push(format!(
+                "SELECT {}, source.resource_id, {}, 0
+            FROM ({}) AS source
+            WHERE {} = $2 AND source.subject_id = $3 AND {} = $4",
+                quote_literal(ns),
+                quote_literal(relation),
+                sql,
+                quote_literal(&source.subject_namespace),
+                quote_literal(&source.subject_action),
+            ));
+
+            if !source.subject_action.is_empty() {
+                edges.push(format!(
+                    "SELECT {}, source.resource_id, {}, granted.depth + 1
+            FROM ({}) AS source
+            WHERE granted.namespace = {}
+                AND granted.action = {}
+                AND source.subject_id = granted.id",
+                    quote_literal(ns),
+                    quote_literal(relation),
+                    sql,
+                    quote_literal(&source.subject_namespace),
+                    quote_literal(&source.subject_action),
+                ));
+            }
+        }
+    }
+
+    for (ns, tupleset, computed_userset, permission, depth) in &rules.tuple_to_usersets {
+        if let Some(source) = schema.source(ns, tupleset) {
+            edges.push(format!(
+                "SELECT {}, source.resource_id, {}, granted.depth + {depth}
+            FROM ({}) AS source
+            WHERE granted.namespace = {}
+                AND granted.action = {}
+                AND source.subject_id = granted.id
+                AND {} IN ('', granted.action)",
+                quote_literal(ns),
+                quote_literal(permission),
+                source_sql(source)?,
+                quote_literal(&source.subject_namespace),
+                quote_literal(computed_userset),
+                quote_literal(&source.subject_action),
+            ));
+        }
+    }
+
+    let query = format!(
+        "WITH RECURSIVE granted (namespace, id, action, depth) AS (
+            {seeds}
+        UNION
+            SELECT edge.namespace, edge.id, edge.action, edge.depth
+            FROM granted, LATERAL (
+                {edges}
+            ) AS edge (namespace, id, action, depth)
+            WHERE edge.depth < {MAX_DEPTH}
+        )
+        SELECT DISTINCT id FROM granted WHERE namespace = {namespace} AND action = {action}",
+        seeds = seeds.join("\n            UNION ALL\n            "),
+        edges = edges.join("\n            UNION ALL\n                "),
+        namespace = quote_literal(namespace),
+        action = quote_literal(action),
+    );
+
+    Ok(Compiled { query, exact })
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use pgrx::prelude::*;
+    use serde_json::json;
+
+    fn source_sql(source: &RelationSource) -> Result<String, PgFgaError> {
+        Spi::connect(|client| Storage::new(client).source_sql(source))
+    }
+
+    fn schema() -> Schema {
+        serde_json::from_value(json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                "folder": {
+                    "relations": {"viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]},
+                    "permissions": {"can_view": {"computedUserset": "viewer"}}
+                },
+                "document": {
+                    "relations": {
+                        "parent": [{"namespace": "folder"}],
+                        "viewer": [{"namespace": "user"}],
+                        "blocked": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "can_view": {"union": [
+                            {"computedUserset": "viewer"},
+                            {"tupleToUserset": ["parent", "can_view"]}
+                        ]},
+                        "can_read": {"exclusion": [
+                            {"computedUserset": "can_view"},
+                            {"computedUserset": "blocked"}
+                        ]}
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn unions_are_exact() {
+        let compiled = compile(&schema(), "document", "can_view", source_sql).unwrap();
+
+        assert!(compiled.exact);
+        assert!(compiled
+            .query
+            .contains("('document', 'viewer', 'can_view', 1)"));
+        assert!(compiled
+            .query
+            .contains("('document', 'parent', 'can_view', 'can_view', 2)"));
+        assert!(compiled
+            .query
+            .ends_with("WHERE namespace = 'document' AND action = 'can_view'"));
+    }
+
+    #[pg_test]
+    fn exclusions_are_inexact() {
+        let schema = schema();
+
+        assert!(
+            !compile(&schema, "document", "can_read", source_sql)
+                .unwrap()
+                .exact
+        );
+        assert!(
+            compile(&schema, "document", "viewer", source_sql)
+                .unwrap()
+                .exact
+        );
+        assert!(
+            compile(&schema, "group", "member", source_sql)
+                .unwrap()
+                .exact
+        );
+    }
+
+    #[pg_test]
+    fn sources_are_followed() {
+        Spi::run("CREATE TABLE blocks (document_id TEXT, user_id TEXT)").unwrap();
+
+        let mut schema = schema();
+        schema.namespaces.get_mut("document").unwrap().sources = serde_json::from_value(json!({
+            "blocked": {"table": "blocks", "resourceId": "document_id", "subjectId": "user_id", "subjectNamespace": "user"},
+            "parent": {"query": "SELECT id, folder_id FROM documents", "subjectNamespace": "folder"}
+        }))
+        .unwrap();
+
+        let compiled = compile(&schema, "document", "can_view", source_sql).unwrap();
+
+        assert!(compiled.query.contains(
+            "FROM (SELECT source.resource_id::text, source.subject_id::text FROM (SELECT document_id, user_id FROM blocks) AS source (resource_id, subject_id)) AS source
+            WHERE 'user' = $2 AND source.subject_id = $3 AND '' = $4"
+        ));
+        assert!(compiled.query.contains(
+            "WHERE granted.namespace = 'folder'
+                AND granted.action = 'can_view'"
+        ));
+    }
+}
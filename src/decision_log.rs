@@ -0,0 +1,35 @@
+use crate::error::PgFgaError;
+use crate::guc;
+use crate::storage::Storage;
+use pgrx::prelude::*;
+use serde_json::Value;
+use std::time::Duration;
+
+/// A decision made by a check, as written to `pgfga.decision_log`.
+pub struct Decision {
+    pub schema_id: pgrx::Uuid,
+
+    /// The arguments of the check, as a JSON object.
+    pub request: Value,
+    pub allowed: bool,
+    pub duration: Duration,
+
+    /// The deepest the check recursed, where 0 is the check itself.
+    pub depth: i64,
+}
+
+/// Whether the decision of the check about to be made is logged, which is
+/// sampled at `pgfga.decision_log_sample_rate`. It is sampled up front, so
+/// that checks left out of the sample don't touch the log at all. Nothing can
+/// be written during a parallel query or in a read-only transaction, e.g. on
+/// a standby, so decisions made there are never logged.
+pub fn sampled() -> bool {
+    guc::DECISION_LOG.get()
+        && fastrand::f64() < guc::DECISION_LOG_SAMPLE_RATE.get()
+        && unsafe { !pg_sys::IsInParallelMode() && !pg_sys::XactReadOnly }
+}
+
+/// Log a decision picked by `sampled`.
+pub fn log(decision: &Decision) -> Result<(), PgFgaError> {
+    Spi::connect(|client| Storage::new(client).log_decision(decision))
+}
@@ -0,0 +1,391 @@
+use crate::error::PgFgaError;
+use crate::schema::{Namespace, Schema, TypeRestriction};
+use crate::storage::Storage;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaDiff {
+    /// Whether any of the changes invalidate existing tuples.
+    pub breaking: bool,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeKind {
+    NamespaceAdded,
+    NamespaceRemoved,
+    RelationAdded,
+    RelationRemoved,
+    TypeRestrictionAdded,
+    TypeRestrictionRemoved,
+    PermissionAdded,
+    PermissionRemoved,
+    PermissionChanged,
+    ConditionAdded,
+    ConditionRemoved,
+    ConditionChanged,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change {
+    pub kind: ChangeKind,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+
+    /// Whether the change invalidates existing tuples.
+    pub breaking: bool,
+
+    /// The number of existing tuples invalidated by a breaking change, once
+    /// counted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affected_tuples: Option<i64>,
+
+    /// Which tuples a breaking change invalidates.
+    #[serde(skip)]
+    pub affects: Option<AffectedTuples>,
+}
+
+/// The tuples invalidated by a breaking change.
+#[derive(Debug, PartialEq)]
+pub enum AffectedTuples {
+    /// Tuples with the namespace as either their resource or subject.
+    Namespace(String),
+
+    /// Tuples of the relation, or with it as their subject set.
+    Relation(String, String),
+
+    /// Tuples of the relation whose subject matches the restriction.
+    TypeRestriction(String, String, TypeRestriction),
+
+    /// Tuples with the condition.
+    Condition(String),
+}
+
+impl Change {
+    fn new(kind: ChangeKind, namespace: Option<&str>, name: Option<&str>) -> Self {
+        Change {
+            kind,
+            namespace: namespace.map(str::to_string),
+            name: name.map(str::to_string),
+            old: None,
+            new: None,
+            breaking: false,
+            affected_tuples: None,
+            affects: None,
+        }
+    }
+
+    fn old<T: Serialize>(mut self, old: &T) -> Self {
+        self.old = serde_json::to_value(old).ok();
+        self
+    }
+
+    fn new_value<T: Serialize>(mut self, new: &T) -> Self {
+        self.new = serde_json::to_value(new).ok();
+        self
+    }
+
+    fn affects(mut self, affects: AffectedTuples) -> Self {
+        self.breaking = true;
+        self.affects = Some(affects);
+        self
+    }
+}
+
+fn sorted_keys<'a, V>(
+    old: &'a HashMap<String, V>,
+    new: &'a HashMap<String, V>,
+) -> BTreeSet<&'a str> {
+    old.keys().chain(new.keys()).map(String::as_str).collect()
+}
+
+/// Compare two schemas. Changes are sorted by namespace and name so that the
+/// result is stable.
+pub fn diff(old: &Schema, new: &Schema) -> SchemaDiff {
+    let mut changes = vec![];
+
+    for ns in sorted_keys(&old.namespaces, &new.namespaces) {
+        match (old.namespaces.get(ns), new.namespaces.get(ns)) {
+            (None, Some(_)) => {
+                changes.push(Change::new(ChangeKind::NamespaceAdded, Some(ns), None))
+            }
+            (Some(_), None) => changes.push(
+                Change::new(ChangeKind::NamespaceRemoved, Some(ns), None)
+                    .affects(AffectedTuples::Namespace(ns.to_string())),
+            ),
+            (Some(old_ns), Some(new_ns)) => diff_namespace(ns, old_ns, new_ns, &mut changes),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    for name in sorted_keys(&old.conditions, &new.conditions) {
+        match (old.conditions.get(name), new.conditions.get(name)) {
+            (None, Some(condition)) => changes.push(
+                Change::new(ChangeKind::ConditionAdded, None, Some(name))
+                    .new_value(&condition.expression),
+            ),
+            (Some(condition), None) => changes.push(
+                Change::new(ChangeKind::ConditionRemoved, None, Some(name))
+                    .old(&condition.expression)
+                    .affects(AffectedTuples::Condition(name.to_string())),
+            ),
+            (Some(old_condition), Some(new_condition)) if old_condition != new_condition => changes
+                .push(
+                    Change::new(ChangeKind::ConditionChanged, None, Some(name))
+                        .old(&old_condition.expression)
+                        .new_value(&new_condition.expression),
+                ),
+            _ => {}
+        }
+    }
+
+    SchemaDiff {
+        breaking: changes.iter().any(|change| change.breaking),
+        changes,
+    }
+}
+
+/// Compare two stored schemas, counting the tuples of `old_schema` that each
+/// breaking change would invalidate.
+pub fn diff_schemas(
+    storage: &Storage,
+    old_schema: pgrx::Uuid,
+    new_schema: pgrx::Uuid,
+) -> Result<SchemaDiff, PgFgaError> {
+    let read = |id| -> Result<Schema, PgFgaError> {
+        storage
+            .read_schemas(Some(id))?
+            .pop()
+            .ok_or_else(|| PgFgaError::UnknownSchemaId(id))?
+            .try_into()
+    };
+
+    let mut diff = diff(&read(old_schema)?, &read(new_schema)?);
+
+    for change in &mut diff.changes {
+        if let Some(affects) = &change.affects {
+            change.affected_tuples = Some(storage.count_affected_tuples(old_schema, affects)?);
+        }
+    }
+
+    Ok(diff)
+}
+
+fn diff_namespace(ns: &str, old: &Namespace, new: &Namespace, changes: &mut Vec<Change>) {
+    for relation in sorted_keys(&old.relations, &new.relations) {
+        match (old.relations.get(relation), new.relations.get(relation)) {
+            (None, Some(restrictions)) => changes.push(
+                Change::new(ChangeKind::RelationAdded, Some(ns), Some(relation))
+                    .new_value(restrictions),
+            ),
+            (Some(restrictions), None) => changes.push(
+                Change::new(ChangeKind::RelationRemoved, Some(ns), Some(relation))
+                    .old(restrictions)
+                    .affects(AffectedTuples::Relation(
+                        ns.to_string(),
+                        relation.to_string(),
+                    )),
+            ),
+            (Some(old_restrictions), Some(new_restrictions)) => {
+                for restriction in old_restrictions {
+                    if !new_restrictions.contains(restriction) {
+                        changes.push(
+                            Change::new(
+                                ChangeKind::TypeRestrictionRemoved,
+                                Some(ns),
+                                Some(relation),
+                            )
+                            .old(restriction)
+                            .affects(AffectedTuples::TypeRestriction(
+                                ns.to_string(),
+                                relation.to_string(),
+                                restriction.clone(),
+                            )),
+                        );
+                    }
+                }
+
+                for restriction in new_restrictions {
+                    if !old_restrictions.contains(restriction) {
+                        changes.push(
+                            Change::new(ChangeKind::TypeRestrictionAdded, Some(ns), Some(relation))
+                                .new_value(restriction),
+                        );
+                    }
+                }
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    for permission in sorted_keys(&old.permissions, &new.permissions) {
+        match (
+            old.permissions.get(permission),
+            new.permissions.get(permission),
+        ) {
+            (None, Some(rewrite)) => changes.push(
+                Change::new(ChangeKind::PermissionAdded, Some(ns), Some(permission))
+                    .new_value(rewrite),
+            ),
+            (Some(rewrite), None) => changes.push(
+                Change::new(ChangeKind::PermissionRemoved, Some(ns), Some(permission)).old(rewrite),
+            ),
+            (Some(old_rewrite), Some(new_rewrite)) if old_rewrite != new_rewrite => changes.push(
+                Change::new(ChangeKind::PermissionChanged, Some(ns), Some(permission))
+                    .old(old_rewrite)
+                    .new_value(new_rewrite),
+            ),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(value: Value) -> Schema {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn identical_schemas_have_no_changes() {
+        let old = schema(json!({"namespaces": {"user": {}}}));
+        let new = schema(json!({"namespaces": {"user": {}}}));
+
+        assert_eq!(
+            diff(&old, &new),
+            SchemaDiff {
+                breaking: false,
+                changes: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn diff_works() {
+        let old = schema(json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}]}},
+                "document": {
+                    "relations": {
+                        "viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}],
+                        "owner": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "can_view": {"computedUserset": "viewer"},
+                        "can_delete": {"computedUserset": "owner"}
+                    }
+                }
+            },
+            "conditions": {"in_network": {"expression": "ip_in_cidr(ip, network)"}}
+        }));
+
+        let new = schema(json!({
+            "namespaces": {
+                "user": {},
+                "folder": {},
+                "document": {
+                    "relations": {
+                        "viewer": [{"namespace": "user"}, {"namespaceWildcard": "user"}],
+                        "editor": [{"namespace": "user"}]
+                    },
+                    "permissions": {
+                        "can_view": {"union": [{"computedUserset": "viewer"}, {"computedUserset": "editor"}]}
+                    }
+                }
+            }
+        }));
+
+        let diff = diff(&old, &new);
+        assert!(diff.breaking);
+
+        let summary: Vec<_> = diff
+            .changes
+            .iter()
+            .map(|change| {
+                (
+                    serde_json::to_value(&change.kind).unwrap(),
+                    change.namespace.as_deref(),
+                    change.name.as_deref(),
+                    change.breaking,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    json!("relationAdded"),
+                    Some("document"),
+                    Some("editor"),
+                    false
+                ),
+                (
+                    json!("relationRemoved"),
+                    Some("document"),
+                    Some("owner"),
+                    true
+                ),
+                (
+                    json!("typeRestrictionRemoved"),
+                    Some("document"),
+                    Some("viewer"),
+                    true
+                ),
+                (
+                    json!("typeRestrictionAdded"),
+                    Some("document"),
+                    Some("viewer"),
+                    false
+                ),
+                (
+                    json!("permissionRemoved"),
+                    Some("document"),
+                    Some("can_delete"),
+                    false
+                ),
+                (
+                    json!("permissionChanged"),
+                    Some("document"),
+                    Some("can_view"),
+                    false
+                ),
+                (json!("namespaceAdded"), Some("folder"), None, false),
+                (json!("namespaceRemoved"), Some("group"), None, true),
+                (json!("conditionRemoved"), None, Some("in_network"), true),
+            ]
+        );
+
+        assert_eq!(
+            diff.changes[2].affects,
+            Some(AffectedTuples::TypeRestriction(
+                "document".to_string(),
+                "viewer".to_string(),
+                TypeRestriction::NamespaceAction("group".to_string(), "member".to_string())
+            ))
+        );
+        assert_eq!(
+            diff.changes[2].old,
+            Some(json!({"namespaceAction": ["group", "member"]}))
+        );
+    }
+}
@@ -1,4 +1,11 @@
 use crate::check::MAX_DEPTH;
+use crate::condition::ConditionError;
+use crate::schema::ImportError;
+use crate::types::ParseError;
+use pgfga_core::CheckError;
+use pgrx::pg_sys;
+use pgrx::pg_sys::panic::ErrorReport;
+use std::ffi::CString;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,4 +24,196 @@ pub enum PgFgaError {
 
     #[error("'{0}' does not correspond to any known schema")]
     UnknownSchemaId(pgrx::Uuid),
+
+    #[error("'{0}' does not correspond to any named schema")]
+    UnknownSchemaName(String),
+
+    #[error("schema name '{0}' is used by more than one store")]
+    AmbiguousSchemaName(String),
+
+    #[error("schema '{0}' is not named '{1}'")]
+    SchemaNameMismatch(pgrx::Uuid, String),
+
+    #[error("schema '{0}' is archived")]
+    SchemaArchived(pgrx::Uuid),
+
+    #[error("schema '{0}' is still referenced by {1} tuples")]
+    SchemaInUse(pgrx::Uuid, i64),
+
+    #[error("'{2}:*' is not an allowed subject of '{0}#{1}'")]
+    WildcardNotAllowed(String, String, String),
+
+    #[error("'{0}' does not correspond to any condition in the schema")]
+    UnknownCondition(String),
+
+    #[error("error evaluating condition '{0}': {1}")]
+    ConditionError(String, ConditionError),
+
+    #[error("{0}")]
+    ParseError(#[from] ParseError),
+
+    #[error("error importing model: {0}")]
+    ImportError(#[from] ImportError),
+
+    #[error("the source of '{0}#{1}' is a query, which only members of pgfga_admin can write")]
+    QuerySourceNotAllowed(String, String),
+
+    #[error("only members of pgfga_admin can modify pgfga.decision_log")]
+    DecisionLogAdminOnly,
+}
+
+impl From<CheckError> for PgFgaError {
+    fn from(err: CheckError) -> Self {
+        match err {
+            CheckError::MaxDepth => PgFgaError::MaxDepth,
+            CheckError::UnknownCondition(name) => PgFgaError::UnknownCondition(name),
+            CheckError::ConditionError(name, err) => PgFgaError::ConditionError(name, err),
+        }
+    }
+}
+
+impl PgFgaError {
+    /// The SQLSTATE the error is raised with. Each kind of error has its own,
+    /// in the `PF` class, and they are stable, so clients can branch on them
+    /// rather than on messages.
+    pub fn sqlstate(&self) -> &'static str {
+        match self {
+            PgFgaError::SpiError(_) => "PF000",
+            PgFgaError::SerdeError(_) => "PF001",
+            PgFgaError::MaxDepth => "PF002",
+            PgFgaError::Public(_) => "PF003",
+            PgFgaError::UnknownSchemaId(_) => "PF004",
+            PgFgaError::UnknownSchemaName(_) => "PF005",
+            PgFgaError::AmbiguousSchemaName(_) => "PF006",
+            PgFgaError::SchemaNameMismatch(..) => "PF007",
+            PgFgaError::SchemaArchived(_) => "PF008",
+            PgFgaError::SchemaInUse(..) => "PF009",
+            PgFgaError::WildcardNotAllowed(..) => "PF010",
+            PgFgaError::UnknownCondition(_) => "PF011",
+            PgFgaError::ConditionError(_, err) => match err {
+                ConditionError::Syntax(..) => "PF012",
+                ConditionError::MissingParameter(_) => "PF013",
+                ConditionError::Type(_) => "PF014",
+                ConditionError::UnknownFunction(_) => "PF015",
+                ConditionError::TooDeep(_) => "PF016",
+            },
+            PgFgaError::ParseError(_) => "PF017",
+            PgFgaError::ImportError(err) => match err {
+                ImportError::Syntax(..) => "PF018",
+                ImportError::Invalid(_) => "PF019",
+                ImportError::Unsupported(_) => "PF020",
+            },
+            PgFgaError::QuerySourceNotAllowed(..) => "PF021",
+            PgFgaError::DecisionLogAdminOnly => "PF022",
+        }
+    }
+
+    /// Raise the error. pgrx's `ErrorReport` can only carry the SQLSTATEs
+    /// Postgres defines, so the error is thrown as Postgres' own `ErrorData`
+    /// instead. pgrx turns it into a panic to unwind the Rust stack, and
+    /// rethrows it as it is once out of the extension.
+    pub fn raise(self) -> ! {
+        let text = |text: String| CString::new(text.replace('\0', "")).unwrap();
+
+        let message = text(self.to_string());
+        let detail = self.detail().map(text);
+        let hint = self.hint().map(text);
+
+        let mut data = pg_sys::ErrorData {
+            elevel: pg_sys::ERROR as _,
+            sqlerrcode: make_sqlstate(self.sqlstate()),
+            message: message.as_ptr() as *mut _,
+            detail: detail
+                .as_ref()
+                .map_or(std::ptr::null_mut(), |d| d.as_ptr() as *mut _),
+            hint: hint
+                .as_ref()
+                .map_or(std::ptr::null_mut(), |h| h.as_ptr() as *mut _),
+            filename: concat!(file!(), "\0").as_ptr().cast(),
+            lineno: line!() as _,
+            funcname: b"pgfga\0".as_ptr().cast(),
+            ..Default::default()
+        };
+
+        // SAFETY: ThrowErrorData copies the strings before throwing, and the
+        // throw is caught at the FFI boundary, so they are dropped as usual.
+        unsafe { pg_sys::ThrowErrorData(&mut data) };
+        unreachable!("ThrowErrorData returned for an ERROR")
+    }
+
+    fn detail(&self) -> Option<String> {
+        match self {
+            PgFgaError::SerdeError(err) if err.line() > 0 => {
+                Some(format!("at line {} column {}", err.line(), err.column()))
+            }
+            PgFgaError::SchemaInUse(_, count) => Some(format!("{count} tuples")),
+            PgFgaError::ParseError(err) => Some(format!(
+                "{}\n{:>width$}",
+                err.input,
+                "^",
+                width = err.position
+            )),
+            _ => None,
+        }
+    }
+
+    fn hint(&self) -> Option<String> {
+        let hint = match self {
+            PgFgaError::MaxDepth => "Check the schema for cycles.",
+            PgFgaError::UnknownSchemaId(_) => {
+                "pgfga.read_schemas() lists the schemas visible to the current role."
+            }
+            PgFgaError::UnknownSchemaName(_) => {
+                "Name a schema when creating it, e.g. pgfga.create_schema(schema, name => 'docs')."
+            }
+            PgFgaError::AmbiguousSchemaName(_) => {
+                "Set pgfga.store_id to the store to resolve names in, or use the schema's id."
+            }
+            PgFgaError::SchemaNameMismatch(..) => {
+                "A schema can only be the current version of the name it was created with."
+            }
+            PgFgaError::SchemaArchived(_) => "Unarchive it with pgfga.archive_schema(id, false).",
+            PgFgaError::QuerySourceNotAllowed(..) => {
+                "Read the relation from a table, or have a member of pgfga_admin create the schema."
+            }
+            PgFgaError::SchemaInUse(..) => {
+                "Delete its tuples first, or delete them along with it using cascade => true."
+            }
+            PgFgaError::WildcardNotAllowed(_, _, ns) => {
+                return Some(format!(
+                    "Allow it by adding {{\"namespaceWildcard\": \"{ns}\"}} to the relation."
+                ))
+            }
+            PgFgaError::UnknownCondition(_) => "Conditions are defined in the schema.",
+            PgFgaError::ConditionError(_, ConditionError::MissingParameter(_)) => {
+                "Pass it in the context, or in the tuple's condition_context."
+            }
+            PgFgaError::ParseError(_) => {
+                "Escape ':', '#', '@' and '\\' in names and ids with a backslash."
+            }
+            PgFgaError::ImportError(ImportError::Unsupported(_)) => {
+                "Remove them from the model. Conditions can be redefined in the schema once imported."
+            }
+            _ => return None,
+        };
+
+        Some(hint.to_string())
+    }
+}
+
+/// Lets `?` raise errors in functions that return `ErrorReport`s. The error
+/// is raised rather than converted, to keep its SQLSTATE.
+impl From<PgFgaError> for ErrorReport {
+    fn from(err: PgFgaError) -> Self {
+        err.raise()
+    }
+}
+
+/// Postgres' packed representation of a SQLSTATE, as `MAKE_SQLSTATE` does it.
+fn make_sqlstate(sqlstate: &str) -> i32 {
+    sqlstate
+        .bytes()
+        .enumerate()
+        .map(|(i, ch)| ((ch.wrapping_sub(b'0') & 0x3F) as i32) << (6 * i))
+        .sum()
 }
@@ -0,0 +1,201 @@
+//! Export a schema and all of its tuples as a single portable document, and
+//! import it again, e.g. for fixtures, backups, or refreshing staging.
+//!
+//! A document is either a single JSON object, or NDJSON: the same object
+//! without `tuples` on the first line, followed by a tuple per line.
+
+use crate::error::PgFgaError;
+use crate::schema::Schema;
+use crate::storage::{validate_write, Storage};
+use pgrx::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The number of tuples fetched at a time when exporting as NDJSON.
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct Export {
+    pub schema: serde_json::Value,
+
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The relations whose group closure is kept, as `[namespace, relation]`.
+    #[serde(default)]
+    pub closure_relations: Vec<(String, String)>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tuples: Vec<ExportedTuple>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct ExportedTuple {
+    pub resource_namespace: String,
+    pub resource_id: String,
+    pub relation: String,
+    pub subject_namespace: String,
+    pub subject_id: String,
+
+    #[serde(default)]
+    pub subject_action: String,
+
+    /// As an ISO 8601 timestamp, which Postgres parses back exactly.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+
+    #[serde(default)]
+    pub condition: String,
+
+    #[serde(default)]
+    pub condition_context: Option<serde_json::Value>,
+}
+
+/// Export a schema without its tuples.
+fn export_header(storage: &Storage, schema_id: pgrx::Uuid) -> Result<Export, PgFgaError> {
+    let row = storage
+        .read_schemas(Some(schema_id))?
+        .pop()
+        .ok_or_else(|| PgFgaError::UnknownSchemaId(schema_id))?;
+
+    let mut closure_relations: Vec<_> = storage
+        .read_closure_relations(schema_id)?
+        .into_iter()
+        .collect();
+    closure_relations.sort();
+
+    Ok(Export {
+        schema: row.schema.0,
+        name: row.name,
+        closure_relations,
+        tuples: vec![],
+    })
+}
+
+/// Export a schema and all of its tuples, oldest first.
+pub fn export(storage: &Storage, schema_id: pgrx::Uuid) -> Result<Export, PgFgaError> {
+    let mut export = export_header(storage, schema_id)?;
+    export.tuples = storage.read_exported_tuples(schema_id)?;
+
+    Ok(export)
+}
+
+/// The lines of an NDJSON export. Tuples are fetched in batches as lines are
+/// read, so a large export is never held in memory all at once.
+pub struct NdjsonExport {
+    header: Option<String>,
+    cursor: String,
+    lines: VecDeque<String>,
+    done: bool,
+}
+
+impl NdjsonExport {
+    pub fn new(schema_id: pgrx::Uuid) -> Result<Self, PgFgaError> {
+        Spi::connect(|client| {
+            let storage = Storage::new(client);
+            let header = serde_json::to_string(&export_header(&storage, schema_id)?)?;
+
+            Ok(NdjsonExport {
+                header: Some(header),
+                cursor: storage.open_exported_tuples(schema_id),
+                lines: VecDeque::new(),
+                done: false,
+            })
+        })
+    }
+
+    fn fetch(&mut self) -> Result<(), PgFgaError> {
+        let tuples = Spi::connect(|client| {
+            Storage::new(client).fetch_exported_tuples(&self.cursor, BATCH_SIZE)
+        })?;
+
+        self.done = (tuples.len() as i64) < BATCH_SIZE;
+        for tuple in tuples {
+            self.lines.push_back(serde_json::to_string(&tuple)?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for NdjsonExport {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if let Some(header) = self.header.take() {
+            return Some(header);
+        }
+
+        if self.lines.is_empty() && !self.done {
+            if let Err(err) = self.fetch() {
+                err.raise();
+            }
+        }
+
+        self.lines.pop_front()
+    }
+}
+
+/// Parse an NDJSON export.
+pub fn parse_ndjson(document: &str) -> Result<Export, PgFgaError> {
+    let mut lines = document
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let parse_error =
+        |i: usize, err: serde_json::Error| PgFgaError::Public(format!("line {}: {err}", i + 1));
+
+    let (i, header) = lines
+        .next()
+        .ok_or_else(|| PgFgaError::Public("export is empty".to_string()))?;
+    let mut export: Export = serde_json::from_str(header).map_err(|err| parse_error(i, err))?;
+
+    for (i, line) in lines {
+        export
+            .tuples
+            .push(serde_json::from_str(line).map_err(|err| parse_error(i, err))?);
+    }
+
+    Ok(export)
+}
+
+/// Create a new schema from an export, along with its tuples and group
+/// closures. The name of the export is used unless another is given. Returns
+/// the id of the new schema.
+pub fn import(
+    storage: &mut Storage,
+    export: Export,
+    store_id: Option<pgrx::Uuid>,
+    name: Option<&str>,
+) -> Result<pgrx::Uuid, PgFgaError> {
+    let schema: Schema = serde_json::from_value(export.schema.clone())?;
+
+    for tuple in &export.tuples {
+        validate_write(
+            &schema,
+            &tuple.resource_namespace,
+            &tuple.relation,
+            &tuple.subject_namespace,
+            &tuple.subject_id,
+            &tuple.subject_action,
+            &tuple.condition,
+        )?;
+    }
+
+    let schema_id = storage
+        .create_schema(
+            pgrx::Json(export.schema),
+            store_id,
+            name.or(export.name.as_deref()),
+        )?
+        .expect("no schema id");
+
+    storage.import_tuples(schema_id, &export.tuples)?;
+
+    for (namespace, relation) in &export.closure_relations {
+        storage.enable_group_closure(schema_id, namespace, relation)?;
+    }
+
+    Ok(schema_id)
+}
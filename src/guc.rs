@@ -0,0 +1,83 @@
+use crate::error::PgFgaError;
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+use std::ffi::CStr;
+
+pub static CLEANUP_INTERVAL: GucSetting<i32> = GucSetting::<i32>::new(60);
+
+pub static CLEANUP_DATABASE: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(Some(unsafe {
+        CStr::from_bytes_with_nul_unchecked(b"postgres\0")
+    }));
+
+pub static DECISION_LOG: GucSetting<bool> = GucSetting::<bool>::new(false);
+
+pub static DECISION_LOG_SAMPLE_RATE: GucSetting<f64> = GucSetting::<f64>::new(1.0);
+
+pub static STORE_ID: GucSetting<Option<&'static CStr>> =
+    GucSetting::<Option<&'static CStr>>::new(None);
+
+pub fn init() {
+    GucRegistry::define_int_guc(
+        "pgfga.cleanup_interval",
+        "Seconds between cleanup cycles of the pgfga background worker.",
+        "Each cycle deletes expired tuples. Set to 0 to disable the worker.",
+        &CLEANUP_INTERVAL,
+        0,
+        i32::MAX,
+        GucContext::Sighup,
+        GucFlags::UNIT_S,
+    );
+
+    GucRegistry::define_string_guc(
+        "pgfga.cleanup_database",
+        "Database the pgfga background worker connects to.",
+        "Only read when the server starts.",
+        &CLEANUP_DATABASE,
+        GucContext::Postmaster,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        "pgfga.decision_log",
+        "Whether checks are logged to pgfga.decision_log.",
+        "Only superusers can change it, so that roles can't hide their own checks.",
+        &DECISION_LOG,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_float_guc(
+        "pgfga.decision_log_sample_rate",
+        "Fraction of checks logged to pgfga.decision_log.",
+        "Each check is logged with this probability while pgfga.decision_log is on.",
+        &DECISION_LOG_SAMPLE_RATE,
+        0.0,
+        1.0,
+        GucContext::Suset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_string_guc(
+        "pgfga.store_id",
+        "Store that schema names are resolved in.",
+        "If unset, a name has to be unambiguous among the stores visible to the current role.",
+        &STORE_ID,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
+
+/// The store of `pgfga.store_id`, if it is set.
+pub fn store_id() -> Result<Option<pgrx::Uuid>, PgFgaError> {
+    let store_id = match STORE_ID.get().map(CStr::to_str) {
+        None => return Ok(None),
+        Some(Ok("")) => return Ok(None),
+        Some(Ok(store_id)) => store_id,
+        Some(Err(err)) => return Err(PgFgaError::Public(err.to_string())),
+    };
+
+    let id = uuid::Uuid::parse_str(store_id)
+        .map_err(|_| PgFgaError::Public(format!("pgfga.store_id '{store_id}' is not a UUID")))?;
+
+    Ok(Some(pgrx::Uuid::from_bytes(id.into_bytes())))
+}
@@ -1,36 +1,536 @@
-use check::Checker;
 use error::PgFgaError;
+use pgrx::pg_sys::panic::ErrorReport;
 use pgrx::prelude::*;
+use std::time::Instant;
 use storage::Storage;
+use types::{object_ref, subject_ref, tuple_key};
 
 pgrx::pg_module_magic!();
 
 pub mod check;
+pub mod compile;
+pub mod decision_log;
+pub mod diff;
 pub mod error;
-pub mod schema;
+pub mod export;
+pub mod guc;
+pub mod migrate;
+pub mod rls;
 pub mod storage;
+pub mod sync;
+pub mod types;
+pub mod worker;
+
+pub use pgfga_core::{condition, openfga, schema, spicedb};
+
+#[pg_guard]
+pub extern "C" fn _PG_init() {
+    guc::init();
+    worker::init();
+}
 
 extension_sql!(
     r#"
+    -- Members of pgfga_admin can write what runs as other roles, i.e. query
+    -- sources. Roles are shared by all databases, so it may already exist.
+    DO $$
+    BEGIN
+        IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'pgfga_admin') THEN
+            CREATE ROLE pgfga_admin NOLOGIN;
+        END IF;
+    END;
+    $$;
+
+    CREATE TABLE pgfga.store (
+        rowid BIGINT GENERATED ALWAYS AS IDENTITY,
+        id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+        name VARCHAR(128) NOT NULL,
+        owner REGROLE DEFAULT CURRENT_USER::REGROLE NOT NULL,
+        created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+    );
+
     CREATE TABLE pgfga.schema (
         rowid BIGINT GENERATED ALWAYS AS IDENTITY,
         id UUID PRIMARY KEY DEFAULT gen_random_uuid() ,
         schema JSON NOT NULL,
-        created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+        created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+        store_id UUID REFERENCES pgfga.store (id),
+        name VARCHAR(128),
+        version INTEGER,
+        archived BOOLEAN DEFAULT FALSE NOT NULL
+    );
+
+    -- Names are per store, so the versions of a name are unique within its
+    -- store, or among the schemas without a store.
+    CREATE UNIQUE INDEX schema_store_name_version_idx
+        ON pgfga.schema (store_id, name, version) WHERE store_id IS NOT NULL;
+    CREATE UNIQUE INDEX schema_name_version_idx
+        ON pgfga.schema (name, version) WHERE store_id IS NULL;
+
+    -- The version of each named schema that checks and writes by name use,
+    -- keyed by the store and name of the schema. A primary key can't have a
+    -- null store, so it is a pair of unique indexes like the ones above.
+    CREATE TABLE pgfga.current_schema (
+        store_id UUID REFERENCES pgfga.store (id),
+        name VARCHAR(128) NOT NULL,
+        schema_id UUID NOT NULL REFERENCES pgfga.schema (id)
     );
 
+    CREATE UNIQUE INDEX current_schema_store_name_idx
+        ON pgfga.current_schema (name, store_id) WHERE store_id IS NOT NULL;
+    CREATE UNIQUE INDEX current_schema_name_idx
+        ON pgfga.current_schema (name) WHERE store_id IS NULL;
+
     CREATE TABLE pgfga.tuple (
         rowid BIGINT GENERATED ALWAYS AS IDENTITY,
-        schema_id UUID NOT NULL,
+        schema_id UUID NOT NULL REFERENCES pgfga.schema (id),
         resource_namespace VARCHAR(128) NOT NULL,
         resource_id VARCHAR(128) NOT NULL,
         relation VARCHAR(128) NOT NULL,
         subject_namespace VARCHAR(128) NOT NULL,
         subject_id VARCHAR(128) NOT NULL,
         subject_action VARCHAR(128) DEFAULT ''::TEXT NOT NULL,
+        expires_at TIMESTAMPTZ,
+        condition VARCHAR(128) DEFAULT ''::TEXT NOT NULL,
+        condition_context JSONB,
         PRIMARY KEY (schema_id, resource_namespace, resource_id, relation, subject_namespace, subject_id, subject_action)
     );
 
+    CREATE INDEX tuple_expires_at_idx ON pgfga.tuple (expires_at) WHERE expires_at IS NOT NULL;
+
+    CREATE INDEX schema_store_id_idx ON pgfga.schema (store_id);
+
+    -- Query sources are run as written by the role doing the check, so only
+    -- members of pgfga_admin can write schemas with them, whether through
+    -- pgfga.create_schema or not.
+    CREATE FUNCTION pgfga.reject_query_sources() RETURNS TRIGGER AS $$
+    DECLARE
+        source RECORD;
+    BEGIN
+        IF pg_has_role('pgfga_admin', 'MEMBER') THEN
+            RETURN NEW;
+        END IF;
+
+        SELECT namespace.key AS namespace, relation.key AS relation INTO source
+        FROM json_each(NEW.schema -> 'namespaces') AS namespace,
+            json_each(namespace.value -> 'sources') AS relation
+        WHERE relation.value ->> 'query' IS NOT NULL
+        LIMIT 1;
+
+        IF FOUND THEN
+            RAISE EXCEPTION 'the source of ''%#%'' is a query, which only members of pgfga_admin can write',
+                source.namespace, source.relation
+                USING ERRCODE = 'PF021';
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER schema_reject_query_sources
+        BEFORE INSERT OR UPDATE OF schema ON pgfga.schema
+        FOR EACH ROW EXECUTE FUNCTION pgfga.reject_query_sources();
+
+    -- Archived schemas can be read, but not written to.
+    CREATE FUNCTION pgfga.reject_archived_schema() RETURNS TRIGGER AS $$
+    BEGIN
+        IF EXISTS (SELECT 1 FROM pgfga.schema WHERE id = NEW.schema_id AND archived) THEN
+            RAISE EXCEPTION 'schema ''%'' is archived', NEW.schema_id
+                USING ERRCODE = 'PF008',
+                    HINT = 'Unarchive it with pgfga.archive_schema(id, false).';
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER tuple_reject_archived_schema
+        BEFORE INSERT OR UPDATE OF schema_id ON pgfga.tuple
+        FOR EACH ROW EXECUTE FUNCTION pgfga.reject_archived_schema();
+
+    -- The relations, e.g. group#member, whose transitive members are kept
+    -- in pgfga.group_closure, so that checks find them in a single read.
+    CREATE TABLE pgfga.closure_relation (
+        schema_id UUID NOT NULL REFERENCES pgfga.schema (id),
+        namespace VARCHAR(128) NOT NULL,
+        relation VARCHAR(128) NOT NULL,
+        PRIMARY KEY (schema_id, namespace, relation)
+    );
+
+    -- Every subject that is a member of a group, directly or through
+    -- unconditional, non-expiring tuples of nested groups.
+    CREATE TABLE pgfga.group_closure (
+        schema_id UUID NOT NULL,
+        group_namespace VARCHAR(128) NOT NULL,
+        group_id VARCHAR(128) NOT NULL,
+        relation VARCHAR(128) NOT NULL,
+        member_namespace VARCHAR(128) NOT NULL,
+        member_id VARCHAR(128) NOT NULL,
+        member_action VARCHAR(128) NOT NULL,
+        PRIMARY KEY (schema_id, group_namespace, group_id, relation, member_namespace, member_id, member_action),
+        FOREIGN KEY (schema_id, group_namespace, relation)
+            REFERENCES pgfga.closure_relation (schema_id, namespace, relation) ON DELETE CASCADE
+    );
+
+    CREATE INDEX group_closure_member_idx
+        ON pgfga.group_closure (schema_id, member_namespace, member_id, member_action);
+
+    CREATE FUNCTION pgfga.rebuild_group_closure(
+        p_schema_id UUID,
+        p_namespace TEXT,
+        p_id TEXT,
+        p_relation TEXT
+    ) RETURNS VOID AS $$
+        DELETE FROM pgfga.group_closure
+        WHERE schema_id = p_schema_id
+            AND group_namespace = p_namespace
+            AND group_id = p_id
+            AND relation = p_relation;
+
+        INSERT INTO pgfga.group_closure
+        WITH RECURSIVE member (namespace, id, action) AS (
+            SELECT subject_namespace, subject_id, subject_action
+            FROM pgfga.tuple
+            WHERE schema_id = p_schema_id
+                AND resource_namespace = p_namespace
+                AND resource_id = p_id
+                AND relation = p_relation
+                AND condition = ''
+                AND expires_at IS NULL
+        UNION
+            SELECT t.subject_namespace, t.subject_id, t.subject_action
+            FROM member m
+            JOIN pgfga.tuple t
+                ON t.schema_id = p_schema_id
+                AND t.resource_namespace = m.namespace
+                AND t.resource_id = m.id
+                AND t.relation = m.action
+            WHERE m.namespace = p_namespace
+                AND m.action = p_relation
+                AND t.condition = ''
+                AND t.expires_at IS NULL
+        )
+        SELECT p_schema_id, p_namespace, p_id, p_relation, namespace, id, action FROM member;
+    $$ LANGUAGE SQL;
+
+    -- The groups a tuple's group is nested in, along with the group itself.
+    CREATE FUNCTION pgfga.group_closure_ancestors(r pgfga.tuple)
+    RETURNS TABLE (group_id VARCHAR(128)) AS $$
+        SELECT r.resource_id
+        UNION
+        SELECT c.group_id FROM pgfga.group_closure c
+        WHERE c.schema_id = r.schema_id
+            AND c.group_namespace = r.resource_namespace
+            AND c.relation = r.relation
+            AND c.member_namespace = r.resource_namespace
+            AND c.member_id = r.resource_id
+            AND c.member_action = r.relation;
+    $$ LANGUAGE SQL STABLE;
+
+    -- A tuple's subject, along with its members if it is a nested group.
+    CREATE FUNCTION pgfga.group_closure_descendants(r pgfga.tuple)
+    RETURNS TABLE (namespace VARCHAR(128), id VARCHAR(128), action VARCHAR(128)) AS $$
+        SELECT r.subject_namespace, r.subject_id, r.subject_action
+        UNION
+        SELECT c.member_namespace, c.member_id, c.member_action FROM pgfga.group_closure c
+        WHERE c.schema_id = r.schema_id
+            AND c.group_namespace = r.resource_namespace
+            AND c.relation = r.relation
+            AND c.group_id = r.subject_id
+            AND r.subject_namespace = r.resource_namespace
+            AND r.subject_action = r.relation;
+    $$ LANGUAGE SQL STABLE;
+
+    -- A new tuple makes everything below it a member of everything above it.
+    CREATE FUNCTION pgfga.add_group_closure_edge(r pgfga.tuple) RETURNS VOID AS $$
+        INSERT INTO pgfga.group_closure
+        SELECT r.schema_id, r.resource_namespace, a.group_id, r.relation, d.namespace, d.id, d.action
+        FROM pgfga.group_closure_ancestors(r) AS a
+        CROSS JOIN pgfga.group_closure_descendants(r) AS d
+        ON CONFLICT DO NOTHING;
+    $$ LANGUAGE SQL;
+
+    -- Whether a tuple is part of a group closure. Conditional and expiring
+    -- tuples aren't, since their membership isn't fixed.
+    CREATE FUNCTION pgfga.is_group_closure_edge(r pgfga.tuple) RETURNS BOOLEAN AS $$
+        SELECT r.condition = '' AND r.expires_at IS NULL AND EXISTS (
+            SELECT 1 FROM pgfga.closure_relation
+            WHERE schema_id = r.schema_id
+                AND namespace = r.resource_namespace
+                AND relation = r.relation
+        );
+    $$ LANGUAGE SQL STABLE;
+
+    -- Maintain the group closures for a statement's tuples, given as the
+    -- transition tables old_tuples and new_tuples.
+    --
+    -- Only the memberships of everything below a removed tuple in everything
+    -- above it may have depended on it. The removed tuples of a statement are
+    -- handled together, so that the memberships that aren't suspect can be
+    -- relied on. A suspect membership is kept if it is still reachable:
+    -- through a remaining tuple whose group is the group itself, one of its
+    -- members that isn't suspect, or one found to be kept.
+    CREATE FUNCTION pgfga.maintain_group_closure() RETURNS TRIGGER AS $$
+    DECLARE
+        r pgfga.tuple;
+    BEGIN
+        IF TG_OP <> 'INSERT' THEN
+            WITH RECURSIVE suspect AS (
+                SELECT DISTINCT
+                    o.schema_id, o.resource_namespace AS group_namespace, a.group_id, o.relation,
+                    d.namespace, d.id, d.action
+                FROM old_tuples o
+                CROSS JOIN LATERAL pgfga.group_closure_ancestors(ROW(o.*)::pgfga.tuple) AS a
+                CROSS JOIN LATERAL pgfga.group_closure_descendants(ROW(o.*)::pgfga.tuple) AS d
+                WHERE pgfga.is_group_closure_edge(ROW(o.*)::pgfga.tuple)
+            ), kept AS (
+                SELECT s.*
+                FROM suspect s
+                JOIN pgfga.tuple t
+                    ON t.schema_id = s.schema_id
+                    AND t.resource_namespace = s.group_namespace
+                    AND t.relation = s.relation
+                    AND t.subject_namespace = s.namespace
+                    AND t.subject_id = s.id
+                    AND t.subject_action = s.action
+                    AND t.condition = ''
+                    AND t.expires_at IS NULL
+                WHERE t.resource_id = s.group_id
+                    OR EXISTS (
+                        SELECT 1 FROM pgfga.group_closure c
+                        WHERE c.schema_id = s.schema_id
+                            AND c.group_namespace = s.group_namespace
+                            AND c.group_id = s.group_id
+                            AND c.relation = s.relation
+                            AND c.member_namespace = s.group_namespace
+                            AND c.member_id = t.resource_id
+                            AND c.member_action = s.relation
+                            AND NOT EXISTS (
+                                SELECT 1 FROM suspect s2
+                                WHERE s2.schema_id = c.schema_id
+                                    AND s2.group_namespace = c.group_namespace
+                                    AND s2.group_id = c.group_id
+                                    AND s2.relation = c.relation
+                                    AND s2.namespace = c.member_namespace
+                                    AND s2.id = c.member_id
+                                    AND s2.action = c.member_action
+                            )
+                    )
+            UNION
+                SELECT s.*
+                FROM kept k
+                JOIN pgfga.tuple t
+                    ON t.schema_id = k.schema_id
+                    AND t.resource_namespace = k.group_namespace
+                    AND t.resource_id = k.id
+                    AND t.relation = k.relation
+                    AND t.condition = ''
+                    AND t.expires_at IS NULL
+                JOIN suspect s
+                    ON s.schema_id = k.schema_id
+                    AND s.group_namespace = k.group_namespace
+                    AND s.group_id = k.group_id
+                    AND s.relation = k.relation
+                    AND s.namespace = t.subject_namespace
+                    AND s.id = t.subject_id
+                    AND s.action = t.subject_action
+                WHERE k.namespace = k.group_namespace
+                    AND k.action = k.relation
+            )
+            DELETE FROM pgfga.group_closure c
+            USING suspect s
+            WHERE c.schema_id = s.schema_id
+                AND c.group_namespace = s.group_namespace
+                AND c.group_id = s.group_id
+                AND c.relation = s.relation
+                AND c.member_namespace = s.namespace
+                AND c.member_id = s.id
+                AND c.member_action = s.action
+                AND NOT EXISTS (
+                    SELECT 1 FROM kept k
+                    WHERE k.schema_id = s.schema_id
+                        AND k.group_namespace = s.group_namespace
+                        AND k.group_id = s.group_id
+                        AND k.relation = s.relation
+                        AND k.namespace = s.namespace
+                        AND k.id = s.id
+                        AND k.action = s.action
+                );
+        END IF;
+
+        IF TG_OP <> 'DELETE' THEN
+            FOR r IN SELECT * FROM new_tuples LOOP
+                IF pgfga.is_group_closure_edge(r) THEN
+                    PERFORM pgfga.add_group_closure_edge(r);
+                END IF;
+            END LOOP;
+        END IF;
+
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    -- Transition tables can only be had by triggers of a single event.
+    CREATE TRIGGER tuple_insert_group_closure
+        AFTER INSERT ON pgfga.tuple
+        REFERENCING NEW TABLE AS new_tuples
+        FOR EACH STATEMENT EXECUTE FUNCTION pgfga.maintain_group_closure();
+
+    CREATE TRIGGER tuple_update_group_closure
+        AFTER UPDATE ON pgfga.tuple
+        REFERENCING OLD TABLE AS old_tuples NEW TABLE AS new_tuples
+        FOR EACH STATEMENT EXECUTE FUNCTION pgfga.maintain_group_closure();
+
+    CREATE TRIGGER tuple_delete_group_closure
+        AFTER DELETE ON pgfga.tuple
+        REFERENCING OLD TABLE AS old_tuples
+        FOR EACH STATEMENT EXECUTE FUNCTION pgfga.maintain_group_closure();
+
+    -- Mirror the rows of an application table as tuples. Installed by
+    -- pgfga.sync_relation, with the arguments schema, resource_namespace,
+    -- resource_id_col, relation, subject_namespace, subject_id_col, and
+    -- subject_action. Ids are cast to text, as the backfill does.
+    CREATE FUNCTION pgfga.sync_tuple() RETURNS TRIGGER AS $$
+    DECLARE
+        old_resource_id TEXT;
+        old_subject_id TEXT;
+        new_resource_id TEXT;
+        new_subject_id TEXT;
+        still_present BOOLEAN;
+    BEGIN
+        IF TG_OP <> 'INSERT' THEN
+            EXECUTE format('SELECT ($1).%I::text, ($1).%I::text', TG_ARGV[2], TG_ARGV[5])
+                INTO old_resource_id, old_subject_id USING OLD;
+        END IF;
+        IF TG_OP <> 'DELETE' THEN
+            EXECUTE format('SELECT ($1).%I::text, ($1).%I::text', TG_ARGV[2], TG_ARGV[5])
+                INTO new_resource_id, new_subject_id USING NEW;
+        END IF;
+
+        IF old_resource_id IS NOT DISTINCT FROM new_resource_id
+            AND old_subject_id IS NOT DISTINCT FROM new_subject_id THEN
+            RETURN NULL;
+        END IF;
+
+        -- Another row may still call for the old tuple.
+        IF old_resource_id IS NOT NULL AND old_subject_id IS NOT NULL THEN
+            EXECUTE format(
+                'SELECT EXISTS (SELECT 1 FROM %s WHERE %I::text = $1 AND %I::text = $2)',
+                TG_RELID::regclass, TG_ARGV[2], TG_ARGV[5]
+            ) INTO still_present USING old_resource_id, old_subject_id;
+
+            IF NOT still_present THEN
+                PERFORM pgfga.delete_tuple(
+                    TG_ARGV[0], TG_ARGV[1], old_resource_id, TG_ARGV[3],
+                    TG_ARGV[4], old_subject_id, TG_ARGV[6]
+                );
+            END IF;
+        END IF;
+
+        IF new_resource_id IS NOT NULL AND new_subject_id IS NOT NULL THEN
+            PERFORM pgfga.create_tuple(
+                TG_ARGV[0], TG_ARGV[1], new_resource_id, TG_ARGV[3],
+                TG_ARGV[4], new_subject_id, TG_ARGV[6]
+            );
+        END IF;
+
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    -- The decisions made by checks while pgfga.decision_log is on. Rows
+    -- outlive their schema, so that decisions can be audited after the fact.
+    CREATE TABLE pgfga.decision_log (
+        rowid BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+        logged_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+        schema_id UUID NOT NULL,
+        request JSONB NOT NULL,
+        allowed BOOLEAN NOT NULL,
+        evaluation_time INTERVAL NOT NULL,
+        depth INTEGER NOT NULL,
+        username NAME DEFAULT CURRENT_USER NOT NULL
+    );
+
+    CREATE INDEX decision_log_logged_at_idx ON pgfga.decision_log (logged_at);
+
+    -- Checks log their decisions through this function, which runs as the
+    -- owner of the extension, so that the roles doing them need no
+    -- privileges on the log. The user is the session's rather than one
+    -- given by the caller, so it can't be forged.
+    CREATE FUNCTION pgfga.log_decision(
+        schema_id UUID,
+        request JSONB,
+        allowed BOOLEAN,
+        evaluation_time INTERVAL,
+        depth INTEGER
+    ) RETURNS VOID AS $$
+        INSERT INTO pgfga.decision_log (schema_id, request, allowed, evaluation_time, depth, username)
+        VALUES ($1, $2, $3, $4, $5, session_user);
+    $$ LANGUAGE sql SECURITY DEFINER SET search_path = pg_catalog, pg_temp;
+
+    -- The log is an audit trail, so only members of pgfga_admin can change
+    -- or delete its rows, even where others have been granted UPDATE or
+    -- DELETE.
+    CREATE FUNCTION pgfga.reject_decision_log_change() RETURNS TRIGGER AS $$
+    BEGIN
+        IF NOT pg_has_role('pgfga_admin', 'MEMBER') THEN
+            RAISE EXCEPTION 'only members of pgfga_admin can modify pgfga.decision_log'
+                USING ERRCODE = 'PF022';
+        END IF;
+        RETURN NULL;
+    END;
+    $$ LANGUAGE plpgsql;
+
+    CREATE TRIGGER decision_log_reject_change
+        BEFORE UPDATE OR DELETE OR TRUNCATE ON pgfga.decision_log
+        FOR EACH STATEMENT EXECUTE FUNCTION pgfga.reject_decision_log_change();
+
+    -- A role only sees the stores it is a member of the owner of, along with
+    -- their schemas and tuples. Schemas without a store are visible to all.
+    ALTER TABLE pgfga.store ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY store_isolation ON pgfga.store
+        USING (pg_has_role(owner, 'MEMBER'));
+
+    ALTER TABLE pgfga.schema ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY schema_isolation ON pgfga.schema
+        USING (store_id IS NULL OR store_id IN (SELECT id FROM pgfga.store));
+
+    ALTER TABLE pgfga.current_schema ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY current_schema_isolation ON pgfga.current_schema
+        USING (schema_id IN (SELECT id FROM pgfga.schema));
+
+    ALTER TABLE pgfga.tuple ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY tuple_isolation ON pgfga.tuple
+        USING (schema_id IN (SELECT id FROM pgfga.schema));
+
+    ALTER TABLE pgfga.closure_relation ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY closure_relation_isolation ON pgfga.closure_relation
+        USING (schema_id IN (SELECT id FROM pgfga.schema));
+
+    ALTER TABLE pgfga.group_closure ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY group_closure_isolation ON pgfga.group_closure
+        USING (schema_id IN (SELECT id FROM pgfga.schema));
+
+    ALTER TABLE pgfga.decision_log ENABLE ROW LEVEL SECURITY;
+    CREATE POLICY decision_log_isolation ON pgfga.decision_log
+        USING (schema_id IN (SELECT id FROM pgfga.schema));
+
+    -- Tables created by an extension are left out of pg_dump unless they are
+    -- marked as configuration tables, as are the identity sequences of their
+    -- rowids. Their rowids are restored as they were, as COPY writes identity
+    -- columns as given. Restore with triggers disabled (--disable-triggers),
+    -- so that tuples of archived schemas are accepted and group closures
+    -- aren't rebuilt on top of their restored rows.
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.store', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.schema', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.current_schema', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.tuple', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.closure_relation', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.group_closure', '');
+    SELECT pg_catalog.pg_extension_config_dump('pgfga.decision_log', '');
+    SELECT pg_catalog.pg_extension_config_dump(pg_get_serial_sequence('pgfga.store', 'rowid'), '');
+    SELECT pg_catalog.pg_extension_config_dump(pg_get_serial_sequence('pgfga.schema', 'rowid'), '');
+    SELECT pg_catalog.pg_extension_config_dump(pg_get_serial_sequence('pgfga.tuple', 'rowid'), '');
+    SELECT pg_catalog.pg_extension_config_dump(pg_get_serial_sequence('pgfga.decision_log', 'rowid'), '');
+
     -- TODO: add indices!
 
     "#,
@@ -38,8 +538,75 @@ extension_sql!(
 );
 
 #[pg_extern]
-fn create_schema(schema: pgrx::Json) -> Result<Option<pgrx::Uuid>, PgFgaError> {
-    Spi::connect(|client| Storage::new(client).create_schema(schema))
+fn create_store(
+    name: &str,
+    owner: default!(Option<&str>, "NULL"),
+) -> Result<Option<pgrx::Uuid>, ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).create_store(name, owner)
+    })?)
+}
+
+#[pg_extern]
+fn read_stores() -> Result<
+    TableIterator<
+        'static,
+        (
+            name!(rowid, i64),
+            name!(id, pgrx::Uuid),
+            name!(name, String),
+            name!(owner, String),
+            name!(created_at, pgrx::TimestampWithTimeZone),
+        ),
+    >,
+    ErrorReport,
+> {
+    let results: Vec<(i64, pgrx::Uuid, String, String, pgrx::TimestampWithTimeZone)> =
+        Spi::connect(|client| Storage::new(client).read_stores())?
+            .into_iter()
+            .map(|row| row.into())
+            .collect();
+
+    Ok(TableIterator::new(results))
+}
+
+#[pg_extern]
+fn delete_store(id: pgrx::Uuid) -> Result<i64, ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).delete_store(id)
+    })?)
+}
+
+#[pg_extern]
+fn create_schema(
+    schema: pgrx::Json,
+    store_id: default!(Option<pgrx::Uuid>, "NULL"),
+    name: default!(Option<&str>, "NULL"),
+) -> Result<Option<pgrx::Uuid>, ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).create_schema(schema, store_id, name)
+    })?)
+}
+
+#[pg_extern]
+fn archive_schema(id: pgrx::Uuid, archived: default!(bool, true)) -> Result<(), ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).archive_schema(id, archived)
+    })?)
+}
+
+#[pg_extern]
+fn delete_schema(id: pgrx::Uuid, cascade: default!(bool, false)) -> Result<i64, ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).delete_schema(id, cascade)
+    })?)
+}
+
+#[pg_extern]
+fn set_current_schema(name: &str, id: pgrx::Uuid) -> Result<(), ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).set_current_schema(name, id)
+    })?)
 }
 
 #[pg_extern]
@@ -53,15 +620,27 @@ fn read_schema(
             name!(id, pgrx::Uuid),
             name!(schema, pgrx::Json),
             name!(created_at, pgrx::TimestampWithTimeZone),
+            name!(store_id, Option<pgrx::Uuid>),
+            name!(name, Option<String>),
+            name!(version, Option<i32>),
+            name!(archived, bool),
         ),
     >,
-    PgFgaError,
+    ErrorReport,
 > {
-    let results: Vec<(i64, pgrx::Uuid, pgrx::Json, pgrx::TimestampWithTimeZone)> =
-        Spi::connect(|client| Storage::new(client).read_schemas(Some(id)))?
-            .into_iter()
-            .map(|row| row.into())
-            .collect();
+    let results: Vec<(
+        i64,
+        pgrx::Uuid,
+        pgrx::Json,
+        pgrx::TimestampWithTimeZone,
+        Option<pgrx::Uuid>,
+        Option<String>,
+        Option<i32>,
+        bool,
+    )> = Spi::connect(|client| Storage::new(client).read_schemas(Some(id)))?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
 
     Ok(TableIterator::new(results))
 }
@@ -75,15 +654,27 @@ fn read_schemas() -> Result<
             name!(id, pgrx::Uuid),
             name!(schema, pgrx::Json),
             name!(created_at, pgrx::TimestampWithTimeZone),
+            name!(store_id, Option<pgrx::Uuid>),
+            name!(name, Option<String>),
+            name!(version, Option<i32>),
+            name!(archived, bool),
         ),
     >,
-    PgFgaError,
+    ErrorReport,
 > {
-    let results: Vec<(i64, pgrx::Uuid, pgrx::Json, pgrx::TimestampWithTimeZone)> =
-        Spi::connect(|client| Storage::new(client).read_schemas(None))?
-            .into_iter()
-            .map(|row| row.into())
-            .collect();
+    let results: Vec<(
+        i64,
+        pgrx::Uuid,
+        pgrx::Json,
+        pgrx::TimestampWithTimeZone,
+        Option<pgrx::Uuid>,
+        Option<String>,
+        Option<i32>,
+        bool,
+    )> = Spi::connect(|client| Storage::new(client).read_schemas(None))?
+        .into_iter()
+        .map(|row| row.into())
+        .collect();
 
     Ok(TableIterator::new(results))
 }
@@ -97,8 +688,11 @@ fn create_tuple(
     subject_namespace: &str,
     subject_id: &str,
     subject_action: default!(&str, "''"),
-) -> Result<i64, PgFgaError> {
-    Spi::connect(|client| {
+    expires_at: default!(Option<pgrx::TimestampWithTimeZone>, "NULL"),
+    condition: default!(&str, "''"),
+    condition_context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<i64, ErrorReport> {
+    Ok(Spi::connect(|client| {
         Storage::new(client).create_tuple(
             schema_id,
             resource_namespace,
@@ -107,8 +701,40 @@ fn create_tuple(
             subject_namespace,
             subject_id,
             subject_action,
+            expires_at,
+            condition,
+            condition_context,
         )
-    })
+    })?)
+}
+
+/// Like `create_tuple`, but writes to the current version of the named
+/// schema.
+#[pg_extern(name = "create_tuple")]
+fn create_tuple_by_name(
+    schema: &str,
+    resource_namespace: &str,
+    resource_id: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: default!(&str, "''"),
+    expires_at: default!(Option<pgrx::TimestampWithTimeZone>, "NULL"),
+    condition: default!(&str, "''"),
+    condition_context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<i64, ErrorReport> {
+    create_tuple(
+        resolve_schema(schema)?,
+        resource_namespace,
+        resource_id,
+        relation,
+        subject_namespace,
+        subject_id,
+        subject_action,
+        expires_at,
+        condition,
+        condition_context,
+    )
 }
 
 #[pg_extern]
@@ -134,7 +760,7 @@ fn read_tuples(
             name!(subject_action, String),
         ),
     >,
-    PgFgaError,
+    ErrorReport,
 > {
     let result: Vec<(
         i64,
@@ -172,8 +798,8 @@ fn delete_tuple(
     subject_namespace: &str,
     subject_id: &str,
     subject_action: default!(&str, "''"),
-) -> Result<i64, PgFgaError> {
-    Spi::connect(|client| {
+) -> Result<i64, ErrorReport> {
+    Ok(Spi::connect(|client| {
         Storage::new(client).delete_tuple(
             schema_id,
             resource_namespace,
@@ -183,7 +809,30 @@ fn delete_tuple(
             subject_id,
             subject_action,
         )
-    })
+    })?)
+}
+
+/// Like `delete_tuple`, but deletes from the current version of the named
+/// schema.
+#[pg_extern(name = "delete_tuple")]
+fn delete_tuple_by_name(
+    schema: &str,
+    resource_namespace: &str,
+    resource_id: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: default!(&str, "''"),
+) -> Result<i64, ErrorReport> {
+    delete_tuple(
+        resolve_schema(schema)?,
+        resource_namespace,
+        resource_id,
+        relation,
+        subject_namespace,
+        subject_id,
+        subject_action,
+    )
 }
 
 #[pg_extern]
@@ -195,104 +844,846 @@ fn check(
     subject_namespace: &str,
     subject_id: &str,
     subject_action: default!(&str, "''"),
-) -> Result<bool, PgFgaError> {
+    context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<bool, ErrorReport> {
+    let context = check_context(context)?;
+
+    let request = decision_log::sampled().then(|| {
+        serde_json::json!({
+            "resource_namespace": resource_namespace,
+            "resource_id": resource_id,
+            "action": action,
+            "subject_namespace": subject_namespace,
+            "subject_id": subject_id,
+            "subject_action": subject_action,
+            "context": context,
+        })
+    });
+    let started = Instant::now();
+
+    let (allowed, depth) = check_unlogged(
+        schema_id,
+        resource_namespace,
+        resource_id,
+        action,
+        subject_namespace,
+        subject_id,
+        subject_action,
+        context,
+    )?;
+
+    if let Some(request) = request {
+        decision_log::log(&decision_log::Decision {
+            schema_id,
+            request,
+            allowed,
+            duration: started.elapsed(),
+            depth,
+        })?;
+    }
+
+    Ok(allowed)
+}
+
+/// Like `check`, but without logging the decision. Returns the deepest the
+/// check recursed along with whether it is allowed.
+fn check_unlogged(
+    schema_id: pgrx::Uuid,
+    resource_namespace: &str,
+    resource_id: &str,
+    action: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: &str,
+    context: serde_json::Map<String, serde_json::Value>,
+) -> Result<(bool, i64), PgFgaError> {
     Spi::connect(|client| {
-        Checker::new(Storage::new(client), schema_id)?.check(
+        let checker = check::checker(Storage::new(client), schema_id, context)?;
+        let allowed = checker.check(
             resource_namespace,
             resource_id,
             action,
             subject_namespace,
             subject_id,
             subject_action,
-        )
+        )?;
+
+        Ok((allowed, checker.depth_reached()))
     })
 }
 
-#[cfg(any(test, feature = "pg_test"))]
-#[pg_schema]
-mod tests {
-    use super::*;
-    use serde_json::json;
-    use uuid;
-
-    #[pg_test]
-    fn test_cannot_create_invalid_schema() {
-        let err = create_schema(pgrx::Json(json!({"foo":"bar"}))).unwrap_err();
-        assert!(matches!(err, PgFgaError::SerdeError(_)));
-    }
+/// Like `check`, but checks against the current version of the named schema.
+#[pg_extern(name = "check")]
+fn check_by_name(
+    schema: &str,
+    resource_namespace: &str,
+    resource_id: &str,
+    action: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: default!(&str, "''"),
+    context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<bool, ErrorReport> {
+    check(
+        resolve_schema(schema)?,
+        resource_namespace,
+        resource_id,
+        action,
+        subject_namespace,
+        subject_id,
+        subject_action,
+        context,
+    )
+}
 
-    #[pg_test]
-    fn test_create_and_read_schema() {
-        let schema = json!({"namespaces":{"user":{"relations": {}, "permissions": {}}}});
-        let id = create_schema(pgrx::Json(schema.clone())).unwrap().unwrap();
+/// Like `create_tuple`, but takes the tuple as a `pgfga.tuple_key`, e.g.
+/// `'document:1#viewer@user:anya'`.
+#[pg_extern(name = "create_tuple")]
+fn create_tuple_by_key(
+    schema_id: pgrx::Uuid,
+    tuple: tuple_key,
+    expires_at: default!(Option<pgrx::TimestampWithTimeZone>, "NULL"),
+    condition: default!(&str, "''"),
+    condition_context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<i64, ErrorReport> {
+    create_tuple(
+        schema_id,
+        &tuple.object.namespace,
+        &tuple.object.id,
+        &tuple.relation,
+        &tuple.subject.namespace,
+        &tuple.subject.id,
+        &tuple.subject.action,
+        expires_at,
+        condition,
+        condition_context,
+    )
+}
 
-        let mut iter = read_schema(id).unwrap();
-        let (_, got_id, got_schema, _) = iter.next().unwrap();
+/// Like `read_tuples`, but reads the tuples of an object, optionally only
+/// those of a relation.
+#[pg_extern(name = "read_tuples")]
+fn read_tuples_by_object(
+    schema_id: pgrx::Uuid,
+    object: object_ref,
+    relation: default!(&str, "''"),
+) -> Result<
+    TableIterator<
+        'static,
+        (
+            name!(rowid, i64),
+            name!(schema_id, pgrx::Uuid),
+            name!(resource_namespace, String),
+            name!(resource_id, String),
+            name!(relation, String),
+            name!(subject_namespace, String),
+            name!(subject_id, String),
+            name!(subject_action, String),
+        ),
+    >,
+    ErrorReport,
+> {
+    read_tuples(
+        schema_id,
+        &object.namespace,
+        &object.id,
+        relation,
+        "",
+        "",
+        "",
+    )
+}
 
-        assert_eq!(got_id, id);
-        assert_eq!(got_schema.0, schema);
-        assert!(iter.next().is_none());
-    }
+/// Like `check`, but takes the object and subject as a `pgfga.object_ref` and
+/// `pgfga.subject_ref`, e.g. `'document:1'` and `'user:anya'`.
+#[pg_extern(name = "check")]
+fn check_by_ref(
+    schema_id: pgrx::Uuid,
+    object: object_ref,
+    action: &str,
+    subject: subject_ref,
+    context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<bool, ErrorReport> {
+    check(
+        schema_id,
+        &object.namespace,
+        &object.id,
+        action,
+        &subject.namespace,
+        &subject.id,
+        &subject.action,
+        context,
+    )
+}
 
-    #[pg_test]
-    fn test_create_and_read_schemas() {
-        let schema = json!({"namespaces":{"user":{"relations": {}, "permissions": {}}}});
-        let id1 = create_schema(pgrx::Json(schema.clone())).unwrap().unwrap();
-        let id2 = create_schema(pgrx::Json(schema.clone())).unwrap().unwrap();
+/// Parse a tuple of the form `namespace:id#relation@namespace:id#action` into
+/// its parts.
+#[pg_extern(immutable, parallel_safe)]
+fn parse_tuple(
+    tuple: &str,
+) -> Result<
+    TableIterator<
+        'static,
+        (
+            name!(resource_namespace, String),
+            name!(resource_id, String),
+            name!(relation, String),
+            name!(subject_namespace, String),
+            name!(subject_id, String),
+            name!(subject_action, String),
+        ),
+    >,
+    ErrorReport,
+> {
+    let tuple = tuple_key::parse(tuple).map_err(PgFgaError::from)?;
 
-        let mut iter = read_schemas().unwrap();
-        let (_, got_id1, got_schema1, _) = iter.next().unwrap();
+    Ok(TableIterator::once((
+        tuple.object.namespace,
+        tuple.object.id,
+        tuple.relation,
+        tuple.subject.namespace,
+        tuple.subject.id,
+        tuple.subject.action,
+    )))
+}
 
-        assert_eq!(got_id1, id1);
-        assert_eq!(got_schema1.0, schema);
+/// The inverse of `parse_tuple`.
+#[pg_extern(immutable, parallel_safe)]
+fn format_tuple(
+    resource_namespace: &str,
+    resource_id: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: default!(&str, "''"),
+) -> String {
+    tuple_key {
+        object: object_ref {
+            namespace: resource_namespace.to_string(),
+            id: resource_id.to_string(),
+        },
+        relation: relation.to_string(),
+        subject: subject_ref {
+            namespace: subject_namespace.to_string(),
+            id: subject_id.to_string(),
+            action: subject_action.to_string(),
+        },
+    }
+    .to_string()
+}
 
-        let (_, got_id2, got_schema2, _) = iter.next().unwrap();
+/// Like `create_tuple`, but takes the tuple as text, e.g. straight from a log.
+/// `schema` is either a schema id or name.
+#[pg_extern]
+fn create_tuple_str(
+    schema: &str,
+    tuple: &str,
+    expires_at: default!(Option<pgrx::TimestampWithTimeZone>, "NULL"),
+    condition: default!(&str, "''"),
+    condition_context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<i64, ErrorReport> {
+    let tuple = tuple_key::parse(tuple).map_err(PgFgaError::from)?;
 
-        assert_eq!(got_id2, id2);
-        assert_eq!(got_schema2.0, schema);
+    create_tuple_by_key(
+        resolve_schema(schema)?,
+        tuple,
+        expires_at,
+        condition,
+        condition_context,
+    )
+}
 
-        assert!(iter.next().is_none());
-    }
+/// Like `check`, but takes the check as a tuple, e.g.
+/// `document:1#viewer@user:anya`. `schema` is either a schema id or name.
+#[pg_extern]
+fn check_str(
+    schema: &str,
+    tuple: &str,
+    context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<bool, ErrorReport> {
+    let tuple = tuple_key::parse(tuple).map_err(PgFgaError::from)?;
 
-    #[pg_test]
-    fn test_cannot_create_tuple_on_nonexistant_schema() {
-        let schema_id = pgrx::Uuid::from_bytes(uuid::Uuid::new_v4().into_bytes());
+    check_by_ref(
+        resolve_schema(schema)?,
+        tuple.object,
+        &tuple.relation,
+        tuple.subject,
+        context,
+    )
+}
 
-        let err =
-            create_tuple(schema_id, "document", "1", "viewer", "user", "anya", "").unwrap_err();
-        assert!(matches!(err, PgFgaError::UnknownSchemaId(_)));
-    }
+/// Create a row-level security policy on `table` that only lets through the
+/// rows whose `id_column` is the id of a `namespace` object the subject has
+/// `action` on. `subject_expr` is an SQL expression for the subject, e.g.
+/// `'user:' || current_user`. A set-based policy uses `accessible_resources`
+/// rather than `check_rls`.
+#[pg_extern]
+fn create_rls_policy(
+    table: pgrx::PgRelation,
+    schema: &str,
+    id_column: &str,
+    namespace: &str,
+    action: &str,
+    subject_expr: &str,
+    command: default!(&str, "'ALL'"),
+    policy_name: default!(Option<&str>, "NULL"),
+    set_based: default!(bool, false),
+) -> Result<String, ErrorReport> {
+    let policy = rls::Policy {
+        name: policy_name,
+        schema,
+        id_column,
+        namespace,
+        action,
+        subject_expr,
+        command,
+        set_based,
+    };
 
-    #[pg_test]
-    fn test_create_duplicate_tuple_returns_zero() {
-        let id = create_schema(pgrx::Json(
-            json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}}),
-        ))
-        .unwrap()
-        .unwrap();
+    Ok(policy.create(&table)?)
+}
 
-        let first = create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
-        assert_eq!(first, 1);
+/// The check used by `create_rls_policy`. It is not leakproof, as its errors
+/// mention its arguments, so Postgres evaluates it before any other
+/// conditions of a query. It never logs its decisions, as it couldn't be
+/// stable and parallel safe if it wrote to the decision log.
+#[pg_extern(stable, parallel_safe)]
+fn check_rls(
+    schema: &str,
+    resource_namespace: &str,
+    resource_id: &str,
+    action: &str,
+    subject: &str,
+) -> Result<bool, ErrorReport> {
+    let subject = subject_ref::parse(subject).map_err(PgFgaError::from)?;
 
-        let second = create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
-        assert_eq!(second, 0);
-    }
+    let (allowed, _) = check_unlogged(
+        resolve_schema(schema)?,
+        resource_namespace,
+        resource_id,
+        action,
+        &subject.namespace,
+        &subject.id,
+        &subject.action,
+        serde_json::Map::new(),
+    )?;
 
-    #[pg_test]
-    fn test_read_tuples_works() {
-        let id = create_schema(pgrx::Json(
-            json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}}),
-        ))
-        .unwrap()
-        .unwrap();
+    Ok(allowed)
+}
 
-        let tup1 = ("document", "1", "parent", "folder", "x", "");
-        let tup2 = ("folder", "x", "viewer", "user", "anya", "");
+/// The ids of the resources of `resource_namespace` the subject has `action`
+/// on. Rather than checking resources one at a time, the permission is
+/// compiled into a single query, so it is much faster than `check` in an RLS
+/// policy over a large table.
+#[pg_extern(stable, parallel_safe)]
+fn accessible_resources(
+    schema: &str,
+    resource_namespace: &str,
+    action: &str,
+    subject: &str,
+    context: default!(Option<pgrx::JsonB>, "NULL"),
+) -> Result<SetOfIterator<'static, String>, ErrorReport> {
+    let schema_id = resolve_schema(schema)?;
+    let subject = subject_ref::parse(subject).map_err(PgFgaError::from)?;
+    let context = check_context(context)?;
 
-        create_tuple(id, tup1.0, tup1.1, tup1.2, tup1.3, tup1.4, tup1.5).unwrap();
-        create_tuple(id, tup2.0, tup2.1, tup2.2, tup2.3, tup2.4, tup2.5).unwrap();
+    let ids = Spi::connect(|client| {
+        let checker = check::checker(Storage::new(client), schema_id, context)?;
 
-        // Read all the tuples.
+        check::accessible_resources(
+            &checker,
+            resource_namespace,
+            action,
+            &subject.namespace,
+            &subject.id,
+            &subject.action,
+        )
+    })?;
+
+    Ok(SetOfIterator::new(ids))
+}
+
+/// Keep the transitive members of a relation, e.g. `group#member`, in
+/// `pgfga.group_closure`, so that checks can find them in a single read.
+#[pg_extern]
+fn enable_group_closure(schema: &str, namespace: &str, relation: &str) -> Result<i64, ErrorReport> {
+    let schema_id = resolve_schema(schema)?;
+
+    Ok(Spi::connect(|client| {
+        Storage::new(client).enable_group_closure(schema_id, namespace, relation)
+    })?)
+}
+
+#[pg_extern]
+fn disable_group_closure(
+    schema: &str,
+    namespace: &str,
+    relation: &str,
+) -> Result<i64, ErrorReport> {
+    let schema_id = resolve_schema(schema)?;
+
+    Ok(Spi::connect(|client| {
+        Storage::new(client).disable_group_closure(schema_id, namespace, relation)
+    })?)
+}
+
+/// Keep the tuples of a relation in sync with the rows of an application
+/// table, e.g. `document:<id>#parent@folder:<folder_id>` for the rows of
+/// `documents`, by installing a trigger on it. Unless told otherwise, the
+/// tuples of the rows already in the table are created too.
+#[pg_extern]
+fn sync_relation(
+    table: pgrx::PgRelation,
+    schema: &str,
+    resource_namespace: &str,
+    resource_id_col: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id_col: &str,
+    subject_action: default!(&str, "''"),
+    backfill: default!(bool, true),
+) -> Result<i64, ErrorReport> {
+    let synced = sync::SyncedRelation {
+        schema,
+        resource_namespace,
+        resource_id_col,
+        relation,
+        subject_namespace,
+        subject_id_col,
+        subject_action,
+    };
+
+    synced.install(&table)?;
+
+    if backfill {
+        Ok(synced.backfill(&table)?)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Create the tuples of the rows already in an application table, as
+/// `sync_relation` does. Returns the number of tuples created.
+#[pg_extern]
+fn backfill_relation(
+    table: pgrx::PgRelation,
+    schema: &str,
+    resource_namespace: &str,
+    resource_id_col: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id_col: &str,
+    subject_action: default!(&str, "''"),
+) -> Result<i64, ErrorReport> {
+    let synced = sync::SyncedRelation {
+        schema,
+        resource_namespace,
+        resource_id_col,
+        relation,
+        subject_namespace,
+        subject_id_col,
+        subject_action,
+    };
+
+    Ok(synced.backfill(&table)?)
+}
+
+/// The context of a check, which must be a JSON object if given.
+fn check_context(
+    context: Option<pgrx::JsonB>,
+) -> Result<serde_json::Map<String, serde_json::Value>, PgFgaError> {
+    match context.map(|context| context.0) {
+        None | Some(serde_json::Value::Null) => Ok(serde_json::Map::new()),
+        Some(serde_json::Value::Object(context)) => Ok(context),
+        Some(_) => Err(PgFgaError::Public(
+            "context must be a JSON object".to_string(),
+        )),
+    }
+}
+
+/// Resolve a schema name to the id of its current version, in the store of
+/// `pgfga.store_id` if it is set. Quoted UUIDs are untyped literals, which
+/// Postgres resolves to the text overloads, so a UUID is accepted as is.
+fn resolve_schema(schema: &str) -> Result<pgrx::Uuid, ErrorReport> {
+    if let Ok(id) = uuid::Uuid::parse_str(schema) {
+        return Ok(pgrx::Uuid::from_bytes(id.into_bytes()));
+    }
+
+    let store_id = guc::store_id()?;
+
+    Spi::connect(|client| Storage::new(client).read_current_schema(store_id, schema))?
+        .ok_or_else(|| PgFgaError::UnknownSchemaName(schema.to_string()).into())
+}
+
+#[pg_extern]
+fn diff_schemas(
+    old_schema: pgrx::Uuid,
+    new_schema: pgrx::Uuid,
+) -> Result<pgrx::JsonB, ErrorReport> {
+    let diff =
+        Spi::connect(|client| diff::diff_schemas(&Storage::new(client), old_schema, new_schema))?;
+
+    Ok(pgrx::JsonB(
+        serde_json::to_value(diff).map_err(PgFgaError::from)?,
+    ))
+}
+
+/// Export a schema and all of its tuples as a single document, which
+/// `import_store` turns back into a new schema.
+#[pg_extern]
+fn export_store(schema_id: pgrx::Uuid) -> Result<pgrx::JsonB, ErrorReport> {
+    let export = Spi::connect(|client| export::export(&Storage::new(client), schema_id))?;
+
+    Ok(pgrx::JsonB(
+        serde_json::to_value(export).map_err(PgFgaError::from)?,
+    ))
+}
+
+/// Like `export_store`, but as NDJSON lines, the schema first and then a
+/// tuple per line, so that large exports can be streamed.
+#[pg_extern]
+fn export_store_ndjson(
+    schema_id: pgrx::Uuid,
+) -> Result<SetOfIterator<'static, String>, ErrorReport> {
+    Ok(SetOfIterator::new(export::NdjsonExport::new(schema_id)?))
+}
+
+/// Create a new schema, along with its tuples, from a document exported by
+/// `export_store`. Returns the id of the new schema.
+#[pg_extern]
+fn import_store(
+    document: pgrx::JsonB,
+    store_id: default!(Option<pgrx::Uuid>, "NULL"),
+    name: default!(Option<&str>, "NULL"),
+) -> Result<pgrx::Uuid, ErrorReport> {
+    let export = serde_json::from_value(document.0).map_err(PgFgaError::from)?;
+
+    Ok(Spi::connect(|client| {
+        export::import(&mut Storage::new(client), export, store_id, name)
+    })?)
+}
+
+/// Like `import_store`, but takes the NDJSON lines of `export_store_ndjson`.
+#[pg_extern]
+fn import_store_ndjson(
+    document: &str,
+    store_id: default!(Option<pgrx::Uuid>, "NULL"),
+    name: default!(Option<&str>, "NULL"),
+) -> Result<pgrx::Uuid, ErrorReport> {
+    let export = export::parse_ndjson(document)?;
+
+    Ok(Spi::connect(|client| {
+        export::import(&mut Storage::new(client), export, store_id, name)
+    })?)
+}
+
+/// Convert an OpenFGA authorization model, in its JSON format, into a schema.
+#[pg_extern(immutable, parallel_safe)]
+fn import_openfga_model(model: pgrx::JsonB) -> Result<pgrx::JsonB, ErrorReport> {
+    let schema = openfga::import_json(model.0).map_err(PgFgaError::from)?;
+
+    Ok(pgrx::JsonB(
+        serde_json::to_value(schema).map_err(PgFgaError::from)?,
+    ))
+}
+
+/// Like `import_openfga_model`, but takes the model in OpenFGA's DSL.
+#[pg_extern(immutable, parallel_safe)]
+fn import_openfga_dsl(model: &str) -> Result<pgrx::JsonB, ErrorReport> {
+    let schema = openfga::import_dsl(model).map_err(PgFgaError::from)?;
+
+    Ok(pgrx::JsonB(
+        serde_json::to_value(schema).map_err(PgFgaError::from)?,
+    ))
+}
+
+/// Convert a SpiceDB schema, written in its schema language, into a schema.
+#[pg_extern(immutable, parallel_safe)]
+fn import_spicedb_schema(schema: &str) -> Result<pgrx::JsonB, ErrorReport> {
+    let schema = spicedb::import_schema(schema).map_err(PgFgaError::from)?;
+
+    Ok(pgrx::JsonB(
+        serde_json::to_value(schema).map_err(PgFgaError::from)?,
+    ))
+}
+
+/// Create a tuple for each relationship of a SpiceDB relationship dump, one
+/// per line. `schema` is either a schema id or name. Returns the number of
+/// tuples created.
+#[pg_extern]
+fn load_spicedb_relationships(schema: &str, relationships: &str) -> Result<i64, ErrorReport> {
+    let schema_id = resolve_schema(schema)?;
+    let relationships = spicedb::parse_relationships(relationships).map_err(PgFgaError::from)?;
+
+    let mut num_created = 0;
+    for relationship in relationships {
+        let tuple = tuple_key::parse(relationship.tuple).map_err(|err| {
+            PgFgaError::from(schema::ImportError::Syntax(
+                relationship.line,
+                err.to_string(),
+            ))
+        })?;
+
+        let expires_at = match relationship.expiration {
+            None => None,
+            Some(expiration) => Spi::get_one_with_args::<pgrx::TimestampWithTimeZone>(
+                "SELECT $1::timestamptz",
+                vec![(PgBuiltInOids::TEXTOID.oid(), expiration.into_datum())],
+            )
+            .map_err(PgFgaError::from)?,
+        };
+
+        num_created += create_tuple_by_key(schema_id, tuple, expires_at, "", None)?;
+    }
+
+    Ok(num_created)
+}
+
+#[pg_extern]
+fn migrate_tuples(
+    from_schema: pgrx::Uuid,
+    to_schema: pgrx::Uuid,
+    dry_run: default!(bool, true),
+    relation_renames: default!(pgrx::JsonB, "'{}'"),
+    move_tuples: default!(bool, false),
+) -> Result<
+    TableIterator<
+        'static,
+        (
+            name!(resource_namespace, String),
+            name!(resource_id, String),
+            name!(relation, String),
+            name!(subject_namespace, String),
+            name!(subject_id, String),
+            name!(subject_action, String),
+            name!(error, Option<String>),
+        ),
+    >,
+    ErrorReport,
+> {
+    let renames: migrate::RelationRenames = serde_json::from_value(relation_renames.0)
+        .map_err(|err| PgFgaError::Public(format!("invalid relation_renames: {err}")))?;
+
+    let results: Vec<(
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+        Option<String>,
+    )> = Spi::connect(|client| {
+        migrate::migrate_tuples(
+            &mut Storage::new(client),
+            from_schema,
+            to_schema,
+            dry_run,
+            &renames,
+            move_tuples,
+        )
+    })?
+    .into_iter()
+    .map(|row| row.into())
+    .collect();
+
+    Ok(TableIterator::new(results))
+}
+
+#[pg_extern]
+fn run_cleanup() -> Result<TableIterator<'static, (name!(expired_tuples, i64),)>, ErrorReport> {
+    let report = Spi::connect(|client| worker::run_cycle(&mut Storage::new(client)))?;
+
+    Ok(TableIterator::once((report.expired_tuples,)))
+}
+
+/// Delete the decisions logged more than `older_than` ago. Returns the
+/// number of decisions deleted.
+#[pg_extern]
+fn purge_decision_log(older_than: pgrx::Interval) -> Result<i64, ErrorReport> {
+    Ok(Spi::connect(|client| {
+        Storage::new(client).purge_decision_log(older_than)
+    })?)
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use super::*;
+    use pgrx::pg_sys::panic::{CaughtError, ErrorReportWithLevel};
+    use serde_json::json;
+    use std::panic::AssertUnwindSafe;
+    use uuid;
+
+    /// Run `f` in a subtransaction, the way a PL/pgSQL `EXCEPTION` block
+    /// does, so that an error raised by Postgres can be handled without
+    /// leaving the transaction aborted.
+    fn in_subtransaction<R>(f: impl FnOnce() -> R) -> Result<R, CaughtError> {
+        let (context, owner) =
+            unsafe { (pg_sys::CurrentMemoryContext, pg_sys::CurrentResourceOwner) };
+
+        unsafe {
+            pg_sys::BeginInternalSubTransaction(std::ptr::null());
+            pg_sys::CurrentMemoryContext = context;
+        }
+
+        PgTryBuilder::new(AssertUnwindSafe(|| {
+            let result = f();
+            unsafe {
+                pg_sys::ReleaseCurrentSubTransaction();
+                pg_sys::CurrentMemoryContext = context;
+                pg_sys::CurrentResourceOwner = owner;
+            }
+            Ok(result)
+        }))
+        .catch_others(move |error| {
+            unsafe {
+                pg_sys::CurrentMemoryContext = context;
+                pg_sys::RollbackAndReleaseCurrentSubTransaction();
+                pg_sys::CurrentMemoryContext = context;
+                pg_sys::CurrentResourceOwner = owner;
+            }
+            Err(error)
+        })
+        .execute()
+    }
+
+    /// The error `f` raises.
+    fn error<R>(f: impl FnOnce() -> R) -> ErrorReportWithLevel {
+        match in_subtransaction(f) {
+            Err(CaughtError::PostgresError(report)) => report,
+            Err(err) => err.rethrow(),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    /// The SQLSTATE a query fails with, if it fails. It is read in PL/pgSQL,
+    /// since pgrx only knows the SQLSTATEs Postgres defines.
+    fn sqlstate(query: &str) -> Option<String> {
+        Spi::run(
+            "CREATE OR REPLACE FUNCTION pg_temp.sqlstate(query TEXT) RETURNS TEXT AS $$
+            BEGIN
+                EXECUTE query;
+                RETURN NULL;
+            EXCEPTION WHEN OTHERS THEN
+                RETURN SQLSTATE;
+            END
+            $$ LANGUAGE plpgsql",
+        )
+        .unwrap();
+
+        Spi::get_one_with_args(
+            "SELECT pg_temp.sqlstate($1)",
+            vec![(PgBuiltInOids::TEXTOID.oid(), query.into_datum())],
+        )
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_cannot_create_invalid_schema() {
+        let err = error(|| create_schema(pgrx::Json(json!({"foo":"bar"})), None, None));
+        assert!(err.message().starts_with("error (de)serializing schema"));
+    }
+
+    #[pg_test]
+    fn test_create_and_read_schema() {
+        let schema = json!({"namespaces":{"user":{"relations": {}, "permissions": {}}}});
+        let id = create_schema(pgrx::Json(schema.clone()), None, None)
+            .unwrap()
+            .unwrap();
+
+        let mut iter = read_schema(id).unwrap();
+        let (_, got_id, got_schema, ..) = iter.next().unwrap();
+
+        assert_eq!(got_id, id);
+        assert_eq!(got_schema.0, schema);
+        assert!(iter.next().is_none());
+    }
+
+    #[pg_test]
+    fn test_create_and_read_schemas() {
+        let schema = json!({"namespaces":{"user":{"relations": {}, "permissions": {}}}});
+        let id1 = create_schema(pgrx::Json(schema.clone()), None, None)
+            .unwrap()
+            .unwrap();
+        let id2 = create_schema(pgrx::Json(schema.clone()), None, None)
+            .unwrap()
+            .unwrap();
+
+        let mut iter = read_schemas().unwrap();
+        let (_, got_id1, got_schema1, ..) = iter.next().unwrap();
+
+        assert_eq!(got_id1, id1);
+        assert_eq!(got_schema1.0, schema);
+
+        let (_, got_id2, got_schema2, ..) = iter.next().unwrap();
+
+        assert_eq!(got_id2, id2);
+        assert_eq!(got_schema2.0, schema);
+
+        assert!(iter.next().is_none());
+    }
+
+    #[pg_test]
+    fn test_cannot_create_tuple_on_nonexistant_schema() {
+        let schema_id = pgrx::Uuid::from_bytes(uuid::Uuid::new_v4().into_bytes());
+
+        let err = error(|| {
+            create_tuple(
+                schema_id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            PgFgaError::UnknownSchemaId(schema_id).to_string()
+        );
+    }
+
+    #[pg_test]
+    fn test_create_duplicate_tuple_returns_zero() {
+        let id = create_schema(
+            pgrx::Json(json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}})),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let first = create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        assert_eq!(first, 1);
+
+        let second = create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        assert_eq!(second, 0);
+    }
+
+    #[pg_test]
+    fn test_read_tuples_works() {
+        let id = create_schema(
+            pgrx::Json(json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}})),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let tup1 = ("document", "1", "parent", "folder", "x", "");
+        let tup2 = ("folder", "x", "viewer", "user", "anya", "");
+
+        create_tuple(
+            id, tup1.0, tup1.1, tup1.2, tup1.3, tup1.4, tup1.5, None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, tup2.0, tup2.1, tup2.2, tup2.3, tup2.4, tup2.5, None, "", None,
+        )
+        .unwrap();
+
+        // Read all the tuples.
         let mut iter = read_tuples(id, "", "", "", "", "", "").unwrap();
         let (_, _, got_rns, got_rid, got_rel, got_sns, got_sid, got_sact) = iter.next().unwrap();
 
@@ -363,13 +1754,18 @@ mod tests {
 
     #[pg_test]
     fn test_delete_tuple_works() {
-        let id = create_schema(pgrx::Json(
-            json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}}),
-        ))
+        let id = create_schema(
+            pgrx::Json(json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}})),
+            None,
+            None,
+        )
         .unwrap()
         .unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
 
         let first = delete_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
         assert_eq!(first, 1);
@@ -383,6 +1779,118 @@ mod tests {
         assert!(iter.next().is_none())
     }
 
+    #[pg_test]
+    fn test_expired_tuples_are_ignored() {
+        let id = create_schema(
+            pgrx::Json(json!(
+                {"namespaces":{"user":{},"document":{"relations":{"viewer":[{"namespace":"user"}]}}}}
+            )),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let an_hour_ago =
+            Spi::get_one::<pgrx::TimestampWithTimeZone>("SELECT NOW() - INTERVAL '1 hour'")
+                .unwrap();
+
+        create_tuple(
+            id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            an_hour_ago,
+            "",
+            None,
+        )
+        .unwrap();
+
+        let should_be_false =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
+        assert!(!should_be_false);
+
+        let mut iter = read_tuples(id, "", "", "", "", "", "").unwrap();
+        assert!(iter.next().is_none());
+
+        // Recreating an expired tuple revives it.
+        let created = create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        assert_eq!(created, 1);
+
+        let should_be_true =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
+        assert!(should_be_true);
+    }
+
+    #[pg_test(
+        error = "update or delete on table \"schema\" violates foreign key constraint \"tuple_schema_id_fkey\" on table \"tuple\""
+    )]
+    fn test_cannot_delete_schema_out_from_under_tuples() {
+        let id = create_schema(
+            pgrx::Json(json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}})),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+
+        Spi::run("DELETE FROM pgfga.schema").unwrap();
+    }
+
+    #[pg_test]
+    fn test_run_cleanup_works() {
+        let id = create_schema(
+            pgrx::Json(json!({"namespaces":{"document":{"relations": {}, "permissions": {}}}})),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let an_hour_ago =
+            Spi::get_one::<pgrx::TimestampWithTimeZone>("SELECT NOW() - INTERVAL '1 hour'")
+                .unwrap();
+
+        create_tuple(
+            id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            an_hour_ago,
+            "",
+            None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "beatrix", "", None, "", None,
+        )
+        .unwrap();
+
+        let (expired,) = run_cleanup().unwrap().next().unwrap();
+        assert_eq!(expired, 1);
+
+        let remaining = Spi::get_one::<i64>("SELECT COUNT(*) FROM pgfga.tuple").unwrap();
+        assert_eq!(remaining, Some(1));
+
+        // A second cycle has nothing left to do.
+        let (expired,) = run_cleanup().unwrap().next().unwrap();
+        assert_eq!(expired, 0);
+    }
+
     //
     // Check tests
     //
@@ -406,17 +1914,23 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_true = check(id, "document", "1", "viewer", "user", "anya", "").unwrap();
+        let should_be_true =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
         assert!(should_be_true);
 
-        let should_be_false1 = check(id, "document", "2", "viewer", "user", "anya", "").unwrap();
+        let should_be_false1 =
+            check(id, "document", "2", "viewer", "user", "anya", "", None).unwrap();
         assert!(!should_be_false1);
 
-        let should_be_false2 = check(id, "document", "2", "viewer", "user", "beatrix", "").unwrap();
+        let should_be_false2 =
+            check(id, "document", "2", "viewer", "user", "beatrix", "", None).unwrap();
         assert!(!should_be_false2);
     }
 
@@ -444,18 +1958,23 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_true = check(id, "document", "1", "can_view", "user", "anya", "").unwrap();
+        let should_be_true =
+            check(id, "document", "1", "can_view", "user", "anya", "", None).unwrap();
         assert!(should_be_true);
 
-        let should_be_false1 = check(id, "document", "2", "can_view", "user", "anya", "").unwrap();
+        let should_be_false1 =
+            check(id, "document", "2", "can_view", "user", "anya", "", None).unwrap();
         assert!(!should_be_false1);
 
         let should_be_false2 =
-            check(id, "document", "2", "can_view", "user", "beatrix", "").unwrap();
+            check(id, "document", "2", "can_view", "user", "beatrix", "", None).unwrap();
         assert!(!should_be_false2);
     }
 
@@ -497,22 +2016,31 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "folder", "x", "viewer", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "1", "parent", "folder", "x", "").unwrap();
+        create_tuple(
+            id, "folder", "x", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "parent", "folder", "x", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_true1 = check(id, "folder", "x", "can_view", "user", "anya", "").unwrap();
+        let should_be_true1 =
+            check(id, "folder", "x", "can_view", "user", "anya", "", None).unwrap();
         assert!(should_be_true1);
 
-        let should_be_true2 = check(id, "document", "1", "can_view", "user", "anya", "").unwrap();
+        let should_be_true2 =
+            check(id, "document", "1", "can_view", "user", "anya", "", None).unwrap();
         assert!(should_be_true2);
 
-        let should_be_false1 = check(id, "document", "2", "can_view", "user", "anya", "").unwrap();
+        let should_be_false1 =
+            check(id, "document", "2", "can_view", "user", "anya", "", None).unwrap();
         assert!(!should_be_false1);
 
         let should_be_false2 =
-            check(id, "document", "2", "can_view", "user", "beatrix", "").unwrap();
+            check(id, "document", "2", "can_view", "user", "beatrix", "", None).unwrap();
         assert!(!should_be_false2);
     }
 
@@ -552,23 +2080,31 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "2", "editor", "user", "beatrix", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "2", "editor", "user", "beatrix", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_true1 = check(id, "document", "1", "can_view", "user", "anya", "").unwrap();
+        let should_be_true1 =
+            check(id, "document", "1", "can_view", "user", "anya", "", None).unwrap();
         assert!(should_be_true1);
 
         let should_be_false1 =
-            check(id, "document", "1", "can_view", "user", "beatrix", "").unwrap();
+            check(id, "document", "1", "can_view", "user", "beatrix", "", None).unwrap();
         assert!(!should_be_false1);
 
         let should_be_true2 =
-            check(id, "document", "2", "can_view", "user", "beatrix", "").unwrap();
+            check(id, "document", "2", "can_view", "user", "beatrix", "", None).unwrap();
         assert!(should_be_true2);
 
-        let should_be_false2 = check(id, "document", "2", "can_view", "user", "anya", "").unwrap();
+        let should_be_false2 =
+            check(id, "document", "2", "can_view", "user", "anya", "", None).unwrap();
         assert!(!should_be_false2);
     }
 
@@ -608,22 +2144,35 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "1", "editor", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "1", "viewer", "user", "beatrix", "").unwrap();
-        create_tuple(id, "document", "1", "editor", "user", "charlie", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "editor", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "beatrix", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "editor", "user", "charlie", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_true = check(id, "document", "1", "can_view", "user", "anya", "").unwrap();
+        let should_be_true =
+            check(id, "document", "1", "can_view", "user", "anya", "", None).unwrap();
         assert!(should_be_true);
 
         let should_be_false1 =
-            check(id, "document", "1", "can_view", "user", "beatrix", "").unwrap();
+            check(id, "document", "1", "can_view", "user", "beatrix", "", None).unwrap();
         assert!(!should_be_false1);
 
         let should_be_false2 =
-            check(id, "document", "1", "can_view", "user", "charlie", "").unwrap();
+            check(id, "document", "1", "can_view", "user", "charlie", "", None).unwrap();
         assert!(!should_be_false2);
     }
 
@@ -663,22 +2212,2161 @@ mod tests {
             }
         ));
 
-        let id = create_schema(schema).unwrap().unwrap();
+        let id = create_schema(schema, None, None).unwrap().unwrap();
 
-        create_tuple(id, "document", "1", "viewer", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "1", "editor", "user", "anya", "").unwrap();
-        create_tuple(id, "document", "1", "viewer", "user", "beatrix", "").unwrap();
-        create_tuple(id, "document", "1", "editor", "user", "charlie", "").unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "editor", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "beatrix", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "editor", "user", "charlie", "", None, "", None,
+        )
+        .unwrap();
 
-        let should_be_false1 = check(id, "document", "1", "can_view", "user", "anya", "").unwrap();
+        let should_be_false1 =
+            check(id, "document", "1", "can_view", "user", "anya", "", None).unwrap();
         assert!(!should_be_false1);
 
-        let should_be_true = check(id, "document", "1", "can_view", "user", "beatrix", "").unwrap();
+        let should_be_true =
+            check(id, "document", "1", "can_view", "user", "beatrix", "", None).unwrap();
+        assert!(should_be_true);
+
+        let should_be_false2 =
+            check(id, "document", "1", "can_view", "user", "charlie", "", None).unwrap();
+        assert!(!should_be_false2);
+    }
+
+    #[pg_test]
+    fn test_subject_set_of_another_namespace() {
+        let schema = pgrx::Json(json!(
+            {
+                "namespaces": {
+                    "user": {},
+                    "group": {
+                        "relations": {
+                            "member": [
+                                {
+                                    "namespace": "user",
+                                },
+                                {
+                                    "namespaceAction": ["group", "member"],
+                                }
+                            ]
+                        },
+                    },
+                    "document": {
+                        "relations": {
+                            "viewer": [
+                                {
+                                    "namespaceAction": ["group", "member"],
+                                }
+                            ]
+                        },
+                    },
+                },
+            }
+        ));
+
+        let id = create_schema(schema, None, None).unwrap().unwrap();
+
+        // The subject is checked against the members of the subject set, so
+        // its own namespace has to be kept, not the subject set's.
+        create_tuple(
+            id, "group", "eng", "member", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "group", "all", "member", "group", "eng", "member", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "group", "all", "member", None, "", None,
+        )
+        .unwrap();
+
+        let should_be_true =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
         assert!(should_be_true);
 
+        let should_be_true = check(
+            id, "document", "1", "viewer", "group", "eng", "member", None,
+        )
+        .unwrap();
+        assert!(should_be_true);
+
+        let should_be_false =
+            check(id, "document", "1", "viewer", "group", "anya", "", None).unwrap();
+        assert!(!should_be_false);
+
+        let should_be_false =
+            check(id, "document", "1", "viewer", "user", "beatrix", "", None).unwrap();
+        assert!(!should_be_false);
+    }
+
+    #[pg_test]
+    fn test_wildcard() {
+        let schema = pgrx::Json(json!(
+            {
+                "namespaces": {
+                    "user": {},
+                    "group": {
+                        "relations": {
+                            "member": [
+                                {
+                                    "namespace": "user",
+                                }
+                            ]
+                        },
+                    },
+                    "document": {
+                        "relations": {
+                            "viewer": [
+                                {
+                                    "namespace": "user",
+                                },
+                                {
+                                    "namespaceWildcard": "user",
+                                },
+                                {
+                                    "namespaceAction": ["group", "member"],
+                                }
+                            ],
+                            "editor": [
+                                {
+                                    "namespace": "user",
+                                }
+                            ]
+                        },
+                        "permissions": {
+                            "can_view": {
+                                "computedUserset": "viewer"
+                            }
+                        }
+                    },
+                },
+            }
+        ));
+
+        let id = create_schema(schema, None, None).unwrap().unwrap();
+
+        create_tuple(
+            id, "document", "1", "viewer", "user", "*", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "2", "viewer", "group", "eng", "member", None, "", None,
+        )
+        .unwrap();
+
+        let should_be_true1 =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
+        assert!(should_be_true1);
+
+        let should_be_true2 =
+            check(id, "document", "1", "can_view", "user", "beatrix", "", None).unwrap();
+        assert!(should_be_true2);
+
+        let should_be_false1 =
+            check(id, "document", "1", "viewer", "group", "eng", "", None).unwrap();
+        assert!(!should_be_false1);
+
         let should_be_false2 =
-            check(id, "document", "1", "can_view", "user", "charlie", "").unwrap();
+            check(id, "document", "2", "viewer", "user", "anya", "", None).unwrap();
+        assert!(!should_be_false2);
+
+        // Wildcards are only allowed where the schema says so.
+        let err = error(|| {
+            create_tuple(
+                id, "document", "1", "editor", "user", "*", "", None, "", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            "'user:*' is not an allowed subject of 'document#editor'"
+        );
+
+        let err = error(|| {
+            create_tuple(
+                id, "document", "1", "viewer", "group", "*", "", None, "", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            "'group:*' is not an allowed subject of 'document#viewer'"
+        );
+    }
+
+    #[pg_test]
+    fn test_wildcard_through_subject_set() {
+        let schema = pgrx::Json(json!(
+            {
+                "namespaces": {
+                    "user": {},
+                    "group": {
+                        "relations": {
+                            "member": [
+                                {
+                                    "namespaceWildcard": "user",
+                                }
+                            ]
+                        },
+                    },
+                    "document": {
+                        "relations": {
+                            "viewer": [
+                                {
+                                    "namespaceAction": ["group", "member"],
+                                }
+                            ]
+                        },
+                    },
+                },
+            }
+        ));
+
+        let id = create_schema(schema, None, None).unwrap().unwrap();
+
+        create_tuple(
+            id, "group", "everyone", "member", "user", "*", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "group", "everyone", "member", None, "", None,
+        )
+        .unwrap();
+
+        let should_be_true =
+            check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap();
+        assert!(should_be_true);
+
+        let should_be_false =
+            check(id, "document", "2", "viewer", "user", "anya", "", None).unwrap();
+        assert!(!should_be_false);
+    }
+
+    #[pg_test]
+    fn test_conditional_tuples() {
+        let schema = pgrx::Json(json!(
+            {
+                "namespaces": {
+                    "user": {},
+                    "document": {
+                        "relations": {
+                            "viewer": [
+                                {
+                                    "namespace": "user",
+                                }
+                            ]
+                        },
+                    },
+                },
+                "conditions": {
+                    "in_network": {
+                        "expression": "ip_in_cidr(request.ip, network)"
+                    },
+                    "cleared": {
+                        "expression": "resource.classification <= user.clearance"
+                    }
+                }
+            }
+        ));
+
+        let id = create_schema(schema, None, None).unwrap().unwrap();
+
+        create_tuple(
+            id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            None,
+            "in_network",
+            Some(pgrx::JsonB(json!({"network": "10.0.0.0/8"}))),
+        )
+        .unwrap();
+        create_tuple(
+            id, "document", "2", "viewer", "user", "anya", "", None, "cleared", None,
+        )
+        .unwrap();
+
+        let context = |context: serde_json::Value| Some(pgrx::JsonB(context));
+
+        let should_be_true1 = check(
+            id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            context(json!({"request": {"ip": "10.1.2.3"}})),
+        )
+        .unwrap();
+        assert!(should_be_true1);
+
+        let should_be_false1 = check(
+            id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            context(json!({"request": {"ip": "192.168.1.1"}})),
+        )
+        .unwrap();
+        assert!(!should_be_false1);
+
+        let should_be_true2 = check(
+            id,
+            "document",
+            "2",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            context(json!({"resource": {"classification": 2}, "user": {"clearance": 3}})),
+        )
+        .unwrap();
+        assert!(should_be_true2);
+
+        let should_be_false2 = check(
+            id,
+            "document",
+            "2",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            context(json!({"resource": {"classification": 4}, "user": {"clearance": 3}})),
+        )
+        .unwrap();
         assert!(!should_be_false2);
+
+        // Missing context is an error rather than a silent deny.
+        let err = error(|| check(id, "document", "1", "viewer", "user", "anya", "", None));
+        assert_eq!(
+            err.message(),
+            PgFgaError::ConditionError(
+                "in_network".to_string(),
+                condition::ConditionError::MissingParameter("request.ip".to_string())
+            )
+            .to_string()
+        );
+
+        let err = error(|| {
+            create_tuple(
+                id, "document", "3", "viewer", "user", "anya", "", None, "nope", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            PgFgaError::UnknownCondition("nope".to_string()).to_string()
+        );
+    }
+
+    #[pg_test]
+    fn test_conditional_exclusions_and_intersections() {
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {
+                        "relations": {
+                            "viewer": [{"namespace": "user"}],
+                            "editor": [{"namespace": "user"}],
+                            "blocked": [{"namespace": "user"}]
+                        },
+                        "permissions": {
+                            "can_read": {"exclusion": [
+                                {"computedUserset": "viewer"},
+                                {"computedUserset": "blocked"}
+                            ]},
+                            "can_edit": {"intersection": [
+                                {"computedUserset": "viewer"},
+                                {"computedUserset": "editor"}
+                            ]}
+                        }
+                    }
+                },
+                "conditions": {"incident": {"expression": "request.incident"}}
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        create_tuple_str("docs", "document:1#viewer@user:anya", None, "", None).unwrap();
+        create_tuple_str(
+            "docs",
+            "document:1#blocked@user:anya",
+            None,
+            "incident",
+            None,
+        )
+        .unwrap();
+        create_tuple_str(
+            "docs",
+            "document:1#editor@user:anya",
+            None,
+            "incident",
+            None,
+        )
+        .unwrap();
+
+        // A condition missing from the context is an error, rather than
+        // anya not being blocked, or being an editor.
+        for action in ["can_read", "can_edit"] {
+            assert_eq!(
+                sqlstate(&format!(
+                    "SELECT pgfga.check_str('docs', 'document:1#{action}@user:anya')"
+                ))
+                .as_deref(),
+                Some("PF013")
+            );
+        }
+
+        let check = |action, incident| {
+            check_str(
+                "docs",
+                &format!("document:1#{action}@user:anya"),
+                Some(pgrx::JsonB(json!({"request": {"incident": incident}}))),
+            )
+            .unwrap()
+        };
+        assert!(check("can_read", false));
+        assert!(!check("can_read", true));
+        assert!(!check("can_edit", false));
+        assert!(check("can_edit", true));
+    }
+
+    #[pg_test]
+    fn test_cannot_create_schema_with_invalid_condition() {
+        let err = error(|| {
+            create_schema(
+                pgrx::Json(json!(
+                    {"namespaces": {}, "conditions": {"broken": {"expression": "a &&"}}}
+                )),
+                None,
+                None,
+            )
+        });
+        assert!(err
+            .message()
+            .starts_with("error evaluating condition 'broken'"));
+    }
+
+    #[pg_test]
+    fn test_store_isolation() {
+        Spi::run("CREATE ROLE pgfga_tenant_a; CREATE ROLE pgfga_tenant_b;").unwrap();
+        Spi::run(
+            "GRANT USAGE ON SCHEMA pgfga TO pgfga_tenant_a, pgfga_tenant_b;
+            GRANT SELECT, INSERT ON ALL TABLES IN SCHEMA pgfga TO pgfga_tenant_a, pgfga_tenant_b;",
+        )
+        .unwrap();
+
+        let store_a = create_store("a", Some("pgfga_tenant_a")).unwrap().unwrap();
+        let store_b = create_store("b", Some("pgfga_tenant_b")).unwrap().unwrap();
+
+        let schema = json!(
+            {"namespaces":{"user":{},"document":{"relations":{"viewer":[{"namespace":"user"}]}}}}
+        );
+        let schema_a = create_schema(pgrx::Json(schema.clone()), Some(store_a), None)
+            .unwrap()
+            .unwrap();
+        let schema_b = create_schema(pgrx::Json(schema), Some(store_b), None)
+            .unwrap()
+            .unwrap();
+
+        create_tuple(
+            schema_a, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            schema_b, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+
+        Spi::run("SET LOCAL ROLE pgfga_tenant_a").unwrap();
+
+        let stores: Vec<_> = read_stores().unwrap().map(|(_, id, ..)| id).collect();
+        assert_eq!(stores, vec![store_a]);
+
+        let schemas: Vec<_> = read_schemas().unwrap().map(|(_, id, ..)| id).collect();
+        assert_eq!(schemas, vec![schema_a]);
+
+        let should_be_true = check(
+            schema_a, "document", "1", "viewer", "user", "anya", "", None,
+        )
+        .unwrap();
+        assert!(should_be_true);
+
+        let err = error(|| {
+            check(
+                schema_b, "document", "1", "viewer", "user", "anya", "", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            PgFgaError::UnknownSchemaId(schema_b).to_string()
+        );
+
+        let err = error(|| {
+            create_tuple(
+                schema_b, "document", "2", "viewer", "user", "anya", "", None, "", None,
+            )
+        });
+        assert_eq!(
+            err.message(),
+            PgFgaError::UnknownSchemaId(schema_b).to_string()
+        );
+
+        Spi::run("RESET ROLE").unwrap();
+
+        // Deleting a store deletes its schemas and tuples.
+        assert_eq!(delete_store(store_a).unwrap(), 1);
+        assert_eq!(delete_store(store_a).unwrap(), 0);
+        assert!(read_schema(schema_a).unwrap().next().is_none());
+        assert!(read_tuples(schema_a, "", "", "", "", "", "")
+            .unwrap()
+            .next()
+            .is_none());
+        assert!(read_schema(schema_b).unwrap().next().is_some());
+    }
+
+    #[pg_test]
+    fn test_migrate_tuples() {
+        let old = create_schema(
+            pgrx::Json(json!(
+                {
+                    "namespaces": {
+                        "user": {},
+                        "group": {"relations": {"member": [{"namespace": "user"}]}},
+                        "document": {
+                            "relations": {
+                                "viewer": [
+                                    {"namespace": "user"},
+                                    {"namespaceAction": ["group", "member"]}
+                                ],
+                                "owner": [{"namespace": "user"}]
+                            }
+                        }
+                    }
+                }
+            )),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let new = create_schema(
+            pgrx::Json(json!(
+                {
+                    "namespaces": {
+                        "user": {},
+                        "team": {"relations": {"member": [{"namespace": "user"}]}},
+                        "document": {
+                            "relations": {
+                                "reader": [{"namespace": "user"}],
+                                "owner": [{"namespace": "user"}]
+                            }
+                        }
+                    }
+                }
+            )),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        create_tuple(
+            old, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            old, "document", "1", "owner", "user", "beatrix", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            old, "document", "1", "viewer", "group", "eng", "member", None, "", None,
+        )
+        .unwrap();
+
+        let renames = pgrx::JsonB(json!({"document": {"viewer": "reader"}}));
+
+        // A dry run reports what would happen without writing anything.
+        let results: Vec<_> = migrate_tuples(old, new, true, renames, false)
+            .unwrap()
+            .map(|(_, _, relation, _, subject_id, _, error)| {
+                (relation, subject_id, error.is_some())
+            })
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                ("reader".to_string(), "anya".to_string(), false),
+                ("owner".to_string(), "beatrix".to_string(), false),
+                ("reader".to_string(), "eng".to_string(), true),
+            ]
+        );
+        assert!(read_tuples(new, "", "", "", "", "", "")
+            .unwrap()
+            .next()
+            .is_none());
+
+        // Move the valid tuples.
+        let renames = pgrx::JsonB(json!({"document": {"viewer": "reader"}}));
+        migrate_tuples(old, new, false, renames, true)
+            .unwrap()
+            .for_each(drop);
+
+        let should_be_true =
+            check(new, "document", "1", "reader", "user", "anya", "", None).unwrap();
+        assert!(should_be_true);
+
+        let should_be_true =
+            check(new, "document", "1", "owner", "user", "beatrix", "", None).unwrap();
+        assert!(should_be_true);
+
+        // Only the invalid tuple is left behind.
+        let remaining: Vec<_> = read_tuples(old, "", "", "", "", "", "")
+            .unwrap()
+            .map(|(_, _, _, _, _, _, subject_id, _)| subject_id)
+            .collect();
+        assert_eq!(remaining, vec!["eng".to_string()]);
+    }
+
+    #[pg_test]
+    fn test_diff_schemas() {
+        let old = create_schema(
+            pgrx::Json(json!(
+                {
+                    "namespaces": {
+                        "user": {},
+                        "group": {"relations": {"member": [{"namespace": "user"}]}},
+                        "document": {
+                            "relations": {
+                                "viewer": [
+                                    {"namespace": "user"},
+                                    {"namespaceAction": ["group", "member"]}
+                                ]
+                            }
+                        }
+                    }
+                }
+            )),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let new = create_schema(
+            pgrx::Json(json!(
+                {
+                    "namespaces": {
+                        "user": {},
+                        "group": {"relations": {"member": [{"namespace": "user"}]}},
+                        "document": {
+                            "relations": {
+                                "viewer": [{"namespace": "user"}]
+                            },
+                            "permissions": {
+                                "can_view": {"computedUserset": "viewer"}
+                            }
+                        }
+                    }
+                }
+            )),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        create_tuple(
+            old, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            old, "document", "1", "viewer", "group", "eng", "member", None, "", None,
+        )
+        .unwrap();
+        create_tuple(
+            old, "document", "2", "viewer", "group", "eng", "member", None, "", None,
+        )
+        .unwrap();
+
+        let pgrx::JsonB(diff) = diff_schemas(old, new).unwrap();
+
+        assert_eq!(
+            diff,
+            json!({
+                "breaking": true,
+                "changes": [
+                    {
+                        "kind": "typeRestrictionRemoved",
+                        "namespace": "document",
+                        "name": "viewer",
+                        "old": {"namespaceAction": ["group", "member"]},
+                        "breaking": true,
+                        "affectedTuples": 2
+                    },
+                    {
+                        "kind": "permissionAdded",
+                        "namespace": "document",
+                        "name": "can_view",
+                        "new": {"computedUserset": "viewer"},
+                        "breaking": false
+                    }
+                ]
+            })
+        );
+
+        let pgrx::JsonB(diff) = diff_schemas(new, new).unwrap();
+        assert_eq!(diff, json!({"breaking": false, "changes": []}));
+    }
+
+    #[pg_test]
+    fn test_named_schemas() {
+        let schema = json!({
+            "namespaces": {
+                "user": {},
+                "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+            }
+        });
+
+        let v1 = create_schema(pgrx::Json(schema.clone()), None, Some("docs"))
+            .unwrap()
+            .unwrap();
+        let v2 = create_schema(pgrx::Json(schema), None, Some("docs"))
+            .unwrap()
+            .unwrap();
+
+        let versions: Vec<_> = read_schemas()
+            .unwrap()
+            .map(|(_, id, _, _, _, name, version, _)| (id, name, version))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                (v1, Some("docs".to_string()), Some(1)),
+                (v2, Some("docs".to_string()), Some(2)),
+            ]
+        );
+
+        // The first version is current until told otherwise.
+        create_tuple_by_name(
+            "docs", "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        assert_eq!(read_tuples(v1, "", "", "", "", "", "").unwrap().count(), 1);
+        assert!(
+            check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None).unwrap()
+        );
+
+        set_current_schema("docs", v2).unwrap();
+        assert!(
+            !check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None).unwrap()
+        );
+
+        // UUIDs are still accepted.
+        assert!(check_by_name(
+            &v1.to_string(),
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            None
+        )
+        .unwrap());
+
+        let err = error(|| set_current_schema("other", v2));
+        assert_eq!(
+            err.message(),
+            PgFgaError::SchemaNameMismatch(v2, "other".to_string()).to_string()
+        );
+
+        let err =
+            error(|| check_by_name("other", "document", "1", "viewer", "user", "anya", "", None));
+        assert_eq!(
+            err.message(),
+            PgFgaError::UnknownSchemaName("other".to_string()).to_string()
+        );
+    }
+
+    #[pg_test]
+    fn test_named_schemas_are_per_store() {
+        Spi::run("CREATE ROLE pgfga_tenant_a; CREATE ROLE pgfga_tenant_b;").unwrap();
+        Spi::run(
+            "GRANT USAGE ON SCHEMA pgfga TO pgfga_tenant_a, pgfga_tenant_b;
+            GRANT SELECT, INSERT ON ALL TABLES IN SCHEMA pgfga TO pgfga_tenant_a, pgfga_tenant_b;",
+        )
+        .unwrap();
+
+        let store_a = create_store("a", Some("pgfga_tenant_a")).unwrap().unwrap();
+        let store_b = create_store("b", Some("pgfga_tenant_b")).unwrap().unwrap();
+
+        let schema = json!(
+            {"namespaces":{"user":{},"document":{"relations":{"viewer":[{"namespace":"user"}]}}}}
+        );
+
+        // Each tenant creates "docs" without seeing the other's.
+        Spi::run("SET LOCAL ROLE pgfga_tenant_a").unwrap();
+        let schema_a = create_schema(pgrx::Json(schema.clone()), Some(store_a), Some("docs"))
+            .unwrap()
+            .unwrap();
+        create_tuple_by_name(
+            "docs", "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+
+        Spi::run("SET LOCAL ROLE pgfga_tenant_b").unwrap();
+        let schema_b = create_schema(pgrx::Json(schema), Some(store_b), Some("docs"))
+            .unwrap()
+            .unwrap();
+        assert!(
+            !check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None).unwrap()
+        );
+        Spi::run("RESET ROLE").unwrap();
+
+        let versions: Vec<_> = read_schemas()
+            .unwrap()
+            .map(|(_, id, _, _, _, _, version, _)| (id, version))
+            .collect();
+        assert_eq!(versions, vec![(schema_a, Some(1)), (schema_b, Some(1))]);
+
+        let current = Spi::get_one::<i64>("SELECT count(*) FROM pgfga.current_schema")
+            .unwrap()
+            .unwrap();
+        assert_eq!(current, 2);
+
+        // Roles that see both stores have to say which one they mean.
+        let err =
+            error(|| check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None));
+        assert_eq!(
+            err.message(),
+            PgFgaError::AmbiguousSchemaName("docs".to_string()).to_string()
+        );
+
+        Spi::run(&format!("SET LOCAL pgfga.store_id = '{store_a}'")).unwrap();
+        assert!(
+            check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None).unwrap()
+        );
+
+        Spi::run(&format!("SET LOCAL pgfga.store_id = '{store_b}'")).unwrap();
+        assert!(
+            !check_by_name("docs", "document", "1", "viewer", "user", "anya", "", None).unwrap()
+        );
+    }
+
+    #[pg_test]
+    fn test_create_tuple_for_a_concurrently_deleted_schema() {
+        let id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+                }
+            })),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        // Deleting the schema once create_tuple has found it stands in for
+        // another transaction deleting it at the same time, which violates
+        // the foreign key of pgfga.tuple.
+        Spi::run(
+            "CREATE FUNCTION delete_schema() RETURNS TRIGGER AS $$
+            BEGIN
+                DELETE FROM pgfga.schema WHERE id = NEW.schema_id;
+                RETURN NEW;
+            END;
+            $$ LANGUAGE plpgsql;
+
+            CREATE TRIGGER delete_schema BEFORE INSERT ON pgfga.tuple
+                FOR EACH ROW EXECUTE FUNCTION delete_schema();",
+        )
+        .unwrap();
+
+        assert_eq!(
+            sqlstate(&format!(
+                "SELECT pgfga.create_tuple('{id}', 'document', '1', 'viewer', 'user', 'anya')"
+            ))
+            .as_deref(),
+            Some("PF004")
+        );
+    }
+
+    #[pg_test]
+    fn test_archive_and_delete_schema() {
+        let schema = json!({
+            "namespaces": {
+                "user": {},
+                "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+            }
+        });
+
+        let id = create_schema(pgrx::Json(schema), None, None)
+            .unwrap()
+            .unwrap();
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+
+        // An archived schema can be read, but not written to or checked.
+        archive_schema(id, true).unwrap();
+        assert_eq!(read_tuples(id, "", "", "", "", "", "").unwrap().count(), 1);
+
+        let err = error(|| {
+            create_tuple(
+                id, "document", "1", "viewer", "user", "beatrix", "", None, "", None,
+            )
+        });
+        assert_eq!(err.message(), PgFgaError::SchemaArchived(id).to_string());
+
+        let err = error(|| check(id, "document", "1", "viewer", "user", "anya", "", None));
+        assert_eq!(err.message(), PgFgaError::SchemaArchived(id).to_string());
+
+        archive_schema(id, false).unwrap();
+        assert!(check(id, "document", "1", "viewer", "user", "anya", "", None).unwrap());
+
+        // A schema with tuples is only deleted when cascading.
+        let err = error(|| delete_schema(id, false));
+        assert_eq!(err.message(), PgFgaError::SchemaInUse(id, 1).to_string());
+
+        assert_eq!(delete_schema(id, true).unwrap(), 1);
+        assert!(read_schema(id).unwrap().next().is_none());
+        assert_eq!(
+            Spi::get_one::<i64>("SELECT COUNT(*) FROM pgfga.tuple").unwrap(),
+            Some(0)
+        );
+    }
+
+    #[pg_test]
+    fn test_errors_have_sqlstates() {
+        let id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+                }
+            })),
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let cases = [
+            (
+                "SELECT pgfga.create_schema('{\"foo\": 1}')".to_string(),
+                "PF001",
+            ),
+            (
+                "SELECT pgfga.check('00000000-0000-0000-0000-000000000000', 'document', '1', 'viewer', 'user', 'anya')".to_string(),
+                "PF004",
+            ),
+            (
+                "SELECT pgfga.check('docs', 'document', '1', 'viewer', 'user', 'anya')".to_string(),
+                "PF005",
+            ),
+            (
+                format!("SELECT pgfga.create_tuple('{id}', 'document', '1', 'viewer', 'user', '*')"),
+                "PF010",
+            ),
+            (
+                format!("SELECT pgfga.create_tuple('{id}', 'document', '1', 'viewer', 'user', 'anya', condition => 'nope')"),
+                "PF011",
+            ),
+            (
+                format!("SELECT pgfga.check('{id}', 'document', '1', 'viewer', 'user', 'anya', context => '[]')"),
+                "PF003",
+            ),
+        ];
+
+        for (query, code) in cases {
+            assert_eq!(sqlstate(&query).as_deref(), Some(code), "{query}");
+        }
+
+        create_tuple(
+            id, "document", "1", "viewer", "user", "anya", "", None, "", None,
+        )
+        .unwrap();
+        assert_eq!(
+            sqlstate(&format!("SELECT pgfga.delete_schema('{id}')")).as_deref(),
+            Some("PF009")
+        );
+
+        archive_schema(id, true).unwrap();
+        assert_eq!(
+            sqlstate(&format!(
+                "SELECT pgfga.check('{id}', 'document', '1', 'viewer', 'user', 'anya')"
+            ))
+            .as_deref(),
+            Some("PF008")
+        );
+
+        // Writes that go around pgfga.create_tuple are rejected with the same
+        // SQLSTATE by the trigger on pgfga.tuple.
+        assert_eq!(
+            sqlstate(&format!(
+                "INSERT INTO pgfga.tuple (schema_id, resource_namespace, resource_id, relation, subject_namespace, subject_id, subject_action)
+                VALUES ('{id}', 'document', '2', 'viewer', 'user', 'anya', '')"
+            ))
+            .as_deref(),
+            Some("PF008")
+        );
+
+        let err = error(|| check(id, "document", "1", "viewer", "user", "anya", "", None));
+        assert_eq!(
+            err.hint(),
+            Some("Unarchive it with pgfga.archive_schema(id, false).")
+        );
+    }
+
+    #[pg_test]
+    fn test_errors_have_their_own_sqlstates() {
+        let id = pgrx::Uuid::from_bytes([0; 16]);
+        let condition = |err| PgFgaError::ConditionError("c".to_string(), err);
+        let errors = [
+            PgFgaError::SpiError(pgrx::spi::Error::NoTupleTable),
+            PgFgaError::SerdeError(serde_json::from_str::<serde_json::Value>("{").unwrap_err()),
+            PgFgaError::MaxDepth,
+            PgFgaError::Public(String::new()),
+            PgFgaError::UnknownSchemaId(id),
+            PgFgaError::UnknownSchemaName(String::new()),
+            PgFgaError::AmbiguousSchemaName(String::new()),
+            PgFgaError::SchemaNameMismatch(id, String::new()),
+            PgFgaError::SchemaArchived(id),
+            PgFgaError::SchemaInUse(id, 1),
+            PgFgaError::WildcardNotAllowed(String::new(), String::new(), String::new()),
+            PgFgaError::UnknownCondition(String::new()),
+            condition(condition::ConditionError::Syntax(0, String::new())),
+            condition(condition::ConditionError::MissingParameter(String::new())),
+            condition(condition::ConditionError::Type(String::new())),
+            condition(condition::ConditionError::UnknownFunction(String::new())),
+            condition(condition::ConditionError::TooDeep(0)),
+            PgFgaError::ParseError(tuple_key::parse("").unwrap_err()),
+            PgFgaError::ImportError(schema::ImportError::Syntax(0, String::new())),
+            PgFgaError::ImportError(schema::ImportError::Invalid(String::new())),
+            PgFgaError::ImportError(schema::ImportError::Unsupported(vec![])),
+            PgFgaError::QuerySourceNotAllowed(String::new(), String::new()),
+            PgFgaError::DecisionLogAdminOnly,
+        ];
+
+        let sqlstates: std::collections::HashSet<_> =
+            errors.iter().map(PgFgaError::sqlstate).collect();
+        assert_eq!(sqlstates.len(), errors.len());
+        assert!(sqlstates.iter().all(|sqlstate| sqlstate.starts_with("PF")));
+    }
+
+    #[pg_test]
+    fn test_object_and_subject_refs() {
+        let schema = json!({
+            "namespaces": {
+                "user": {},
+                "group": {"relations": {"member": [{"namespace": "user"}]}},
+                "document": {
+                    "relations": {
+                        "viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]
+                    }
+                }
+            }
+        });
+        let schema_id = create_schema(pgrx::Json(schema), None, None)
+            .unwrap()
+            .unwrap();
+
+        Spi::run(&format!(
+            "SELECT pgfga.create_tuple('{schema_id}', 'document:1#viewer@group:eng#member')"
+        ))
+        .unwrap();
+        create_tuple_by_key(
+            schema_id,
+            tuple_key::parse("group:eng#member@user:anya").unwrap(),
+            None,
+            "",
+            None,
+        )
+        .unwrap();
+
+        let allowed = Spi::get_one::<bool>(&format!(
+            "SELECT pgfga.check('{schema_id}', 'document:1', 'viewer', 'user:anya')"
+        ))
+        .unwrap();
+        assert_eq!(allowed, Some(true));
+
+        let tuples: Vec<_> = read_tuples_by_object(
+            schema_id,
+            object_ref::parse("document:1").unwrap(),
+            "viewer",
+        )
+        .unwrap()
+        .map(|(_, _, _, _, _, ns, id, action)| (ns, id, action))
+        .collect();
+        assert_eq!(
+            tuples,
+            vec![("group".to_string(), "eng".to_string(), "member".to_string())]
+        );
+
+        let key =
+            Spi::get_one::<String>("SELECT 'document:1#viewer@user:anya'::pgfga.tuple_key::text")
+                .unwrap();
+        assert_eq!(key.as_deref(), Some("document:1#viewer@user:anya"));
+
+        assert_eq!(
+            sqlstate("SELECT 'document'::pgfga.object_ref").as_deref(),
+            Some("PF017")
+        );
+    }
+
+    #[pg_test]
+    fn test_tuple_strings() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+
+        let tuple = format_tuple("document", "a:b", "viewer", "user", "anya", "");
+        assert_eq!(tuple, r"document:a\:b#viewer@user:anya");
+
+        let parts: Vec<_> = parse_tuple(&tuple).unwrap().collect();
+        assert_eq!(
+            parts,
+            vec![(
+                "document".to_string(),
+                "a:b".to_string(),
+                "viewer".to_string(),
+                "user".to_string(),
+                "anya".to_string(),
+                "".to_string(),
+            )]
+        );
+
+        assert_eq!(create_tuple_str("docs", &tuple, None, "", None).unwrap(), 1);
+        assert!(check_str(&schema_id.to_string(), &tuple, None).unwrap());
+        assert!(!check_str("docs", r"document:a\:b#viewer@user:beth", None).unwrap());
+
+        let err = error(|| check_str("docs", "document:1#viewer", None));
+        assert_eq!(
+            err.message(),
+            "invalid tuple 'document:1#viewer': expected '@', found end of input at character 18"
+        );
+        assert_eq!(err.detail(), Some("document:1#viewer\n                 ^"));
+        assert_eq!(
+            sqlstate("SELECT pgfga.check_str('docs', 'document:1#viewer')").as_deref(),
+            Some("PF017")
+        );
+    }
+
+    #[pg_test]
+    fn test_rls_policy() {
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {"relations": {"viewer": [{"namespace": "user"}]}}
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        create_tuple_str(
+            "docs",
+            "document:1#viewer@user:pgfga_reader",
+            None,
+            "",
+            None,
+        )
+        .unwrap();
+
+        Spi::run(
+            "CREATE TABLE documents (id INTEGER PRIMARY KEY, title TEXT);
+            INSERT INTO documents VALUES (1, 'one'), (2, 'two');
+            CREATE ROLE pgfga_reader;
+            GRANT SELECT ON documents TO pgfga_reader;
+            GRANT USAGE ON SCHEMA pgfga TO pgfga_reader;
+            GRANT SELECT ON ALL TABLES IN SCHEMA pgfga TO pgfga_reader;",
+        )
+        .unwrap();
+
+        let name = Spi::get_one::<String>(
+            "SELECT pgfga.create_rls_policy('documents', 'docs', 'id', 'document', 'viewer', $$'user:' || current_user$$)",
+        )
+        .unwrap();
+        assert_eq!(name.as_deref(), Some("pgfga_document_viewer"));
+
+        Spi::run("SET LOCAL ROLE pgfga_reader").unwrap();
+        let titles = Spi::get_one::<Vec<String>>("SELECT array_agg(title) FROM documents").unwrap();
+        assert_eq!(titles, Some(vec!["one".to_string()]));
+        Spi::run("RESET ROLE").unwrap();
+
+        assert_eq!(
+            sqlstate(
+                "SELECT pgfga.create_rls_policy('documents', 'docs', 'id', 'document', 'viewer', 'x', 'TRUNCATE')"
+            )
+            .as_deref(),
+            Some("PF003")
+        );
+    }
+
+    #[pg_test]
+    fn test_accessible_resources() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                    "folder": {
+                        "relations": {"viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}
+                    },
+                    "document": {
+                        "relations": {
+                            "parent": [{"namespace": "folder"}],
+                            "viewer": [{"namespace": "user"}, {"namespaceWildcard": "user"}],
+                            "blocked": [{"namespace": "user"}]
+                        },
+                        "permissions": {
+                            "can_view": {"union": [
+                                {"computedUserset": "viewer"},
+                                {"tupleToUserset": ["parent", "viewer"]}
+                            ]},
+                            "can_read": {"exclusion": [
+                                {"computedUserset": "can_view"},
+                                {"computedUserset": "blocked"}
+                            ]}
+                        }
+                    }
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+
+        for tuple in [
+            "group:eng#member@user:anya",
+            "group:all#member@group:eng#member",
+            "folder:f#viewer@group:all#member",
+            "document:1#parent@folder:f",
+            "document:2#viewer@user:anya",
+            "document:3#viewer@user:*",
+            "document:4#viewer@user:beth",
+            "document:2#blocked@user:anya",
+        ] {
+            create_tuple_str("docs", tuple, None, "", None).unwrap();
+        }
+
+        let accessible = |action, subject| {
+            let mut ids: Vec<_> = accessible_resources("docs", "document", action, subject, None)
+                .unwrap()
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(accessible("can_view", "user:anya"), vec!["1", "2", "3"]);
+        assert_eq!(accessible("can_read", "user:anya"), vec!["1", "3"]);
+        assert_eq!(accessible("can_view", "user:beth"), vec!["3", "4"]);
+        assert_eq!(accessible("viewer", "user:anya"), vec!["2", "3"]);
+
+        // The results agree with check.
+        for id in ["1", "2", "3", "4"] {
+            for action in ["can_view", "can_read", "viewer"] {
+                assert_eq!(
+                    accessible(action, "user:anya").contains(&id.to_string()),
+                    check(schema_id, "document", id, action, "user", "anya", "", None).unwrap()
+                );
+            }
+        }
+
+        Spi::run(
+            "CREATE TABLE documents (id TEXT PRIMARY KEY);
+            INSERT INTO documents VALUES ('1'), ('2'), ('3'), ('4');
+            CREATE ROLE anya;
+            GRANT SELECT ON documents TO anya;
+            GRANT USAGE ON SCHEMA pgfga TO anya;
+            GRANT SELECT ON ALL TABLES IN SCHEMA pgfga TO anya;
+            SELECT pgfga.create_rls_policy('documents', 'docs', 'id', 'document', 'can_read', $$'user:' || current_user$$, set_based => true);
+            SET LOCAL ROLE anya;",
+        )
+        .unwrap();
+        let ids =
+            Spi::get_one::<Vec<String>>("SELECT array_agg(id ORDER BY id) FROM documents").unwrap();
+        Spi::run("RESET ROLE").unwrap();
+        assert_eq!(ids, Some(vec!["1".to_string(), "3".to_string()]));
+    }
+
+    #[pg_test]
+    fn test_accessible_resources_stop_at_the_maximum_depth() {
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                    "document": {
+                        "relations": {"viewer": [{"namespaceAction": ["group", "member"]}]},
+                        "permissions": {"can_view": {"union": [{"computedUserset": "viewer"}]}}
+                    }
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+
+        // Document n is viewed through a chain of n + 1 nested groups, the
+        // innermost of which anya is a member of. Checking can_view reaches
+        // the innermost group at depth n + 2, through the union and viewer.
+        let boundary = check::MAX_DEPTH - 3;
+        for n in [boundary, boundary + 1] {
+            create_tuple_str(
+                "docs",
+                &format!("document:{n}#viewer@group:{n}-0#member"),
+                None,
+                "",
+                None,
+            )
+            .unwrap();
+            for i in 0..n {
+                create_tuple_str(
+                    "docs",
+                    &format!("group:{n}-{i}#member@group:{n}-{}#member", i + 1),
+                    None,
+                    "",
+                    None,
+                )
+                .unwrap();
+            }
+            create_tuple_str(
+                "docs",
+                &format!("group:{n}-{n}#member@user:anya"),
+                None,
+                "",
+                None,
+            )
+            .unwrap();
+        }
+
+        let ids: Vec<_> = accessible_resources("docs", "document", "can_view", "user:anya", None)
+            .unwrap()
+            .collect();
+        assert_eq!(ids, vec![boundary.to_string()]);
+
+        for n in [boundary, boundary + 1] {
+            assert_eq!(
+                check_str("docs", &format!("document:{n}#can_view@user:anya"), None).unwrap(),
+                ids.contains(&n.to_string())
+            );
+        }
+    }
+
+    #[pg_test]
+    fn test_group_closure() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "group": {"relations": {"member": [
+                        {"namespace": "user"},
+                        {"namespaceAction": ["group", "member"]}
+                    ]}}
+                }
+            })),
+            None,
+            Some("groups"),
+        )
+        .unwrap()
+        .unwrap();
+
+        for tuple in [
+            "group:a#member@group:b#member",
+            "group:b#member@group:c#member",
+            "group:c#member@user:anya",
+        ] {
+            create_tuple_str("groups", tuple, None, "", None).unwrap();
+        }
+
+        let members = |group: &str| {
+            Spi::get_one::<i64>(&format!(
+                "SELECT COUNT(*) FROM pgfga.group_closure WHERE group_id = '{group}'"
+            ))
+            .unwrap()
+            .unwrap()
+        };
+
+        // group:a has group:b#member, group:c#member, and user:anya.
+        assert_eq!(
+            enable_group_closure("groups", "group", "member").unwrap(),
+            6
+        );
+        assert_eq!(members("a"), 3);
+        assert!(check_str("groups", "group:a#member@user:anya", None).unwrap());
+
+        // Writes are reflected in every group the change is nested in.
+        create_tuple_str("groups", "group:c#member@user:beth", None, "", None).unwrap();
+        assert_eq!(members("a"), 4);
+        assert!(check_str("groups", "group:a#member@user:beth", None).unwrap());
+
+        delete_tuple(schema_id, "group", "b", "member", "group", "c", "member").unwrap();
+        assert_eq!(members("a"), 1);
+        assert_eq!(members("b"), 0);
+        assert!(!check_str("groups", "group:a#member@user:anya", None).unwrap());
+
+        let err = error(|| enable_group_closure("groups", "group", "owner"));
+        assert_eq!(err.message(), "'group' has no relation 'owner'");
+
+        assert_eq!(
+            disable_group_closure("groups", "group", "member").unwrap(),
+            1
+        );
+        assert_eq!(members("c"), 0);
+    }
+
+    #[pg_test]
+    fn test_group_closure_is_maintained_incrementally() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "group": {"relations": {"member": [
+                        {"namespace": "user"},
+                        {"namespaceAction": ["group", "member"]}
+                    ]}}
+                }
+            })),
+            None,
+            Some("groups"),
+        )
+        .unwrap()
+        .unwrap();
+
+        // group:root contains group:eng both directly and through group:all.
+        for tuple in [
+            "group:root#member@group:eng#member",
+            "group:root#member@group:all#member",
+            "group:all#member@group:eng#member",
+        ] {
+            create_tuple_str("groups", tuple, None, "", None).unwrap();
+        }
+        Spi::run(&format!(
+            "INSERT INTO pgfga.tuple (schema_id, resource_namespace, resource_id, relation, subject_namespace, subject_id, subject_action)
+            SELECT '{schema_id}', 'group', 'eng', 'member', 'user', i::text, ''
+            FROM generate_series(1, 1000) AS i"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            enable_group_closure("groups", "group", "member").unwrap(),
+            3 * 1000 + 3
+        );
+
+        let closure = || {
+            Spi::get_one::<String>(
+                "SELECT string_agg(
+                    group_id || '>' || member_namespace || ':' || member_id || '#' || member_action,
+                    ',' ORDER BY group_id, member_namespace, member_id, member_action
+                )
+                FROM pgfga.group_closure",
+            )
+            .unwrap()
+            .unwrap_or_default()
+        };
+
+        // The closure after each write is the one a rebuild would find.
+        let assert_exact = || {
+            let maintained = closure();
+            disable_group_closure("groups", "group", "member").unwrap();
+            enable_group_closure("groups", "group", "member").unwrap();
+            assert_eq!(maintained, closure());
+        };
+
+        create_tuple_str("groups", "group:eng#member@user:anya", None, "", None).unwrap();
+        assert_exact();
+        assert!(check_str("groups", "group:root#member@user:anya", None).unwrap());
+
+        // group:eng is still in group:root through group:all.
+        delete_tuple(
+            schema_id, "group", "root", "member", "group", "eng", "member",
+        )
+        .unwrap();
+        assert_exact();
+        assert!(check_str("groups", "group:root#member@user:500", None).unwrap());
+
+        // A cycle through group:root keeps everything in it.
+        create_tuple_str(
+            "groups",
+            "group:eng#member@group:root#member",
+            None,
+            "",
+            None,
+        )
+        .unwrap();
+        assert_exact();
+        delete_tuple(
+            schema_id, "group", "all", "member", "group", "eng", "member",
+        )
+        .unwrap();
+        assert_exact();
+        delete_tuple(
+            schema_id, "group", "eng", "member", "group", "root", "member",
+        )
+        .unwrap();
+        assert_exact();
+        assert!(!check_str("groups", "group:root#member@user:500", None).unwrap());
+
+        // Several tuples removed in one statement.
+        create_tuple_str(
+            "groups",
+            "group:all#member@group:eng#member",
+            None,
+            "",
+            None,
+        )
+        .unwrap();
+        Spi::run(
+            "DELETE FROM pgfga.tuple
+            WHERE resource_id = 'eng' AND subject_id IN ('1', '2', 'anya')",
+        )
+        .unwrap();
+        assert_exact();
+        assert!(!check_str("groups", "group:root#member@user:1", None).unwrap());
+        assert!(check_str("groups", "group:root#member@user:3", None).unwrap());
+    }
+
+    #[pg_test]
+    fn test_sync_relation() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "folder": {"relations": {"viewer": [{"namespace": "user"}]}},
+                    "document": {
+                        "relations": {"parent": [{"namespace": "folder"}]},
+                        "permissions": {"can_view": {"tupleToUserset": ["parent", "viewer"]}}
+                    }
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        create_tuple_str("docs", "folder:1#viewer@user:anya", None, "", None).unwrap();
+
+        Spi::run(
+            "CREATE TABLE documents (id INTEGER PRIMARY KEY, folder_id INTEGER);
+            INSERT INTO documents VALUES (1, 1), (2, NULL);",
+        )
+        .unwrap();
+
+        // Existing rows are backfilled.
+        let num_created = Spi::get_one::<i64>(
+            "SELECT pgfga.sync_relation('documents', 'docs', 'document', 'id', 'parent', 'folder', 'folder_id')",
+        )
+        .unwrap();
+        assert_eq!(num_created, Some(1));
+
+        let can_view =
+            |id| check_str("docs", &format!("document:{id}#can_view@user:anya"), None).unwrap();
+        assert!(can_view(1));
+        assert!(!can_view(2));
+
+        // Writes are mirrored.
+        Spi::run("UPDATE documents SET folder_id = 1 WHERE id = 2").unwrap();
+        Spi::run("INSERT INTO documents VALUES (3, 1)").unwrap();
+        assert!(can_view(2));
+        assert!(can_view(3));
+
+        Spi::run("UPDATE documents SET folder_id = 2 WHERE id = 1").unwrap();
+        Spi::run("DELETE FROM documents WHERE id = 3").unwrap();
+        assert!(!can_view(1));
+        assert!(!can_view(3));
+
+        let mut parents: Vec<_> = read_tuples(schema_id, "document", "", "parent", "", "", "")
+            .unwrap()
+            .map(|(_, _, _, id, _, _, folder, _)| (id, folder))
+            .collect();
+        parents.sort();
+        assert_eq!(
+            parents,
+            vec![
+                ("1".to_string(), "2".to_string()),
+                ("2".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[pg_test]
+    fn test_sync_relation_with_non_text_ids() {
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "folder": {},
+                    "document": {"relations": {"parent": [{"namespace": "folder"}]}}
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+
+        // Blank-padded ids are padded in JSON, but not as text.
+        Spi::run(
+            "CREATE TABLE documents (code CHAR(8) PRIMARY KEY, folder_id INTEGER);
+            INSERT INTO documents VALUES ('a', 1);",
+        )
+        .unwrap();
+        Spi::run(
+            "SELECT pgfga.sync_relation('documents', 'docs', 'document', 'code', 'parent', 'folder', 'folder_id')",
+        )
+        .unwrap();
+        Spi::run("INSERT INTO documents VALUES ('b', 1)").unwrap();
+
+        let parent = |code| check_str("docs", &format!("document:{code}#parent@folder:1"), None);
+        assert!(parent("a").unwrap());
+        assert!(parent("b").unwrap());
+
+        Spi::run("DELETE FROM documents").unwrap();
+        assert!(!parent("a").unwrap());
+        assert!(!parent("b").unwrap());
+    }
+
+    #[pg_test]
+    fn test_relation_sources() {
+        Spi::run(
+            "CREATE TABLE documents (id INTEGER PRIMARY KEY, owner_id TEXT, team_id TEXT);
+            INSERT INTO documents VALUES (1, 'anya', NULL), (2, 'bob', 'eng'), (3, NULL, NULL);",
+        )
+        .unwrap();
+
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "team": {"relations": {"member": [{"namespace": "user"}]}},
+                    "document": {
+                        "relations": {
+                            "owner": [{"namespace": "user"}],
+                            "team": [{"namespaceAction": ["team", "member"]}]
+                        },
+                        "permissions": {"can_view": {"union": [
+                            {"computedUserset": "owner"},
+                            {"computedUserset": "team"}
+                        ]}},
+                        "sources": {
+                            "owner": {"table": "documents", "resourceId": "id", "subjectId": "owner_id", "subjectNamespace": "user"},
+                            "team": {"query": "SELECT id, team_id FROM documents", "subjectNamespace": "team", "subjectAction": "member"}
+                        }
+                    }
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        create_tuple_str("docs", "team:eng#member@user:anya", None, "", None).unwrap();
+
+        let can_view = |id, user| {
+            check_str("docs", &format!("document:{id}#can_view@user:{user}"), None).unwrap()
+        };
+        assert!(can_view(1, "anya"));
+        assert!(can_view(2, "anya"));
+        assert!(can_view(2, "bob"));
+        assert!(!can_view(1, "bob"));
+        assert!(!can_view(3, "anya"));
+
+        // Changes to the table are seen straight away.
+        Spi::run("UPDATE documents SET owner_id = 'bob' WHERE id = 1").unwrap();
+        assert!(can_view(1, "bob"));
+        assert!(!can_view(1, "anya"));
+
+        let mut ids: Vec<_> =
+            accessible_resources("docs", "document", "can_view", "user:anya", None)
+                .unwrap()
+                .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["2"]);
+
+        // Sources must be for relations of their namespace.
+        assert_eq!(
+            sqlstate(
+                r#"SELECT pgfga.create_schema('{"namespaces":{"document":{"sources":{"owner":{"query":"SELECT 1, 2","subjectNamespace":"user"}}}}}')"#
+            )
+            .as_deref(),
+            Some("PF003")
+        );
+    }
+
+    #[pg_test]
+    fn test_relation_sources_are_not_injectable() {
+        Spi::run(
+            "CREATE TABLE documents (id INTEGER PRIMARY KEY, owner_id TEXT);
+            INSERT INTO documents VALUES (1, 'anya');
+            CREATE ROLE pgfga_tenant;
+            GRANT USAGE ON SCHEMA pgfga TO pgfga_tenant;
+            GRANT SELECT, INSERT ON ALL TABLES IN SCHEMA pgfga TO pgfga_tenant;
+            GRANT USAGE ON ALL SEQUENCES IN SCHEMA pgfga TO pgfga_tenant;
+            GRANT SELECT ON documents TO pgfga_tenant;",
+        )
+        .unwrap();
+
+        let schema = |source: serde_json::Value| {
+            json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {
+                        "relations": {"owner": [{"namespace": "user"}]},
+                        "sources": {"owner": source}
+                    }
+                }
+            })
+        };
+        let create = |source| {
+            sqlstate(&format!(
+                "SELECT pgfga.create_schema({})",
+                pgrx::spi::quote_literal(schema(source).to_string())
+            ))
+        };
+        let documents_exist = || {
+            Spi::get_one::<bool>("SELECT to_regclass('documents') IS NOT NULL")
+                .unwrap()
+                .unwrap()
+        };
+
+        // Tables are resolved through regclass and columns are quoted, so
+        // neither can smuggle in more SQL.
+        assert_eq!(
+            create(json!({
+                "table": "documents; DROP TABLE documents",
+                "resourceId": "id",
+                "subjectId": "owner_id",
+                "subjectNamespace": "user"
+            }))
+            .as_deref(),
+            Some("42602")
+        );
+        assert_eq!(
+            create(json!({
+                "table": "documents",
+                "resourceId": "id, owner_id FROM documents; DROP TABLE documents; --",
+                "subjectId": "owner_id",
+                "subjectNamespace": "user"
+            }))
+            .as_deref(),
+            Some("42703")
+        );
+        assert!(documents_exist());
+
+        // Schemas written straight into pgfga.schema are resolved the same
+        // way when they are checked.
+        let id = Spi::get_one::<pgrx::Uuid>(&format!(
+            "INSERT INTO pgfga.schema (schema) VALUES ({}) RETURNING id",
+            pgrx::spi::quote_literal(
+                schema(json!({
+                    "table": "documents; DROP TABLE documents",
+                    "resourceId": "id",
+                    "subjectId": "owner_id",
+                    "subjectNamespace": "user"
+                }))
+                .to_string()
+            )
+        ))
+        .unwrap()
+        .unwrap();
+        error(|| check(id, "document", "1", "owner", "user", "anya", "", None));
+        assert!(documents_exist());
+
+        // Query sources are run as written, so only members of pgfga_admin
+        // can write them, whether through pgfga.create_schema or not.
+        let query = schema(json!({
+            "query": "SELECT id, owner_id FROM documents",
+            "subjectNamespace": "user"
+        }));
+        Spi::run("SET LOCAL ROLE pgfga_tenant").unwrap();
+        let err = error(|| create_schema(pgrx::Json(query.clone()), None, None));
+        assert_eq!(
+            err.message(),
+            "the source of 'document#owner' is a query, which only members of pgfga_admin can write"
+        );
+        let err = error(|| {
+            Spi::run(&format!(
+                "INSERT INTO pgfga.schema (schema) VALUES ({})",
+                pgrx::spi::quote_literal(query.to_string())
+            ))
+        });
+        assert_eq!(
+            err.message(),
+            "the source of 'document#owner' is a query, which only members of pgfga_admin can write"
+        );
+        Spi::run("RESET ROLE").unwrap();
+
+        Spi::run("GRANT pgfga_admin TO pgfga_tenant; SET LOCAL ROLE pgfga_tenant").unwrap();
+        let id = create_schema(pgrx::Json(query), None, None)
+            .unwrap()
+            .unwrap();
+        assert!(check(id, "document", "1", "owner", "user", "anya", "", None).unwrap());
+        Spi::run("RESET ROLE").unwrap();
+    }
+
+    #[pg_test]
+    fn test_decision_log() {
+        create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "document": {
+                        "relations": {"viewer": [{"namespace": "user"}]},
+                        "permissions": {"can_view": {"computedUserset": "viewer"}}
+                    }
+                }
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        create_tuple_str("docs", "document:1#viewer@user:anya", None, "", None).unwrap();
+
+        // Nothing is logged until the decision log is turned on.
+        assert!(check_str("docs", "document:1#can_view@user:anya", None).unwrap());
+
+        Spi::run("SET pgfga.decision_log = on").unwrap();
+        assert!(check_str("docs", "document:1#can_view@user:anya", None).unwrap());
+        assert!(!check_str("docs", "document:1#can_view@user:bob", None).unwrap());
+
+        // check_rls is stable and parallel safe, so it never logs.
+        assert!(check_rls("docs", "document", "1", "can_view", "user:anya").unwrap());
+
+        Spi::run("SET pgfga.decision_log_sample_rate = 0").unwrap();
+        assert!(check_str("docs", "document:1#can_view@user:anya", None).unwrap());
+        Spi::run("RESET pgfga.decision_log_sample_rate; RESET pgfga.decision_log").unwrap();
+
+        let decisions = Spi::connect(|client| {
+            client
+                .select(
+                    "SELECT request->>'subject_id', allowed, depth, username = CURRENT_USER
+                    FROM pgfga.decision_log ORDER BY rowid",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .map(|row| {
+                    (
+                        row.get::<String>(1).unwrap().unwrap(),
+                        row.get::<bool>(2).unwrap().unwrap(),
+                        row.get::<i32>(3).unwrap().unwrap(),
+                        row.get::<bool>(4).unwrap().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(
+            decisions,
+            vec![
+                ("anya".to_string(), true, 1, true),
+                ("bob".to_string(), false, 1, true),
+            ]
+        );
+
+        // Only decisions older than the retention period are purged.
+        Spi::run(
+            "UPDATE pgfga.decision_log SET logged_at = NOW() - INTERVAL '2 days'
+            WHERE request->>'subject_id' = 'anya'",
+        )
+        .unwrap();
+        let num_purged =
+            Spi::get_one::<i64>("SELECT pgfga.purge_decision_log(INTERVAL '1 day')").unwrap();
+        assert_eq!(num_purged, Some(1));
+
+        // Roles without privileges on the log have their checks logged all
+        // the same, under the session's user.
+        Spi::run(
+            "CREATE ROLE pgfga_tenant;
+            GRANT USAGE ON SCHEMA pgfga TO pgfga_tenant;
+            GRANT SELECT ON ALL TABLES IN SCHEMA pgfga TO pgfga_tenant;
+            SET LOCAL pgfga.decision_log = on;
+            SET LOCAL ROLE pgfga_tenant",
+        )
+        .unwrap();
+        assert!(check_str("docs", "document:1#can_view@user:anya", None).unwrap());
+        Spi::run("RESET ROLE; SET LOCAL pgfga.decision_log = off").unwrap();
+        let logged_by_session_user = Spi::get_one::<bool>(
+            "SELECT username = session_user FROM pgfga.decision_log ORDER BY rowid DESC LIMIT 1",
+        )
+        .unwrap();
+        assert_eq!(logged_by_session_user, Some(true));
+
+        // Only members of pgfga_admin can change or delete decisions, by
+        // purging them or otherwise.
+        Spi::run(
+            "GRANT UPDATE, DELETE, TRUNCATE ON pgfga.decision_log TO pgfga_tenant;
+            SET LOCAL ROLE pgfga_tenant",
+        )
+        .unwrap();
+        for query in [
+            "SELECT pgfga.purge_decision_log(INTERVAL '-1 day')",
+            "UPDATE pgfga.decision_log SET allowed = NOT allowed",
+            "DELETE FROM pgfga.decision_log",
+            "TRUNCATE pgfga.decision_log",
+        ] {
+            let err = error(|| Spi::run(query));
+            assert_eq!(
+                err.message(),
+                "only members of pgfga_admin can modify pgfga.decision_log"
+            );
+        }
+        Spi::run("RESET ROLE").unwrap();
+        let num_logged = Spi::get_one::<i64>("SELECT COUNT(*) FROM pgfga.decision_log").unwrap();
+        assert_eq!(num_logged, Some(2));
+
+        Spi::run("GRANT pgfga_admin TO pgfga_tenant; SET LOCAL ROLE pgfga_tenant").unwrap();
+        let num_purged =
+            Spi::get_one::<i64>("SELECT pgfga.purge_decision_log(INTERVAL '-1 day')").unwrap();
+        assert_eq!(num_purged, Some(2));
+        Spi::run("RESET ROLE").unwrap();
+    }
+
+    #[pg_test]
+    fn test_import_openfga_model() {
+        let schema = import_openfga_dsl(
+            "model
+  schema 1.1
+type user
+type document
+  relations
+    define owner: [user]
+    define viewer: [user, user:*] or owner",
+        )
+        .unwrap();
+
+        let schema_id = create_schema(pgrx::Json(schema.0), None, Some("docs"))
+            .unwrap()
+            .unwrap();
+        create_tuple_str("docs", "document:1#owner@user:anya", None, "", None).unwrap();
+        create_tuple_str("docs", "document:2#viewer_direct@user:*", None, "", None).unwrap();
+
+        assert!(check_str("docs", "document:1#viewer@user:anya", None).unwrap());
+        assert!(check_str("docs", "document:2#viewer@user:bob", None).unwrap());
+        assert!(!check_str("docs", "document:1#viewer@user:bob", None).unwrap());
+
+        // The JSON format converts to the same schema.
+        let schema = import_openfga_model(pgrx::JsonB(json!({
+            "schema_version": "1.1",
+            "type_definitions": [
+                {"type": "user"},
+                {
+                    "type": "document",
+                    "relations": {
+                        "owner": {"this": {}},
+                        "viewer": {"union": {"child": [
+                            {"this": {}},
+                            {"computedUserset": {"relation": "owner"}}
+                        ]}}
+                    },
+                    "metadata": {"relations": {
+                        "owner": {"directly_related_user_types": [{"type": "user"}]},
+                        "viewer": {"directly_related_user_types": [
+                            {"type": "user"}, {"type": "user", "wildcard": {}}
+                        ]}
+                    }}
+                }
+            ]
+        })))
+        .unwrap();
+        let (_, _, imported, ..) = read_schema(schema_id).unwrap().next().unwrap();
+        assert_eq!(schema.0, imported.0);
+
+        assert_eq!(
+            sqlstate(
+                "SELECT pgfga.import_openfga_dsl('type document\n  relations\n    define viewer: [user with recent]')"
+            )
+            .as_deref(),
+            Some("PF020")
+        );
+    }
+
+    #[pg_test]
+    fn test_import_spicedb() {
+        let schema = import_spicedb_schema(
+            "definition user {}
+
+            definition group {
+                relation member: user | group#member
+            }
+
+            definition document {
+                relation viewer: user | group#member with expiration
+                relation blocked: user
+                permission view = viewer - blocked
+            }",
+        )
+        .unwrap();
+        create_schema(pgrx::Json(schema.0), None, Some("docs"))
+            .unwrap()
+            .unwrap();
+
+        let num_created = load_spicedb_relationships(
+            "docs",
+            "// Engineering
+            group:eng#member@user:anya
+            group:eng#member@user:bob
+            document:1#viewer@group:eng#member
+            document:1#blocked@user:bob
+            document:2#viewer@group:eng#member[expiration:2000-01-01T00:00:00Z]",
+        )
+        .unwrap();
+        assert_eq!(num_created, 5);
+
+        assert!(check_str("docs", "document:1#view@user:anya", None).unwrap());
+        assert!(!check_str("docs", "document:1#view@user:bob", None).unwrap());
+        assert!(!check_str("docs", "document:2#view@user:anya", None).unwrap());
+
+        assert_eq!(
+            sqlstate("SELECT pgfga.load_spicedb_relationships('docs', 'document:1#viewer')")
+                .as_deref(),
+            Some("PF018")
+        );
+        assert_eq!(
+            sqlstate(
+                "SELECT pgfga.import_spicedb_schema('definition user { relation x: y with z }')"
+            )
+            .as_deref(),
+            Some("PF020")
+        );
+    }
+
+    #[pg_test]
+    fn test_export_and_import_store() {
+        let schema_id = create_schema(
+            pgrx::Json(json!({
+                "namespaces": {
+                    "user": {},
+                    "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+                    "document": {"relations": {"viewer": [
+                        {"namespace": "user"},
+                        {"namespaceWildcard": "user"},
+                        {"namespaceAction": ["group", "member"]}
+                    ]}}
+                },
+                "conditions": {"in_office": {"expression": "request.ip == '10.0.0.1'"}}
+            })),
+            None,
+            Some("docs"),
+        )
+        .unwrap()
+        .unwrap();
+        for tuple in [
+            "group:eng#member@user:anya",
+            "group:all#member@group:eng#member",
+            "document:1#viewer@group:all#member",
+            "document:2#viewer@user:*",
+        ] {
+            create_tuple_str("docs", tuple, None, "", None).unwrap();
+        }
+        Spi::run(
+            "SELECT pgfga.create_tuple_str('docs', 'document:3#viewer@user:bob', '2030-01-01T00:00:00Z', 'in_office', '{\"request\": {}}')",
+        )
+        .unwrap();
+        enable_group_closure(&schema_id.to_string(), "group", "member").unwrap();
+
+        let exported = export_store(schema_id).unwrap().0;
+        assert_eq!(exported["name"], json!("docs"));
+        assert_eq!(exported["closure_relations"], json!([["group", "member"]]));
+        assert_eq!(exported["tuples"].as_array().unwrap().len(), 5);
+
+        // Importing creates a new schema with the same export.
+        let imported_id = import_store(pgrx::JsonB(exported.clone()), None, None).unwrap();
+        assert_ne!(imported_id, schema_id);
+        assert_eq!(export_store(imported_id).unwrap().0, exported);
+        assert!(check(
+            imported_id,
+            "document",
+            "1",
+            "viewer",
+            "user",
+            "anya",
+            "",
+            None
+        )
+        .unwrap());
+
+        // As does importing the NDJSON export.
+        let ndjson = Spi::get_one_with_args::<String>(
+            "SELECT string_agg(line, E'\\n') FROM pgfga.export_store_ndjson($1) AS line",
+            vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(ndjson.lines().count(), 6);
+
+        let imported_id = import_store_ndjson(&ndjson, None, Some("docs_copy")).unwrap();
+        let mut reexported = export_store(imported_id).unwrap().0;
+        assert_eq!(reexported["name"], json!("docs_copy"));
+        reexported["name"] = json!("docs");
+        assert_eq!(reexported, exported);
+    }
+
+    #[pg_test]
+    fn test_tables_are_dumped() {
+        // Tables and sequences created by the extension are only dumped if
+        // they are configuration tables. See tests/dump_restore.sh.
+        let dumped = Spi::get_one::<Vec<String>>(
+            "SELECT array_agg(c::regclass::text ORDER BY c::regclass::text)
+             FROM pg_extension, unnest(extconfig) AS c
+             WHERE extname = 'pgfga'",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            dumped,
+            vec![
+                "pgfga.closure_relation",
+                "pgfga.current_schema",
+                "pgfga.decision_log",
+                "pgfga.decision_log_rowid_seq",
+                "pgfga.group_closure",
+                "pgfga.schema",
+                "pgfga.schema_rowid_seq",
+                "pgfga.store",
+                "pgfga.store_rowid_seq",
+                "pgfga.tuple",
+                "pgfga.tuple_rowid_seq",
+            ]
+        );
     }
 }
 
@@ -0,0 +1,150 @@
+use crate::error::PgFgaError;
+use crate::schema::Schema;
+use crate::storage::Storage;
+use std::collections::HashMap;
+
+/// Relation renames to apply while migrating, keyed by namespace and then by
+/// the old relation name, e.g. `{"document": {"viewer": "reader"}}`.
+pub type RelationRenames = HashMap<String, HashMap<String, String>>;
+
+#[derive(Debug)]
+pub struct MigratedTuple {
+    pub resource_namespace: String,
+    pub resource_id: String,
+    pub relation: String,
+    pub subject_namespace: String,
+    pub subject_id: String,
+    pub subject_action: String,
+    pub error: Option<String>,
+}
+
+impl From<MigratedTuple>
+    for (
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+        Option<String>,
+    )
+{
+    fn from(tuple: MigratedTuple) -> Self {
+        (
+            tuple.resource_namespace,
+            tuple.resource_id,
+            tuple.relation,
+            tuple.subject_namespace,
+            tuple.subject_id,
+            tuple.subject_action,
+            tuple.error,
+        )
+    }
+}
+
+/// Copy (or move) the tuples of `from_schema` to `to_schema`, renaming
+/// relations along the way. Every tuple is reported back as it would be
+/// written to `to_schema`, along with the reason it is invalid under
+/// `to_schema` if it is. Invalid tuples are left where they are, and nothing
+/// is written at all on a dry run.
+pub fn migrate_tuples(
+    storage: &mut Storage,
+    from_schema: pgrx::Uuid,
+    to_schema: pgrx::Uuid,
+    dry_run: bool,
+    renames: &RelationRenames,
+    move_tuples: bool,
+) -> Result<Vec<MigratedTuple>, PgFgaError> {
+    if storage.read_schemas(Some(from_schema))?.is_empty() {
+        return Err(PgFgaError::UnknownSchemaId(from_schema));
+    }
+
+    let schema: Schema = storage
+        .read_schemas(Some(to_schema))?
+        .pop()
+        .ok_or_else(|| PgFgaError::UnknownSchemaId(to_schema))?
+        .try_into()?;
+
+    let rename = |namespace: &str, relation: &str| {
+        renames
+            .get(namespace)
+            .and_then(|relations| relations.get(relation))
+            .cloned()
+            .unwrap_or_else(|| relation.to_string())
+    };
+
+    let tuples = storage.read_tuples(from_schema, "", "", "", "", "", "")?;
+    let mut results = Vec::with_capacity(tuples.len());
+
+    for tuple in tuples {
+        let relation = rename(&tuple.resource_namespace, &tuple.relation);
+        let subject_action = if tuple.subject_action.is_empty() {
+            String::new()
+        } else {
+            rename(&tuple.subject_namespace, &tuple.subject_action)
+        };
+
+        let mut error = schema
+            .validate_tuple(
+                &tuple.resource_namespace,
+                &relation,
+                &tuple.subject_namespace,
+                &tuple.subject_id,
+                &subject_action,
+            )
+            .err()
+            .map(|err| err.to_string());
+
+        if error.is_none()
+            && !tuple.condition.is_empty()
+            && !schema.conditions.contains_key(&tuple.condition)
+        {
+            error = Some(PgFgaError::UnknownCondition(tuple.condition.clone()).to_string());
+        }
+
+        if error.is_none() && !dry_run {
+            storage.create_tuple(
+                to_schema,
+                &tuple.resource_namespace,
+                &tuple.resource_id,
+                &relation,
+                &tuple.subject_namespace,
+                &tuple.subject_id,
+                &subject_action,
+                tuple.expires_at,
+                &tuple.condition,
+                tuple.condition_context,
+            )?;
+
+            // When migrating within a schema an unrenamed tuple is already
+            // where it belongs, so deleting it would lose it.
+            let unchanged = from_schema == to_schema
+                && relation == tuple.relation
+                && subject_action == tuple.subject_action;
+
+            if move_tuples && !unchanged {
+                storage.delete_tuple(
+                    from_schema,
+                    &tuple.resource_namespace,
+                    &tuple.resource_id,
+                    &tuple.relation,
+                    &tuple.subject_namespace,
+                    &tuple.subject_id,
+                    &tuple.subject_action,
+                )?;
+            }
+        }
+
+        results.push(MigratedTuple {
+            resource_namespace: tuple.resource_namespace,
+            resource_id: tuple.resource_id,
+            relation,
+            subject_namespace: tuple.subject_namespace,
+            subject_id: tuple.subject_id,
+            subject_action,
+            error,
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,89 @@
+use crate::error::PgFgaError;
+use pgrx::prelude::*;
+use pgrx::spi::{quote_identifier, quote_literal, quote_qualified_identifier};
+use pgrx::PgRelation;
+
+const COMMANDS: [&str; 5] = ["ALL", "SELECT", "INSERT", "UPDATE", "DELETE"];
+
+/// A row-level security policy that lets through the rows of `table` whose
+/// `id_column` names an object of `namespace` the subject has `action` on.
+pub struct Policy<'a> {
+    pub name: Option<&'a str>,
+    pub schema: &'a str,
+    pub id_column: &'a str,
+    pub namespace: &'a str,
+    pub action: &'a str,
+    pub subject_expr: &'a str,
+    pub command: &'a str,
+
+    /// Whether to look up the accessible ids once with
+    /// `pgfga.accessible_resources`, rather than checking each row.
+    pub set_based: bool,
+}
+
+impl Policy<'_> {
+    fn name(&self) -> String {
+        match self.name {
+            Some(name) => name.to_string(),
+            None => format!("pgfga_{}_{}", self.namespace, self.action),
+        }
+    }
+
+    fn sql(&self, table: &PgRelation) -> Result<String, PgFgaError> {
+        let command = self.command.to_uppercase();
+        if !COMMANDS.contains(&command.as_str()) {
+            return Err(PgFgaError::Public(format!(
+                "command must be one of {}",
+                COMMANDS.join(", ")
+            )));
+        }
+
+        let check = if self.set_based {
+            format!(
+                "({})::text IN (SELECT pgfga.accessible_resources({}, {}, {}, ({})::text))",
+                quote_identifier(self.id_column),
+                quote_literal(self.schema),
+                quote_literal(self.namespace),
+                quote_literal(self.action),
+                self.subject_expr,
+            )
+        } else {
+            format!(
+                "pgfga.check_rls({}, {}, ({})::text, {}, ({})::text)",
+                quote_literal(self.schema),
+                quote_literal(self.namespace),
+                quote_identifier(self.id_column),
+                quote_literal(self.action),
+                self.subject_expr,
+            )
+        };
+
+        // Inserted rows don't exist yet, so they can only be checked after
+        // the fact.
+        let clause = if command == "INSERT" {
+            "WITH CHECK"
+        } else {
+            "USING"
+        };
+
+        Ok(format!(
+            "CREATE POLICY {} ON {} FOR {command} {clause} ({check})",
+            quote_identifier(self.name()),
+            quote_qualified_identifier(table.namespace(), table.name()),
+        ))
+    }
+
+    /// Create the policy, enabling row-level security on the table if it
+    /// isn't already. Returns the name of the policy.
+    pub fn create(&self, table: &PgRelation) -> Result<String, PgFgaError> {
+        let sql = self.sql(table)?;
+
+        Spi::run(&format!(
+            "ALTER TABLE {} ENABLE ROW LEVEL SECURITY",
+            quote_qualified_identifier(table.namespace(), table.name()),
+        ))?;
+        Spi::run(&sql)?;
+
+        Ok(self.name())
+    }
+}
@@ -1,7 +1,18 @@
+use crate::check::parse_conditions;
+use crate::compile::Compiled;
+use crate::decision_log::Decision;
+use crate::diff::AffectedTuples;
 use crate::error::PgFgaError;
-use crate::schema::Schema;
+use crate::export::ExportedTuple;
+use crate::schema::{
+    RelationSource, Schema, SourceFrom, TypeRestriction, ValidationError, WILDCARD,
+};
+use pgfga_core::Tuple;
+use pgrx::pg_sys::panic::CaughtError;
 use pgrx::prelude::*;
-use pgrx::spi::{SpiClient, SpiHeapTupleData};
+use pgrx::spi::{quote_identifier, SpiClient, SpiHeapTupleData};
+use std::collections::HashSet;
+use std::panic::AssertUnwindSafe;
 
 pub struct Storage<'a> {
     client: SpiClient<'a>,
@@ -17,6 +28,9 @@ pub struct TupleRow {
     pub subject_namespace: String,
     pub subject_id: String,
     pub subject_action: String,
+    pub expires_at: Option<pgrx::TimestampWithTimeZone>,
+    pub condition: String,
+    pub condition_context: Option<pgrx::JsonB>,
 }
 
 impl From<TupleRow>
@@ -45,6 +59,21 @@ impl From<TupleRow>
     }
 }
 
+impl From<TupleRow> for Tuple {
+    fn from(row: TupleRow) -> Self {
+        Tuple {
+            resource_namespace: row.resource_namespace,
+            resource_id: row.resource_id,
+            relation: row.relation,
+            subject_namespace: row.subject_namespace,
+            subject_id: row.subject_id,
+            subject_action: row.subject_action,
+            condition: row.condition,
+            condition_context: row.condition_context.map(|context| context.0),
+        }
+    }
+}
+
 impl<'a> TryFrom<SpiHeapTupleData<'a>> for TupleRow {
     type Error = spi::Error;
 
@@ -68,6 +97,9 @@ impl<'a> TryFrom<SpiHeapTupleData<'a>> for TupleRow {
             subject_action: row["subject_action"]
                 .value::<String>()?
                 .expect("no subject_action"),
+            expires_at: row["expires_at"].value::<pgrx::TimestampWithTimeZone>()?,
+            condition: row["condition"].value::<String>()?.expect("no condition"),
+            condition_context: row["condition_context"].value::<pgrx::JsonB>()?,
         })
     }
 }
@@ -78,11 +110,50 @@ pub struct SchemaRow {
     pub id: pgrx::Uuid,
     pub schema: pgrx::Json,
     pub created_at: pgrx::TimestampWithTimeZone,
+    pub store_id: Option<pgrx::Uuid>,
+    pub name: Option<String>,
+    pub version: Option<i32>,
+    pub archived: bool,
 }
 
-impl From<SchemaRow> for (i64, pgrx::Uuid, pgrx::Json, pgrx::TimestampWithTimeZone) {
+impl From<SchemaRow>
+    for (
+        i64,
+        pgrx::Uuid,
+        pgrx::Json,
+        pgrx::TimestampWithTimeZone,
+        Option<pgrx::Uuid>,
+        Option<String>,
+        Option<i32>,
+        bool,
+    )
+{
     fn from(row: SchemaRow) -> Self {
-        (row.rowid, row.id, row.schema, row.created_at)
+        (
+            row.rowid,
+            row.id,
+            row.schema,
+            row.created_at,
+            row.store_id,
+            row.name,
+            row.version,
+            row.archived,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct StoreRow {
+    pub rowid: i64,
+    pub id: pgrx::Uuid,
+    pub name: String,
+    pub owner: String,
+    pub created_at: pgrx::TimestampWithTimeZone,
+}
+
+impl From<StoreRow> for (i64, pgrx::Uuid, String, String, pgrx::TimestampWithTimeZone) {
+    fn from(row: StoreRow) -> Self {
+        (row.rowid, row.id, row.name, row.owner, row.created_at)
     }
 }
 
@@ -99,24 +170,426 @@ impl<'a> Storage<'a> {
         Self { client }
     }
 
-    pub fn create_schema(&mut self, schema: pgrx::Json) -> Result<Option<pgrx::Uuid>, PgFgaError> {
+    pub fn create_store(
+        &mut self,
+        name: &str,
+        owner: Option<&str>,
+    ) -> Result<Option<pgrx::Uuid>, PgFgaError> {
+        let result = self
+            .client
+            .update(
+                "
+                INSERT INTO pgfga.store (name, owner)
+                VALUES ($1, COALESCE($2::regrole, CURRENT_USER::regrole))
+                RETURNING id
+                ",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::VARCHAROID.oid(), name.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), owner.into_datum()),
+                ]),
+            )?
+            .first()
+            .get_one::<pgrx::Uuid>()?;
+
+        Ok(result)
+    }
+
+    pub fn read_stores(&self) -> Result<Vec<StoreRow>, PgFgaError> {
+        let results = self
+            .client
+            .select(
+                "SELECT rowid, id, name, owner::text, created_at FROM pgfga.store",
+                None,
+                None,
+            )?
+            .map(|row| {
+                Ok(StoreRow {
+                    rowid: row["rowid"].value()?.expect("no rowid"),
+                    id: row["id"].value::<pgrx::Uuid>()?.expect("no id"),
+                    name: row["name"].value::<String>()?.expect("no name"),
+                    owner: row["owner"].value::<String>()?.expect("no owner"),
+                    created_at: row["created_at"].value()?.expect("no created_at"),
+                })
+            })
+            .collect::<Result<Vec<_>, spi::Error>>()?;
+
+        Ok(results)
+    }
+
+    /// Delete a store along with all of its schemas and their tuples.
+    pub fn delete_store(&mut self, id: pgrx::Uuid) -> Result<i64, PgFgaError> {
+        let args = || Some(vec![(PgBuiltInOids::UUIDOID.oid(), id.into_datum())]);
+
+        self.client.update(
+            "
+            DELETE FROM pgfga.closure_relation
+            WHERE schema_id IN (SELECT id FROM pgfga.schema WHERE store_id = $1)
+            ",
+            None,
+            args(),
+        )?;
+
+        self.client.update(
+            "
+            DELETE FROM pgfga.tuple
+            WHERE schema_id IN (SELECT id FROM pgfga.schema WHERE store_id = $1)
+            ",
+            None,
+            args(),
+        )?;
+
+        self.client.update(
+            "
+            DELETE FROM pgfga.current_schema
+            WHERE schema_id IN (SELECT id FROM pgfga.schema WHERE store_id = $1)
+            ",
+            None,
+            args(),
+        )?;
+
+        self.client
+            .update("DELETE FROM pgfga.schema WHERE store_id = $1", None, args())?;
+
+        let num_deleted = self
+            .client
+            .update("DELETE FROM pgfga.store WHERE id = $1", None, args())?
+            .len();
+
+        Ok(num_deleted as i64)
+    }
+
+    pub fn create_schema(
+        &mut self,
+        schema: pgrx::Json,
+        store_id: Option<pgrx::Uuid>,
+        name: Option<&str>,
+    ) -> Result<Option<pgrx::Uuid>, PgFgaError> {
         // Check that the given JSON schema can actually be deserialized to a
-        // Schema and return an error if not.
-        serde_json::from_value::<Schema>(schema.0.clone())?;
+        // Schema, that its conditions parse, and that its sources are for
+        // relations it defines, and return an error if not.
+        let parsed = serde_json::from_value::<Schema>(schema.0.clone())?;
+        parse_conditions(&parsed)?;
+        parsed
+            .validate_sources()
+            .map_err(|err| PgFgaError::Public(err.to_string()))?;
+
+        // Sources are planned once now, so that one naming a table or columns
+        // that don't exist is rejected rather than failing every check.
+        for (ns, namespace) in &parsed.namespaces {
+            for (relation, source) in &namespace.sources {
+                if matches!(source.from, SourceFrom::Query { .. }) && !self.is_admin()? {
+                    return Err(PgFgaError::QuerySourceNotAllowed(
+                        ns.to_string(),
+                        relation.to_string(),
+                    ));
+                }
+
+                let query = format!(
+                    "SELECT 1 FROM ({}) AS source LIMIT 0",
+                    self.source_sql(source)?
+                );
+                self.client.select(&query, None, None)?;
+            }
+        }
+
+        // Named schemas are versioned, starting at 1.
+        let query = "
+        INSERT INTO pgfga.schema (schema, store_id, name, version)
+        VALUES (
+            $1,
+            $2,
+            $3,
+            CASE WHEN $3 IS NOT NULL THEN
+                COALESCE(
+                    (
+                        SELECT MAX(version) FROM pgfga.schema
+                        WHERE name = $3 AND store_id IS NOT DISTINCT FROM $2
+                    ),
+                    0
+                ) + 1
+            END
+        )
+        RETURNING id
+        ";
 
         let result = self
             .client
             .update(
-                "INSERT INTO pgfga.schema (schema) VALUES ($1) RETURNING id",
+                query,
                 None,
-                Some(vec![(PgBuiltInOids::JSONOID.oid(), schema.into_datum())]),
+                Some(vec![
+                    (PgBuiltInOids::JSONOID.oid(), schema.into_datum()),
+                    (PgBuiltInOids::UUIDOID.oid(), store_id.into_datum()),
+                    (PgBuiltInOids::VARCHAROID.oid(), name.into_datum()),
+                ]),
             )?
             .first()
             .get_one::<pgrx::Uuid>()?;
 
+        // The first version of a named schema becomes its current version.
+        if let (Some(name), Some(id)) = (name, result) {
+            self.client.update(
+                "
+                INSERT INTO pgfga.current_schema (store_id, name, schema_id)
+                SELECT $1, $2, $3
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM pgfga.current_schema
+                    WHERE name = $2 AND store_id IS NOT DISTINCT FROM $1
+                )
+                ",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), store_id.into_datum()),
+                    (PgBuiltInOids::VARCHAROID.oid(), name.into_datum()),
+                    (PgBuiltInOids::UUIDOID.oid(), id.into_datum()),
+                ]),
+            )?;
+        }
+
         Ok(result)
     }
 
+    pub fn set_current_schema(&mut self, name: &str, id: pgrx::Uuid) -> Result<(), PgFgaError> {
+        let row = self
+            .read_schemas(Some(id))?
+            .pop()
+            .ok_or_else(|| PgFgaError::UnknownSchemaId(id))?;
+
+        if row.name.as_deref() != Some(name) {
+            return Err(PgFgaError::SchemaNameMismatch(id, name.to_string()));
+        }
+
+        if row.archived {
+            return Err(PgFgaError::SchemaArchived(id));
+        }
+
+        // The name is current in the store of the schema. Names without a
+        // store can't be upserted with a single unique index, so the pointer
+        // is replaced instead.
+        let args = || {
+            Some(vec![
+                (PgBuiltInOids::UUIDOID.oid(), row.store_id.into_datum()),
+                (PgBuiltInOids::VARCHAROID.oid(), name.into_datum()),
+                (PgBuiltInOids::UUIDOID.oid(), id.into_datum()),
+            ])
+        };
+
+        let num_updated = self
+            .client
+            .update(
+                "
+                UPDATE pgfga.current_schema SET schema_id = $3
+                WHERE name = $2 AND store_id IS NOT DISTINCT FROM $1
+                ",
+                None,
+                args(),
+            )?
+            .len();
+
+        if num_updated == 0 {
+            self.client.update(
+                "INSERT INTO pgfga.current_schema (store_id, name, schema_id) VALUES ($1, $2, $3)",
+                None,
+                args(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn archive_schema(&mut self, id: pgrx::Uuid, archived: bool) -> Result<(), PgFgaError> {
+        let num_updated = self
+            .client
+            .update(
+                "UPDATE pgfga.schema SET archived = $2 WHERE id = $1",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), id.into_datum()),
+                    (PgBuiltInOids::BOOLOID.oid(), archived.into_datum()),
+                ]),
+            )?
+            .len();
+
+        if num_updated == 0 {
+            return Err(PgFgaError::UnknownSchemaId(id));
+        }
+
+        Ok(())
+    }
+
+    /// Delete a schema. A schema that still has tuples is only deleted, along
+    /// with its tuples, when cascading.
+    pub fn delete_schema(&mut self, id: pgrx::Uuid, cascade: bool) -> Result<i64, PgFgaError> {
+        let args = || Some(vec![(PgBuiltInOids::UUIDOID.oid(), id.into_datum())]);
+
+        let num_tuples = self
+            .client
+            .select(
+                "SELECT COUNT(*) FROM pgfga.tuple WHERE schema_id = $1",
+                None,
+                args(),
+            )?
+            .first()
+            .get_one::<i64>()?
+            .unwrap_or_default();
+
+        if num_tuples > 0 && !cascade {
+            return Err(PgFgaError::SchemaInUse(id, num_tuples));
+        }
+
+        self.client.update(
+            "DELETE FROM pgfga.closure_relation WHERE schema_id = $1",
+            None,
+            args(),
+        )?;
+
+        self.client
+            .update("DELETE FROM pgfga.tuple WHERE schema_id = $1", None, args())?;
+
+        self.client.update(
+            "DELETE FROM pgfga.current_schema WHERE schema_id = $1",
+            None,
+            args(),
+        )?;
+
+        let num_deleted = self
+            .client
+            .update("DELETE FROM pgfga.schema WHERE id = $1", None, args())?
+            .len();
+
+        Ok(num_deleted as i64)
+    }
+
+    /// Keep the transitive members of `namespace#relation` in
+    /// `pgfga.group_closure`, starting with those of the existing tuples.
+    /// Returns the number of members found.
+    pub fn enable_group_closure(
+        &mut self,
+        schema_id: pgrx::Uuid,
+        namespace: &str,
+        relation: &str,
+    ) -> Result<i64, PgFgaError> {
+        let schema: Schema = self
+            .read_schemas(Some(schema_id))?
+            .pop()
+            .ok_or_else(|| PgFgaError::UnknownSchemaId(schema_id))?
+            .try_into()?;
+
+        if !schema.is_relation(namespace, relation) {
+            return Err(PgFgaError::Public(
+                ValidationError::UnknownRelation(namespace.to_string(), relation.to_string())
+                    .to_string(),
+            ));
+        }
+
+        let args = || {
+            Some(vec![
+                (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), namespace.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), relation.into_datum()),
+            ])
+        };
+
+        self.client.update(
+            "
+            INSERT INTO pgfga.closure_relation (schema_id, namespace, relation)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            ",
+            None,
+            args(),
+        )?;
+
+        self.client.update(
+            "
+            SELECT pgfga.rebuild_group_closure($1, $2, resource_id, $3)
+            FROM (
+                SELECT DISTINCT resource_id FROM pgfga.tuple
+                WHERE schema_id = $1 AND resource_namespace = $2 AND relation = $3
+            ) AS groups
+            ",
+            None,
+            args(),
+        )?;
+
+        let num_members = self
+            .client
+            .select(
+                "
+                SELECT COUNT(*) FROM pgfga.group_closure
+                WHERE schema_id = $1 AND group_namespace = $2 AND relation = $3
+                ",
+                None,
+                args(),
+            )?
+            .first()
+            .get_one::<i64>()?
+            .unwrap_or_default();
+
+        Ok(num_members)
+    }
+
+    /// Stop keeping the transitive members of `namespace#relation`.
+    pub fn disable_group_closure(
+        &mut self,
+        schema_id: pgrx::Uuid,
+        namespace: &str,
+        relation: &str,
+    ) -> Result<i64, PgFgaError> {
+        let num_deleted = self
+            .client
+            .update(
+                "
+                DELETE FROM pgfga.closure_relation
+                WHERE schema_id = $1 AND namespace = $2 AND relation = $3
+                ",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), namespace.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), relation.into_datum()),
+                ]),
+            )?
+            .len();
+
+        Ok(num_deleted as i64)
+    }
+
+    /// The current version of the name, among the schemas of the store and
+    /// those without one, which the store's own take precedence over. Without
+    /// a store, the name must be current in only one of the stores visible to
+    /// the current role.
+    pub fn read_current_schema(
+        &self,
+        store_id: Option<pgrx::Uuid>,
+        name: &str,
+    ) -> Result<Option<pgrx::Uuid>, PgFgaError> {
+        let ids = self
+            .client
+            .select(
+                "
+                SELECT schema_id FROM pgfga.current_schema
+                WHERE name = $2 AND ($1 IS NULL OR store_id = $1 OR store_id IS NULL)
+                ORDER BY store_id NULLS LAST
+                ",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), store_id.into_datum()),
+                    (PgBuiltInOids::VARCHAROID.oid(), name.into_datum()),
+                ]),
+            )?
+            .map(|row| row.get::<pgrx::Uuid>(1))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match ids.as_slice() {
+            [] => Ok(None),
+            [id] => Ok(*id),
+            [id, ..] if store_id.is_some() => Ok(*id),
+            _ => Err(PgFgaError::AmbiguousSchemaName(name.to_string())),
+        }
+    }
+
     pub fn read_schemas(&self, id: Option<pgrx::Uuid>) -> Result<Vec<SchemaRow>, PgFgaError> {
         let mut query = "SELECT * FROM pgfga.schema".to_string();
         let mut args = vec![];
@@ -135,6 +608,10 @@ impl<'a> Storage<'a> {
                     id: row["id"].value::<pgrx::Uuid>()?.expect("no id"),
                     schema: row["schema"].value()?.expect("no schema"),
                     created_at: row["created_at"].value()?.expect("no created_at"),
+                    store_id: row["store_id"].value::<pgrx::Uuid>()?,
+                    name: row["name"].value::<String>()?,
+                    version: row["version"].value::<i32>()?,
+                    archived: row["archived"].value()?.expect("no archived"),
                 })
             })
             .collect::<Result<Vec<_>, spi::Error>>()?;
@@ -142,21 +619,149 @@ impl<'a> Storage<'a> {
         Ok(results)
     }
 
-    pub fn create_tuple(
-        &mut self,
+    /// Run a query compiled by `compile::compile`, returning the ids of the
+    /// resources it finds.
+    pub fn read_compiled(
+        &self,
+        compiled: &Compiled,
         schema_id: pgrx::Uuid,
-        resource_namespace: &str,
-        resource_id: &str,
-        relation: &str,
         subject_namespace: &str,
         subject_id: &str,
         subject_action: &str,
+    ) -> Result<Vec<String>, PgFgaError> {
+        let args = vec![
+            (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), subject_namespace.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), subject_id.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), subject_action.into_datum()),
+        ];
+
+        let results = self
+            .client
+            .select(&compiled.query, None, Some(args))?
+            .map(|row| row.get::<String>(1).map(|id| id.expect("no id")))
+            .collect::<Result<Vec<_>, spi::Error>>()?;
+
+        Ok(results)
+    }
+
+    /// Count the live tuples of the schema that a breaking schema change
+    /// would invalidate.
+    pub fn count_affected_tuples(
+        &self,
+        schema_id: pgrx::Uuid,
+        affected: &AffectedTuples,
     ) -> Result<i64, PgFgaError> {
-        // Ensure that the schema_id corresponds to a known schema.
-        if self.read_schemas(Some(schema_id))?.is_empty() {
-            return Err(PgFgaError::UnknownSchemaId(schema_id));
-        }
+        let mut args = vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())];
+        let mut text = |value: &str| {
+            args.push((PgBuiltInOids::TEXTOID.oid(), value.into_datum()));
+            args.len()
+        };
+
+        let filter = match affected {
+            AffectedTuples::Namespace(ns) => {
+                let ns = text(ns);
+                format!("resource_namespace = ${ns} OR subject_namespace = ${ns}")
+            }
+            AffectedTuples::Relation(ns, relation) => {
+                let (ns, relation) = (text(ns), text(relation));
+                format!(
+                    "(resource_namespace = ${ns} AND relation = ${relation})
+                    OR (subject_namespace = ${ns} AND subject_action = ${relation})"
+                )
+            }
+            AffectedTuples::TypeRestriction(ns, relation, restriction) => {
+                let (ns, relation) = (text(ns), text(relation));
+                let subject = match restriction {
+                    TypeRestriction::Namespace(sns) => format!(
+                        "subject_namespace = ${} AND subject_id <> '{WILDCARD}' AND subject_action = ''",
+                        text(sns)
+                    ),
+                    TypeRestriction::NamespaceAction(sns, sact) => format!(
+                        "subject_namespace = ${} AND subject_action = ${}",
+                        text(sns),
+                        text(sact)
+                    ),
+                    TypeRestriction::NamespaceWildcard(sns) => format!(
+                        "subject_namespace = ${} AND subject_id = '{WILDCARD}'",
+                        text(sns)
+                    ),
+                };
+                format!("resource_namespace = ${ns} AND relation = ${relation} AND {subject}")
+            }
+            AffectedTuples::Condition(condition) => {
+                format!("condition = ${}", text(condition))
+            }
+        };
+
+        let query = format!(
+            "
+            SELECT COUNT(*) FROM pgfga.tuple
+            WHERE schema_id = $1
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND ({filter})
+            "
+        );
+
+        let count = self
+            .client
+            .select(&query, None, Some(args))?
+            .first()
+            .get_one::<i64>()?;
 
+        Ok(count.unwrap_or_default())
+    }
+
+    /// The tuples of a schema, oldest first.
+    pub fn read_exported_tuples(
+        &self,
+        schema_id: pgrx::Uuid,
+    ) -> Result<Vec<ExportedTuple>, PgFgaError> {
+        self.client
+            .select(
+                EXPORT_QUERY,
+                None,
+                Some(vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())]),
+            )?
+            .map(exported_tuple)
+            .collect()
+    }
+
+    /// Open a cursor over the tuples of a schema, oldest first, and return
+    /// its name. The cursor stays open until the end of the transaction, so
+    /// that it can be fetched from across SPI connections.
+    pub fn open_exported_tuples(&self, schema_id: pgrx::Uuid) -> String {
+        self.client
+            .open_cursor(
+                EXPORT_QUERY,
+                Some(vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())]),
+            )
+            .detach_into_name()
+    }
+
+    /// Fetch up to `count` more tuples from a cursor opened by
+    /// `open_exported_tuples`.
+    pub fn fetch_exported_tuples(
+        &self,
+        cursor: &str,
+        count: i64,
+    ) -> Result<Vec<ExportedTuple>, PgFgaError> {
+        let mut cursor = self.client.find_cursor(cursor)?;
+        let tuples = cursor.fetch(count)?.map(exported_tuple).collect();
+
+        // Leave the cursor open for the next fetch.
+        cursor.detach_into_name();
+
+        tuples
+    }
+
+    /// Insert tuples in bulk, as exported by `read_exported_tuples`. They
+    /// must already have been validated. Returns the number inserted.
+    pub fn import_tuples(
+        &mut self,
+        schema_id: pgrx::Uuid,
+        tuples: &[ExportedTuple],
+    ) -> Result<i64, PgFgaError> {
         let query = "
         INSERT INTO pgfga.tuple (
             schema_id,
@@ -165,31 +770,107 @@ impl<'a> Storage<'a> {
             relation,
             subject_namespace,
             subject_id,
-            subject_action
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7)
-        ON CONFLICT DO NOTHING
-        RETURNING rowid
+            subject_action,
+            expires_at,
+            condition,
+            condition_context
+        )
+        SELECT
+            $1,
+            t.resource_namespace,
+            t.resource_id,
+            t.relation,
+            t.subject_namespace,
+            t.subject_id,
+            t.subject_action,
+            t.expires_at,
+            t.condition,
+            t.condition_context
+        FROM jsonb_populate_recordset(NULL::pgfga.tuple, $2) AS t
         ";
 
-        let args = vec![
-            (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
-            (
-                PgBuiltInOids::VARCHAROID.oid(),
-                resource_namespace.into_datum(),
-            ),
-            (PgBuiltInOids::VARCHAROID.oid(), resource_id.into_datum()),
-            (PgBuiltInOids::VARCHAROID.oid(), relation.into_datum()),
-            (
-                PgBuiltInOids::VARCHAROID.oid(),
-                subject_namespace.into_datum(),
-            ),
-            (PgBuiltInOids::VARCHAROID.oid(), subject_id.into_datum()),
-            (PgBuiltInOids::VARCHAROID.oid(), subject_action.into_datum()),
-        ];
+        let num_imported = self
+            .client
+            .update(
+                query,
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+                    (
+                        PgBuiltInOids::JSONBOID.oid(),
+                        pgrx::JsonB(serde_json::to_value(tuples)?).into_datum(),
+                    ),
+                ]),
+            )?
+            .len();
+
+        Ok(num_imported as i64)
+    }
+
+    pub fn delete_expired_tuples(&mut self) -> Result<i64, PgFgaError> {
+        let query = "DELETE FROM pgfga.tuple WHERE expires_at <= NOW()";
+
+        let num_deleted = self.client.update(query, None, None)?.len();
+
+        Ok(num_deleted as i64)
+    }
 
-        let num_created = self.client.update(query, Some(1), Some(args))?.len();
+    /// Write the decision to the decision log. It is always written, as
+    /// decisions are sampled by `decision_log::sampled` before the check.
+    pub fn log_decision(&mut self, decision: &Decision) -> Result<(), PgFgaError> {
+        // pgfga.log_decision writes it as the owner of the extension, so the
+        // role doing the check needs no privileges on the log.
+        let query = "SELECT pgfga.log_decision($1, $2, $3, $4 * INTERVAL '1 microsecond', $5)";
 
-        Ok(num_created as i64)
+        self.client.update(
+            query,
+            None,
+            Some(vec![
+                (
+                    PgBuiltInOids::UUIDOID.oid(),
+                    decision.schema_id.into_datum(),
+                ),
+                (
+                    PgBuiltInOids::JSONBOID.oid(),
+                    pgrx::JsonB(decision.request.clone()).into_datum(),
+                ),
+                (PgBuiltInOids::BOOLOID.oid(), decision.allowed.into_datum()),
+                (
+                    PgBuiltInOids::INT8OID.oid(),
+                    (decision.duration.as_micros() as i64).into_datum(),
+                ),
+                (
+                    PgBuiltInOids::INT4OID.oid(),
+                    (decision.depth as i32).into_datum(),
+                ),
+            ]),
+        )?;
+
+        Ok(())
+    }
+
+    /// Delete the decisions logged before `older_than` ago. Returns the
+    /// number of decisions deleted.
+    pub fn purge_decision_log(&mut self, older_than: pgrx::Interval) -> Result<i64, PgFgaError> {
+        if !self.is_admin()? {
+            return Err(PgFgaError::DecisionLogAdminOnly);
+        }
+
+        let query = "DELETE FROM pgfga.decision_log WHERE logged_at < NOW() - $1";
+
+        let num_deleted = self
+            .client
+            .update(
+                query,
+                None,
+                Some(vec![(
+                    PgBuiltInOids::INTERVALOID.oid(),
+                    older_than.into_datum(),
+                )]),
+            )?
+            .len();
+
+        Ok(num_deleted as i64)
     }
 
     pub fn read_tuple(
@@ -211,6 +892,7 @@ impl<'a> Storage<'a> {
             AND subject_namespace = $5
             AND subject_id = $6
             AND subject_action = $7
+            AND (expires_at IS NULL OR expires_at > NOW())
         ";
 
         let args = vec![
@@ -250,7 +932,11 @@ impl<'a> Storage<'a> {
         subject_id: &str,
         subject_action: &str,
     ) -> Result<Vec<TupleRow>, PgFgaError> {
-        let mut query = "SELECT * FROM pgfga.tuple WHERE schema_id = $1".to_string();
+        let mut query = "
+        SELECT * FROM pgfga.tuple
+        WHERE schema_id = $1
+            AND (expires_at IS NULL OR expires_at > NOW())"
+            .to_string();
         let mut args = vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())];
 
         if !resource_namespace.is_empty() {
@@ -302,7 +988,11 @@ impl<'a> Storage<'a> {
         resource_id: &str,
         relation: &str,
     ) -> Result<Vec<TupleRow>, PgFgaError> {
-        let mut query = "SELECT * FROM pgfga.tuple WHERE schema_id = $1".to_string();
+        let mut query = "
+        SELECT * FROM pgfga.tuple
+        WHERE schema_id = $1
+            AND (expires_at IS NULL OR expires_at > NOW())"
+            .to_string();
         let mut args = vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())];
 
         if !resource_namespace.is_empty() {
@@ -332,6 +1022,123 @@ impl<'a> Storage<'a> {
         Ok(results)
     }
 
+    pub fn create_tuple(
+        &mut self,
+        schema_id: pgrx::Uuid,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+        expires_at: Option<pgrx::TimestampWithTimeZone>,
+        condition: &str,
+        condition_context: Option<pgrx::JsonB>,
+    ) -> Result<i64, PgFgaError> {
+        if subject_id == WILDCARD || !condition.is_empty() {
+            let schema: Schema = self
+                .read_schemas(Some(schema_id))?
+                .pop()
+                .ok_or_else(|| PgFgaError::UnknownSchemaId(schema_id))?
+                .try_into()?;
+
+            validate_write(
+                &schema,
+                resource_namespace,
+                relation,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                condition,
+            )?;
+        }
+
+        // Only schemas the role can see and that aren't archived are written
+        // to, so that neither the row level security policy nor the trigger
+        // on pgfga.tuple has to be caught. An existing tuple that hasn't
+        // expired is left as it is, and isn't counted.
+        let query = "
+        WITH schema AS (
+            SELECT archived FROM pgfga.schema WHERE id = $1
+        ), inserted AS (
+            INSERT INTO pgfga.tuple (
+                schema_id,
+                resource_namespace,
+                resource_id,
+                relation,
+                subject_namespace,
+                subject_id,
+                subject_action,
+                expires_at,
+                condition,
+                condition_context
+            )
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
+            WHERE EXISTS (SELECT 1 FROM schema WHERE NOT archived)
+            ON CONFLICT ON CONSTRAINT tuple_pkey DO UPDATE
+                SET expires_at = EXCLUDED.expires_at,
+                    condition = EXCLUDED.condition,
+                    condition_context = EXCLUDED.condition_context
+                WHERE pgfga.tuple.expires_at <= NOW()
+            RETURNING rowid
+        )
+        SELECT (SELECT archived FROM schema), (SELECT COUNT(*) FROM inserted)
+        ";
+
+        let args = vec![
+            (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+            (
+                PgBuiltInOids::VARCHAROID.oid(),
+                resource_namespace.into_datum(),
+            ),
+            (PgBuiltInOids::VARCHAROID.oid(), resource_id.into_datum()),
+            (PgBuiltInOids::VARCHAROID.oid(), relation.into_datum()),
+            (
+                PgBuiltInOids::VARCHAROID.oid(),
+                subject_namespace.into_datum(),
+            ),
+            (PgBuiltInOids::VARCHAROID.oid(), subject_id.into_datum()),
+            (PgBuiltInOids::VARCHAROID.oid(), subject_action.into_datum()),
+            (PgBuiltInOids::TIMESTAMPTZOID.oid(), expires_at.into_datum()),
+            (PgBuiltInOids::VARCHAROID.oid(), condition.into_datum()),
+            (
+                PgBuiltInOids::JSONBOID.oid(),
+                condition_context.into_datum(),
+            ),
+        ];
+
+        // The schema can still be deleted between the lookup above and the
+        // insert, which violates the foreign key instead. The error is raised
+        // as UnknownSchemaId straight from the handler, rather than returned,
+        // since the transaction can't go on without a subtransaction.
+        let client = &mut self.client;
+        let (archived, num_created) = PgTryBuilder::new(AssertUnwindSafe(|| {
+            client
+                .update(query, None, Some(args))?
+                .first()
+                .get_two::<bool, i64>()
+        }))
+        .catch_when(
+            PgSqlErrorCode::ERRCODE_FOREIGN_KEY_VIOLATION,
+            |error| match error {
+                CaughtError::PostgresError(ref report)
+                    if report.message().contains("tuple_schema_id_fkey") =>
+                {
+                    unsafe { pg_sys::FlushErrorState() };
+                    PgFgaError::UnknownSchemaId(schema_id).raise()
+                }
+                _ => error.rethrow(),
+            },
+        )
+        .execute()?;
+
+        match archived {
+            None => Err(PgFgaError::UnknownSchemaId(schema_id)),
+            Some(true) => Err(PgFgaError::SchemaArchived(schema_id)),
+            Some(false) => Ok(num_created.unwrap_or(0)),
+        }
+    }
+
     pub fn delete_tuple(
         &mut self,
         schema_id: pgrx::Uuid,
@@ -370,4 +1177,243 @@ impl<'a> Storage<'a> {
 
         Ok(num_deleted as i64)
     }
+
+    /// The relations whose transitive members are kept in
+    /// `pgfga.group_closure`.
+    pub fn read_closure_relations(
+        &self,
+        schema_id: pgrx::Uuid,
+    ) -> Result<HashSet<(String, String)>, PgFgaError> {
+        let results = self
+            .client
+            .select(
+                "SELECT namespace, relation FROM pgfga.closure_relation WHERE schema_id = $1",
+                None,
+                Some(vec![(PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum())]),
+            )?
+            .map(|row| {
+                Ok((
+                    row["namespace"].value::<String>()?.expect("no namespace"),
+                    row["relation"].value::<String>()?.expect("no relation"),
+                ))
+            })
+            .collect::<Result<HashSet<_>, spi::Error>>()?;
+
+        Ok(results)
+    }
+
+    /// Whether the subject is a member of the group according to
+    /// `pgfga.group_closure`, either itself or through a wildcard.
+    pub fn in_group_closure(
+        &self,
+        schema_id: pgrx::Uuid,
+        group_namespace: &str,
+        group_id: &str,
+        relation: &str,
+        subject_namespace: &str,
+        subject_id: &str,
+        subject_action: &str,
+    ) -> Result<bool, PgFgaError> {
+        let query = format!(
+            "
+            SELECT EXISTS (
+                SELECT 1 FROM pgfga.group_closure
+                WHERE schema_id = $1
+                    AND group_namespace = $2
+                    AND group_id = $3
+                    AND relation = $4
+                    AND member_namespace = $5
+                    AND (
+                        (member_id = $6 AND member_action = $7)
+                        OR (member_id = '{WILDCARD}' AND member_action = '' AND $7 = '')
+                    )
+            )
+            "
+        );
+
+        let result = self
+            .client
+            .select(
+                &query,
+                None,
+                Some(vec![
+                    (PgBuiltInOids::UUIDOID.oid(), schema_id.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), group_namespace.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), group_id.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), relation.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), subject_namespace.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), subject_id.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), subject_action.into_datum()),
+                ]),
+            )?
+            .first()
+            .get_one::<bool>()?;
+
+        Ok(result.unwrap_or_default())
+    }
+
+    /// Whether the current role is a member of pgfga_admin, which superusers
+    /// always are.
+    fn is_admin(&self) -> Result<bool, PgFgaError> {
+        let result = self
+            .client
+            .select("SELECT pg_has_role('pgfga_admin', 'MEMBER')", None, None)?
+            .first()
+            .get_one::<bool>()?;
+
+        Ok(result.unwrap_or_default())
+    }
+
+    /// A query for the `(resource_id, subject_id)` pairs of the source, as
+    /// text. The table of a table source is resolved through `regclass` and
+    /// its columns are quoted, so that they can't be anything but a table and
+    /// its columns. Query sources are used as written, as only members of
+    /// pgfga_admin can write them.
+    pub fn source_sql(&self, source: &RelationSource) -> Result<String, PgFgaError> {
+        let query = match &source.from {
+            SourceFrom::Table {
+                table,
+                resource_id,
+                subject_id,
+            } => {
+                let table = self
+                    .client
+                    .select(
+                        "SELECT $1::regclass::text",
+                        None,
+                        Some(vec![(PgBuiltInOids::TEXTOID.oid(), table.into_datum())]),
+                    )?
+                    .first()
+                    .get_one::<String>()?
+                    .unwrap_or_default();
+
+                format!(
+                    "SELECT {}, {} FROM {table}",
+                    quote_identifier(resource_id),
+                    quote_identifier(subject_id)
+                )
+            }
+            SourceFrom::Query { query } => query.clone(),
+        };
+
+        Ok(format!(
+            "SELECT source.resource_id::text, source.subject_id::text FROM ({query}) AS source (resource_id, subject_id)"
+        ))
+    }
+
+    /// Whether the source has a row from the resource to the subject.
+    pub fn in_source(
+        &self,
+        source: &RelationSource,
+        resource_id: &str,
+        subject_id: &str,
+    ) -> Result<bool, PgFgaError> {
+        let query = format!(
+            "SELECT EXISTS (SELECT 1 FROM ({}) AS source WHERE resource_id = $1 AND subject_id = $2)",
+            self.source_sql(source)?
+        );
+
+        let result = self
+            .client
+            .select(
+                &query,
+                None,
+                Some(vec![
+                    (PgBuiltInOids::TEXTOID.oid(), resource_id.into_datum()),
+                    (PgBuiltInOids::TEXTOID.oid(), subject_id.into_datum()),
+                ]),
+            )?
+            .first()
+            .get_one::<bool>()?;
+
+        Ok(result.unwrap_or_default())
+    }
+
+    /// The rows of the source from the resource, as tuples of `relation`.
+    pub fn read_source_tuples(
+        &self,
+        schema_id: pgrx::Uuid,
+        resource_namespace: &str,
+        resource_id: &str,
+        relation: &str,
+        source: &RelationSource,
+    ) -> Result<Vec<TupleRow>, PgFgaError> {
+        let query = format!(
+            "SELECT subject_id FROM ({}) AS source WHERE resource_id = $1 AND subject_id IS NOT NULL",
+            self.source_sql(source)?
+        );
+
+        let results = self
+            .client
+            .select(
+                &query,
+                None,
+                Some(vec![(
+                    PgBuiltInOids::TEXTOID.oid(),
+                    resource_id.into_datum(),
+                )]),
+            )?
+            .map(|row| {
+                Ok(TupleRow {
+                    rowid: 0,
+                    schema_id,
+                    resource_namespace: resource_namespace.to_string(),
+                    resource_id: resource_id.to_string(),
+                    relation: relation.to_string(),
+                    subject_namespace: source.subject_namespace.clone(),
+                    subject_id: row["subject_id"].value::<String>()?.expect("no subject_id"),
+                    subject_action: source.subject_action.clone(),
+                    expires_at: None,
+                    condition: String::new(),
+                    condition_context: None,
+                })
+            })
+            .collect::<Result<Vec<_>, spi::Error>>()?;
+
+        Ok(results)
+    }
+}
+
+/// The tuples of a schema as JSON, in the shape of `ExportedTuple`.
+const EXPORT_QUERY: &str = "
+SELECT to_jsonb(t) - 'rowid' - 'schema_id'
+FROM pgfga.tuple t
+WHERE t.schema_id = $1
+ORDER BY t.rowid
+";
+
+fn exported_tuple(row: SpiHeapTupleData<'_>) -> Result<ExportedTuple, PgFgaError> {
+    let tuple = row.get::<pgrx::JsonB>(1)?.expect("no tuple");
+
+    Ok(serde_json::from_value(tuple.0)?)
+}
+
+/// Check that the schema allows a tuple to be written: wildcards may only be
+/// written where the schema allows them, and conditions must be defined by
+/// the schema.
+pub fn validate_write(
+    schema: &Schema,
+    resource_namespace: &str,
+    relation: &str,
+    subject_namespace: &str,
+    subject_id: &str,
+    subject_action: &str,
+    condition: &str,
+) -> Result<(), PgFgaError> {
+    if subject_id == WILDCARD
+        && (!subject_action.is_empty()
+            || !schema.allows_wildcard(resource_namespace, relation, subject_namespace))
+    {
+        return Err(PgFgaError::WildcardNotAllowed(
+            resource_namespace.to_string(),
+            relation.to_string(),
+            subject_namespace.to_string(),
+        ));
+    }
+
+    if !condition.is_empty() && !schema.conditions.contains_key(condition) {
+        return Err(PgFgaError::UnknownCondition(condition.to_string()));
+    }
+
+    Ok(())
 }
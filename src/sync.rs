@@ -0,0 +1,69 @@
+use crate::error::PgFgaError;
+use pgrx::prelude::*;
+use pgrx::spi::{quote_identifier, quote_literal, quote_qualified_identifier};
+use pgrx::PgRelation;
+
+/// A relation whose tuples mirror the rows of an application table, e.g.
+/// `document:<documents.id>#parent@folder:<documents.folder_id>`.
+pub struct SyncedRelation<'a> {
+    pub schema: &'a str,
+    pub resource_namespace: &'a str,
+    pub resource_id_col: &'a str,
+    pub relation: &'a str,
+    pub subject_namespace: &'a str,
+    pub subject_id_col: &'a str,
+    pub subject_action: &'a str,
+}
+
+impl SyncedRelation<'_> {
+    fn trigger_name(&self) -> String {
+        format!("pgfga_sync_{}_{}", self.resource_namespace, self.relation)
+    }
+
+    /// Install the trigger that creates and deletes tuples as rows of the
+    /// table are written. Returns the name of the trigger.
+    pub fn install(&self, table: &PgRelation) -> Result<String, PgFgaError> {
+        let args = [
+            self.schema,
+            self.resource_namespace,
+            self.resource_id_col,
+            self.relation,
+            self.subject_namespace,
+            self.subject_id_col,
+            self.subject_action,
+        ]
+        .map(quote_literal)
+        .join(", ");
+
+        Spi::run(&format!(
+            "CREATE TRIGGER {} AFTER INSERT OR UPDATE OR DELETE ON {} FOR EACH ROW EXECUTE FUNCTION pgfga.sync_tuple({args})",
+            quote_identifier(self.trigger_name()),
+            quote_qualified_identifier(table.namespace(), table.name()),
+        ))?;
+
+        Ok(self.trigger_name())
+    }
+
+    /// Create the tuples of the rows already in the table. Returns the number
+    /// of tuples created.
+    pub fn backfill(&self, table: &PgRelation) -> Result<i64, PgFgaError> {
+        let resource_id = quote_identifier(self.resource_id_col);
+        let subject_id = quote_identifier(self.subject_id_col);
+
+        let num_created = Spi::get_one::<i64>(&format!(
+            "
+            SELECT COALESCE(SUM(pgfga.create_tuple({}::text, {}, {resource_id}::text, {}, {}, {subject_id}::text, {})), 0)::bigint
+            FROM {}
+            WHERE {resource_id} IS NOT NULL AND {subject_id} IS NOT NULL
+            ",
+            quote_literal(self.schema),
+            quote_literal(self.resource_namespace),
+            quote_literal(self.relation),
+            quote_literal(self.subject_namespace),
+            quote_literal(self.subject_action),
+            quote_qualified_identifier(table.namespace(), table.name()),
+        ))?;
+
+        Ok(num_created.unwrap_or_default())
+    }
+}
@@ -0,0 +1,228 @@
+//! SQL types for referring to objects, subjects, and tuples as a single value
+//! rather than as several text arguments. They are named after their SQL
+//! types, e.g. `pgfga.object_ref`, and read and written in the same text
+//! format as Zanzibar, e.g. `document:1#viewer@user:anya`, which is parsed by
+//! `pgfga_core::text`. They are base types, stored as varlenas, rather than
+//! composite types, so their fields aren't visible from SQL.
+#![allow(non_camel_case_types)]
+
+use crate::error::PgFgaError;
+use pgfga_core::text::{ObjectRef, SubjectRef, TupleKey};
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use pgrx::StringInfo;
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+use std::fmt;
+
+pub use pgfga_core::text::ParseError;
+
+/// An object, e.g. `document:1`.
+#[derive(Clone, Debug, Deserialize, PartialEq, PostgresType, Serialize)]
+#[inoutfuncs]
+pub struct object_ref {
+    pub namespace: String,
+    pub id: String,
+}
+
+/// A subject, e.g. `user:anya`, or a subject set, e.g. `group:eng#member`.
+#[derive(Clone, Debug, Deserialize, PartialEq, PostgresType, Serialize)]
+#[inoutfuncs]
+pub struct subject_ref {
+    pub namespace: String,
+    pub id: String,
+    pub action: String,
+}
+
+/// A relation between an object and a subject, e.g.
+/// `document:1#viewer@user:anya`.
+#[derive(Clone, Debug, Deserialize, PartialEq, PostgresType, Serialize)]
+#[inoutfuncs]
+pub struct tuple_key {
+    pub object: object_ref,
+    pub relation: String,
+    pub subject: subject_ref,
+}
+
+impl object_ref {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        ObjectRef::parse(input).map(Self::from)
+    }
+}
+
+impl subject_ref {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        SubjectRef::parse(input).map(Self::from)
+    }
+}
+
+impl tuple_key {
+    /// Parse a tuple of the form `namespace:id#relation@namespace:id#action`,
+    /// where the subject's `#action` is optional.
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        TupleKey::parse(input).map(Self::from)
+    }
+}
+
+impl From<ObjectRef> for object_ref {
+    fn from(object: ObjectRef) -> Self {
+        object_ref {
+            namespace: object.namespace,
+            id: object.id,
+        }
+    }
+}
+
+impl From<SubjectRef> for subject_ref {
+    fn from(subject: SubjectRef) -> Self {
+        subject_ref {
+            namespace: subject.namespace,
+            id: subject.id,
+            action: subject.action,
+        }
+    }
+}
+
+impl From<TupleKey> for tuple_key {
+    fn from(tuple: TupleKey) -> Self {
+        tuple_key {
+            object: tuple.object.into(),
+            relation: tuple.relation,
+            subject: tuple.subject.into(),
+        }
+    }
+}
+
+impl From<&object_ref> for ObjectRef {
+    fn from(object: &object_ref) -> Self {
+        ObjectRef {
+            namespace: object.namespace.clone(),
+            id: object.id.clone(),
+        }
+    }
+}
+
+impl From<&subject_ref> for SubjectRef {
+    fn from(subject: &subject_ref) -> Self {
+        SubjectRef {
+            namespace: subject.namespace.clone(),
+            id: subject.id.clone(),
+            action: subject.action.clone(),
+        }
+    }
+}
+
+impl From<&tuple_key> for TupleKey {
+    fn from(tuple: &tuple_key) -> Self {
+        TupleKey {
+            object: (&tuple.object).into(),
+            relation: tuple.relation.clone(),
+            subject: (&tuple.subject).into(),
+        }
+    }
+}
+
+impl fmt::Display for object_ref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        ObjectRef::from(self).fmt(f)
+    }
+}
+
+impl fmt::Display for subject_ref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        SubjectRef::from(self).fmt(f)
+    }
+}
+
+impl fmt::Display for tuple_key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        TupleKey::from(self).fmt(f)
+    }
+}
+
+/// Parse the text representation of a type, raising an error if it is
+/// invalid.
+fn input<T>(input: &CStr, parse: fn(&str) -> Result<T, ParseError>) -> T {
+    let input = match input.to_str() {
+        Ok(input) => input,
+        Err(err) => {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_INVALID_TEXT_REPRESENTATION,
+                err.to_string(),
+                "pgfga",
+            )
+            .report(PgLogLevel::ERROR);
+            unreachable!()
+        }
+    };
+
+    match parse(input) {
+        Ok(value) => value,
+        Err(err) => PgFgaError::from(err).raise(),
+    }
+}
+
+impl InOutFuncs for object_ref {
+    fn input(input: &CStr) -> Self {
+        self::input(input, object_ref::parse)
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+impl InOutFuncs for subject_ref {
+    fn input(input: &CStr) -> Self {
+        self::input(input, subject_ref::parse)
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+impl InOutFuncs for tuple_key {
+    fn input(input: &CStr) -> Self {
+        self::input(input, tuple_key::parse)
+    }
+
+    fn output(&self, buffer: &mut StringInfo) {
+        buffer.push_str(&self.to_string());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_then_format_works() {
+        for input in [
+            "document:1#viewer@user:anya",
+            "document:1#viewer@group:eng#member",
+        ] {
+            assert_eq!(tuple_key::parse(input).unwrap().to_string(), input);
+        }
+
+        assert_eq!(
+            tuple_key::parse("document:1#viewer@group:eng#member").unwrap(),
+            tuple_key {
+                object: object_ref {
+                    namespace: "document".to_string(),
+                    id: "1".to_string(),
+                },
+                relation: "viewer".to_string(),
+                subject: subject_ref {
+                    namespace: "group".to_string(),
+                    id: "eng".to_string(),
+                    action: "member".to_string(),
+                },
+            }
+        );
+        assert_eq!(
+            subject_ref::parse("group:eng#").unwrap_err().kind,
+            "subject"
+        );
+    }
+}
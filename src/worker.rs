@@ -0,0 +1,96 @@
+use crate::error::PgFgaError;
+use crate::guc;
+use crate::storage::Storage;
+use pgrx::bgworkers::{BackgroundWorker, BackgroundWorkerBuilder, SignalWakeFlags};
+use pgrx::prelude::*;
+use std::time::Duration;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CleanupReport {
+    pub expired_tuples: i64,
+}
+
+/// Run a single cleanup cycle. This is what the background worker does every
+/// `pgfga.cleanup_interval` seconds, and what `pgfga.run_cleanup()` does on
+/// demand.
+pub fn run_cycle(storage: &mut Storage) -> Result<CleanupReport, PgFgaError> {
+    Ok(CleanupReport {
+        expired_tuples: storage.delete_expired_tuples()?,
+    })
+}
+
+/// Register the cleanup worker. Background workers can only be registered
+/// while the postmaster is loading `shared_preload_libraries`, so this is a
+/// no-op when pgfga is loaded any other way.
+pub fn init() {
+    if unsafe { !pg_sys::process_shared_preload_libraries_in_progress } {
+        return;
+    }
+
+    BackgroundWorkerBuilder::new("pgfga cleanup")
+        .set_library("pgfga")
+        .set_function("pgfga_cleanup_worker_main")
+        .enable_spi_access()
+        .set_restart_time(Some(Duration::from_secs(60)))
+        .load();
+}
+
+#[pg_guard]
+#[no_mangle]
+pub extern "C" fn pgfga_cleanup_worker_main(_arg: pg_sys::Datum) {
+    BackgroundWorker::attach_signal_handlers(SignalWakeFlags::SIGHUP | SignalWakeFlags::SIGTERM);
+
+    let database = guc::CLEANUP_DATABASE
+        .get()
+        .map(|db| db.to_string_lossy().into_owned());
+    BackgroundWorker::connect_worker_to_spi(database.as_deref(), None);
+
+    loop {
+        // An interval of 0 disables cleanup, in which case we sleep until a
+        // configuration reload wakes us up.
+        let interval = guc::CLEANUP_INTERVAL.get();
+        let timeout = (interval > 0).then(|| Duration::from_secs(interval as u64));
+
+        if !BackgroundWorker::wait_latch(timeout) {
+            break;
+        }
+
+        // The interval may have changed, so start waiting again.
+        if BackgroundWorker::sighup_received() {
+            continue;
+        }
+
+        if interval == 0 {
+            continue;
+        }
+
+        let result = BackgroundWorker::transaction(|| {
+            Spi::connect(|client| {
+                let installed = client
+                    .select(
+                        "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pgfga')",
+                        None,
+                        None,
+                    )?
+                    .first()
+                    .get_one::<bool>()?
+                    .unwrap_or(false);
+
+                // Nothing to do until the extension is created in this database.
+                if !installed {
+                    return Ok(CleanupReport::default());
+                }
+
+                run_cycle(&mut Storage::new(client))
+            })
+        });
+
+        match result {
+            Ok(report) => debug1!(
+                "pgfga cleanup: deleted {} expired tuples",
+                report.expired_tuples
+            ),
+            Err(err) => warning!("pgfga cleanup failed: {err}"),
+        }
+    }
+}
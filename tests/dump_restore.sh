@@ -0,0 +1,101 @@
+#!/usr/bin/env bash
+#
+# Dump a database with pgfga data in it, restore it into a new database, and
+# check that everything came back. Run with the same Postgres version that
+# `cargo pgrx test` uses, e.g. `tests/dump_restore.sh pg15`.
+
+set -euo pipefail
+
+PG_VERSION=${1:-pg15}
+PORT=$((28800 + ${PG_VERSION#pg}))
+SOURCE=pgfga_dump_source
+TARGET=pgfga_dump_target
+DUMP=$(mktemp)
+trap 'rm -f "$DUMP"' EXIT
+
+PG_CONFIG=$(cargo pgrx info pg-config "$PG_VERSION")
+BIN=$("$PG_CONFIG" --bindir)
+
+cargo pgrx install --pg-config "$PG_CONFIG"
+cargo pgrx start "$PG_VERSION"
+
+psql() {
+    "$BIN/psql" -h localhost -p "$PORT" -v ON_ERROR_STOP=1 -At "$@"
+}
+
+"$BIN/dropdb" -h localhost -p "$PORT" --if-exists "$SOURCE"
+"$BIN/dropdb" -h localhost -p "$PORT" --if-exists "$TARGET"
+"$BIN/createdb" -h localhost -p "$PORT" "$SOURCE"
+
+psql -d "$SOURCE" <<'SQL'
+CREATE EXTENSION pgfga;
+
+SELECT pgfga.create_store('acme');
+
+SELECT pgfga.create_schema('{
+    "namespaces": {
+        "user": {},
+        "group": {"relations": {"member": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}},
+        "document": {"relations": {"viewer": [{"namespace": "user"}, {"namespaceAction": ["group", "member"]}]}}
+    }
+}', (SELECT id FROM pgfga.store), 'docs');
+
+SELECT pgfga.create_tuple_str('docs', 'group:eng#member@user:anya');
+SELECT pgfga.create_tuple_str('docs', 'group:all#member@group:eng#member');
+SELECT pgfga.create_tuple_str('docs', 'document:1#viewer@group:all#member');
+SELECT pgfga.create_tuple_str('docs', 'document:2#viewer@user:bob', '2100-01-01T00:00:00Z');
+SELECT pgfga.enable_group_closure('docs', 'group', 'member');
+
+-- A second version of the schema, and an archived first version.
+SELECT pgfga.create_schema(schema, store_id, name) FROM pgfga.schema WHERE name = 'docs';
+SELECT pgfga.create_tuple((SELECT id FROM pgfga.schema WHERE version = 2), 'document', '3', 'viewer', 'user', 'anya');
+SELECT pgfga.set_current_schema('docs', (SELECT id FROM pgfga.schema WHERE version = 2));
+SELECT pgfga.archive_schema((SELECT id FROM pgfga.schema WHERE version = 1));
+
+SET pgfga.decision_log = on;
+SELECT pgfga.check_str('docs', 'document:3#viewer@user:anya');
+SQL
+
+"$BIN/pg_dump" -h localhost -p "$PORT" -Fc -f "$DUMP" "$SOURCE"
+"$BIN/createdb" -h localhost -p "$PORT" "$TARGET"
+"$BIN/pg_restore" -h localhost -p "$PORT" --disable-triggers --exit-on-error -d "$TARGET" "$DUMP"
+
+STATE="
+SELECT 'store', count(*), max(rowid) FROM pgfga.store
+UNION ALL SELECT 'schema', count(*), max(rowid) FROM pgfga.schema
+UNION ALL SELECT 'current_schema', count(*), NULL FROM pgfga.current_schema
+UNION ALL SELECT 'tuple', count(*), max(rowid) FROM pgfga.tuple
+UNION ALL SELECT 'closure_relation', count(*), NULL FROM pgfga.closure_relation
+UNION ALL SELECT 'group_closure', count(*), NULL FROM pgfga.group_closure
+UNION ALL SELECT 'decision_log', count(*), max(rowid) FROM pgfga.decision_log
+UNION ALL SELECT 'checks', count(*) FILTER (WHERE allowed), NULL FROM (
+    SELECT pgfga.check((SELECT id FROM pgfga.schema WHERE version = 1), 'document', '1', 'viewer', 'user', 'anya')
+    UNION ALL SELECT pgfga.check((SELECT id FROM pgfga.schema WHERE version = 1), 'document', '2', 'viewer', 'user', 'bob')
+    UNION ALL SELECT pgfga.check_str('docs', 'document:3#viewer@user:anya')
+) AS checks (allowed)
+"
+
+EXPECTED=$(psql -d "$SOURCE" -c "$STATE")
+ACTUAL=$(psql -d "$TARGET" -c "$STATE")
+
+if [ "$EXPECTED" != "$ACTUAL" ]; then
+    echo "restored database differs from the dumped one" >&2
+    diff <(echo "$EXPECTED") <(echo "$ACTUAL") >&2
+    exit 1
+fi
+
+# Identity sequences carry on from the restored rowids.
+MAX_ROWID=$(psql -d "$TARGET" -c "SELECT max(rowid) FROM pgfga.tuple")
+ROWID=$(psql -d "$TARGET" -c "
+    INSERT INTO pgfga.tuple (schema_id, resource_namespace, resource_id, relation, subject_namespace, subject_id)
+    VALUES ((SELECT schema_id FROM pgfga.current_schema), 'document', '4', 'viewer', 'user', 'bob')
+    RETURNING rowid
+")
+
+if [ "$ROWID" -le "$MAX_ROWID" ]; then
+    echo "new tuple got rowid $ROWID, but $MAX_ROWID was restored" >&2
+    exit 1
+fi
+
+echo "$EXPECTED"
+echo "dump and restore ok"